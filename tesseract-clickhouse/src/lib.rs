@@ -4,7 +4,9 @@ use failure::{Error, format_err};
 use futures::{future, Future, Stream};
 use log::*;
 use std::time::{Duration, Instant};
-use tesseract_core::{Backend, DataFrame, QueryIr};
+use tesseract_core::{Backend, DataFrame, IdentifierStyle, LiteralStyle, QueryIr};
+use tesseract_core::query_ir::{quote_identifier, text_literal};
+use tesseract_core::schema::{DateLevel, DatePart, Table};
 
 mod df;
 mod sql;
@@ -79,7 +81,40 @@ impl Backend for Clickhouse {
         Box::new((*self).clone())
     }
 
-    fn generate_sql(&self, query_ir: QueryIr) -> String {
+    fn identifier_style(&self) -> IdentifierStyle {
+        IdentifierStyle::Backtick
+    }
+
+    fn literal_style(&self) -> LiteralStyle {
+        LiteralStyle::Backslash
+    }
+
+    fn date_level_sql(&self, date_level: &DateLevel) -> String {
+        let col = &quote_identifier(&date_level.date_column, self.identifier_style());
+
+        match date_level.part {
+            DatePart::Year => format!("toYear({})", col),
+            DatePart::Quarter => format!("toYear({col}) * 10 + toQuarter({col})", col=col),
+            DatePart::Month => format!("toYYYYMM({})", col),
+            DatePart::Day => format!("toYYYYMMDD({})", col),
+        }
+    }
+
+    fn table_columns_sql(&self, table: &Table) -> Option<String> {
+        let database = match table.schema {
+            Some(ref schema) => text_literal(schema, self.literal_style()),
+            None => "currentDatabase()".to_owned(),
+        };
+
+        Some(format!("select name, type from system.columns where database = {} and table = {}",
+            database,
+            text_literal(&table.name, self.literal_style()),
+        ))
+    }
+
+    fn generate_sql(&self, mut query_ir: QueryIr) -> String {
+        query_ir.quote_identifiers(self.identifier_style());
+
         clickhouse_sql(
             &query_ir
         )
@@ -15,6 +15,7 @@ use tesseract_core::query_ir::{
     RcaSql,
     GrowthSql,
     RateSql,
+    LiteralStyle,
     dim_subquery,
 };
 use tesseract_core::{QueryIr};
@@ -68,7 +69,7 @@ mod test {
     use super::*;
     use tesseract_core::Table;
     use tesseract_core::names::Mask;
-    use tesseract_core::query_ir::{LevelColumn, MemberType};
+    use tesseract_core::query_ir::{IdentifierStyle, LevelColumn, MemberType, PropertyColumn};
 
     // TODO move this to better place?
     // Should all of these internal checks be moved to one place? Is this an ok place?
@@ -77,25 +78,31 @@ mod test {
         let cuts = vec![
             CutSql {
                 foreign_key: "".into(),
+                foreign_key_expression: false,
                 primary_key: "".into(),
                 inline_table: None,
                 table: Table { name: "".into(), schema: None, primary_key: None },
                 column: "geo".into(),
+                expression: false,
                 members: vec!["1".into(), "2".into()],
                 member_type: MemberType::Text,
                 mask: Mask::Include,
                 for_match: false,
+                bound: false,
             },
             CutSql {
                 foreign_key: "".into(),
+                foreign_key_expression: false,
                 primary_key: "".into(),
                 inline_table: None,
                 table: Table { name: "".into(), schema: None, primary_key: None },
                 column: "age".into(),
+                expression: false,
                 members: vec!["3".into()],
                 member_type: MemberType::NonText,
                 mask: Mask::Include,
                 for_match: false,
+                bound: false,
             },
         ];
 
@@ -109,6 +116,45 @@ mod test {
         );
     }
 
+    #[test]
+    fn cutsql_escaped_members() {
+        let mut cut = CutSql {
+            foreign_key: "".into(),
+            foreign_key_expression: false,
+            primary_key: "".into(),
+            inline_table: None,
+            table: Table { name: "".into(), schema: None, primary_key: None },
+            column: "name".into(),
+            expression: false,
+            members: vec!["O'Brien".into(), "a\\' or 1=1 --".into()],
+            member_type: MemberType::Text,
+            mask: Mask::Include,
+            for_match: false,
+            bound: false,
+        };
+
+        assert_eq!(
+            cuts::cut_sql_string(&cut),
+            "name in ('O\\'Brien', 'a\\\\\\' or 1=1 --')",
+        );
+
+        // non-text members which aren't numbers are quoted
+        cut.members = vec!["1".into(), "1) or (1=1".into()];
+        cut.member_type = MemberType::NonText;
+        assert_eq!(
+            cuts::cut_sql_string(&cut),
+            "name in (1, '1) or (1=1')",
+        );
+
+        cut.members = vec!["O'Brien".into()];
+        cut.member_type = MemberType::Text;
+        cut.for_match = true;
+        assert_eq!(
+            cuts::cut_sql_string(&cut),
+            "(name like '%O\\'Brien%')",
+        );
+    }
+
     // TODO move this to better place?
     // Should all of these internal checks be moved to one place? Is this an ok place?
     #[test]
@@ -116,6 +162,7 @@ mod test {
         let drill = DrilldownSql {
             alias_postfix: "".into(),
             foreign_key: "product_id".into(),
+            foreign_key_expression: false,
             primary_key: "product_id".into(),
             inline_table: None,
             table: Table { name: "dim_products".into(), schema: None, primary_key: None },
@@ -123,13 +170,25 @@ mod test {
                 LevelColumn {
                     key_column: "product_group_id".into(),
                     name_column: Some("product_group_label".into()),
+                    order_column: None,
+                    key_expression: false,
+                    name_expression: false,
+                    order_expression: false,
                 },
                 LevelColumn {
                     key_column: "product_id_raw".into(),
                     name_column: Some("product_label".into()),
+                    order_column: None,
+                    key_expression: false,
+                    name_expression: false,
+                    order_expression: false,
                 },
             ],
-            property_columns: vec!["hexcode".to_owned(), "form".to_owned()],
+            property_columns: vec![
+                PropertyColumn { column: "hexcode".to_owned(), expression: false },
+                PropertyColumn { column: "form".to_owned(), expression: false },
+            ],
+            calculated_members: vec![],
         };
 
         assert_eq!(
@@ -138,4 +197,95 @@ mod test {
         );
     }
 
+    #[test]
+    fn drilldown_quoted_identifiers() {
+        let mut drill = DrilldownSql {
+            alias_postfix: "Product".into(),
+            foreign_key: "Product ID".into(),
+            foreign_key_expression: false,
+            primary_key: "id".into(),
+            inline_table: None,
+            table: Table { name: "dim-products".into(), schema: Some("Sales".into()), primary_key: None },
+            level_columns: vec![
+                LevelColumn {
+                    key_column: "Product ID".into(),
+                    name_column: Some("label".into()),
+                    order_column: None,
+                    key_expression: false,
+                    name_expression: false,
+                    order_expression: false,
+                },
+            ],
+            property_columns: vec![PropertyColumn { column: "Group".to_owned(), expression: false }],
+            calculated_members: vec![],
+        };
+
+        drill.quote_identifiers(IdentifierStyle::Backtick);
+
+        assert_eq!(drill.table.full_name(), "`Sales`.`dim-products`");
+        assert_eq!(
+            drill.col_alias_string(),
+            "`Product ID` as Product_ID_da52af89_Product, label as label_Product, `Group`",
+        );
+        assert_eq!(
+            drill.col_alias_only_string(),
+            "Product_ID_da52af89_Product, label_Product, `Group`",
+        );
+        assert_eq!(
+            dim_subquery(Some(&drill), None, LiteralStyle::Backslash).sql,
+            "select `Product ID` as Product_ID_da52af89_Product, label as label_Product, `Group`, id as `Product ID` from `Sales`.`dim-products`",
+        );
+    }
+
+    #[test]
+    fn drilldown_order_column() {
+        let drill = DrilldownSql {
+            alias_postfix: "Month".into(),
+            foreign_key: "month_id".into(),
+            foreign_key_expression: false,
+            primary_key: "month_id".into(),
+            inline_table: None,
+            table: Table { name: "dim_month".into(), schema: None, primary_key: None },
+            level_columns: vec![
+                LevelColumn {
+                    key_column: "month_id".into(),
+                    name_column: Some("month_name".into()),
+                    order_column: Some("month_num".into()),
+                    key_expression: false,
+                    name_expression: false,
+                    order_expression: false,
+                },
+            ],
+            property_columns: vec![],
+            calculated_members: vec![],
+        };
+
+        // the order column is projected from the dim table, but isn't a dim col
+        let dim = dim_subquery(Some(&drill), None, LiteralStyle::Backslash);
+        assert_eq!(
+            dim.sql,
+            "select month_id as month_id_Month, month_name as month_name_Month, month_num as month_num_Month_order, month_id as month_id from dim_month",
+        );
+        assert_eq!(dim.dim_cols, Some("month_id_Month, month_name_Month".to_owned()));
+        assert_eq!(dim.order_cols, vec!["month_num_Month_order".to_owned()]);
+
+        let table = TableSql { name: "sales".into(), primary_key: None };
+        let meas = vec![MeasureSql { aggregator: tesseract_core::Aggregator::Sum, column: "quantity".into(), expression: false }];
+        let (sql, final_drill_cols) = primary_agg(&table, &[], &[drill.clone()], &meas, None);
+
+        assert_eq!(final_drill_cols, "month_id_Month, month_name_Month");
+        assert_eq!(
+            sql,
+            "SELECT month_id_Month, month_name_Month, sum(m0) as final_m0, min(month_num_Month_order) as month_num_Month_order_min \
+            FROM (SELECT month_id, month_id_Month, month_name_Month, month_num_Month_order, m0 \
+            FROM (select month_id as month_id_Month, month_name as month_name_Month, month_num as month_num_Month_order, month_id as month_id from dim_month) ALIAS0 \
+            ALL INNER JOIN (SELECT month_id, sum(quantity) as m0 FROM sales GROUP BY month_id) ALIAS1 USING month_id) \
+            GROUP BY month_id_Month, month_name_Month",
+        );
+        assert_eq!(
+            drill.sort_alias_vec(),
+            vec!["month_num_Month_order_min".to_owned(), "month_id_Month".to_owned(), "month_name_Month".to_owned()],
+        );
+    }
+
 }
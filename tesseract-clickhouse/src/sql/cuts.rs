@@ -1,10 +1,11 @@
 use super::CutSql;
+use tesseract_core::query_ir::LiteralStyle;
 
 pub fn cut_sql_string(cut: &CutSql) -> String {
     if cut.for_match {
-        format!("{}", cut.members_like_string())
+        format!("{}", cut.members_like_string(LiteralStyle::Backslash))
     } else {
         // col not in ('', '',...)
-        format!("{} {} ({})", cut.column, cut.mask_sql_in_string(), cut.members_string())
+        format!("{} {} ({})", cut.column, cut.mask_sql_in_string(), cut.members_sql(LiteralStyle::Backslash))
     }
 }
@@ -45,12 +45,20 @@ pub fn wrap_options(
         }
     };
 
+    let has_order_cols = has_order_cols(query_ir);
+
+    let order_drill_cols = if has_order_cols {
+        join(query_ir.drills.iter().flat_map(|d| d.sort_alias_vec()), ", ")
+    } else {
+        final_drill_cols.to_owned()
+    };
+
     let sort_sql = {
         if let Some(sort) = sort {
             format!("order by {} {}, {}",
                 sort.column,
                 sort.direction.sql_string(),
-                final_drill_cols,
+                order_drill_cols,
             )
         } else if let Some(top) = top {
             format!("order by {} asc, {}",
@@ -61,7 +69,7 @@ pub fn wrap_options(
             // default uses just final drill cols
             // asc default for all cols
             format!("order by {}",
-                final_drill_cols,
+                order_drill_cols,
             )
         }
     };
@@ -94,7 +102,16 @@ pub fn wrap_options(
     }
 
 
-    final_sql = format!("select * from ({}) {} {} {}",
+    // the order cols are sorted on, but not returned
+    let final_cols = if has_order_cols {
+        let mea_cols = (0..num_measures).map(|i| format!("final_m{}", i));
+        join(Some(final_drill_cols.to_owned()).into_iter().chain(mea_cols), ", ")
+    } else {
+        "*".to_owned()
+    };
+
+    final_sql = format!("select {} from ({}) {} {} {}",
+        final_cols,
         final_sql,
         filters_sql,
         sort_sql,
@@ -103,3 +120,12 @@ pub fn wrap_options(
 
     final_sql
 }
+
+/// Whether the final sql has the min order cols of the drilldowns to sort on.
+///
+/// They're added by `primary_agg`. Calculations select their own cols, so
+/// their results are sorted on the drill cols.
+fn has_order_cols(query_ir: &QueryIr) -> bool {
+    query_ir.rca.is_none() && query_ir.rate.is_none() && query_ir.growth.is_none() &&
+        query_ir.drills.iter().any(|d| !d.order_alias_only_vec().is_empty())
+}
@@ -12,6 +12,7 @@ use super::{
     DrilldownSql,
     MeasureSql,
     HiddenDrilldownSql,
+    LiteralStyle,
     dim_subquery,
 };
 
@@ -44,8 +45,10 @@ pub fn primary_agg(
 
     // Loop through all drilldowns. Collect any drilldown that either references an
     // inline table OR a table that is not the fact table
+    // Drilldowns with calculated members also need a subquery, even on the fact table,
+    // because the calculated members are added as extra rows to the dim table.
     let mut ext_drills: Vec<_> = drills.iter()
-        .filter(|d| d.inline_table.is_some() || (d.table.name != table.name))
+        .filter(|d| d.inline_table.is_some() || (d.table.name != table.name) || !d.calculated_members.is_empty())
         .collect();
 
     let ext_cuts: Vec<_> = cuts.iter()
@@ -55,7 +58,7 @@ pub fn primary_agg(
 
     // An inline drilldown is one that only relies on the fact table
     let inline_drills: Vec<_> = drills.iter()
-        .filter(|d| d.table.name == table.name && d.inline_table.is_none())
+        .filter(|d| d.table.name == table.name && d.inline_table.is_none() && d.calculated_members.is_empty())
         .collect();
 
     let inline_cuts: Vec<_> = cuts.iter()
@@ -67,7 +70,7 @@ pub fn primary_agg(
     // For each of the external drilldowns, we will need to add a subquery
     while let Some(drill) = ext_drills.pop() {
         dim_subqueries.push(
-            dim_subquery(Some(drill), None)
+            dim_subquery(Some(drill), None, LiteralStyle::Backslash)
         );
     }
 
@@ -111,8 +114,22 @@ pub fn primary_agg(
     let hidden_drills = hidden_drills.map(|ds| ds.to_vec()).unwrap_or(vec![]);
     let hidden_dim_cols = join(hidden_drills.iter().map(|d| d.drilldown_sql.col_alias_string()), ", ");
 
+    // Order columns of inline drills are aggregated in the fact table query,
+    // because it's grouped on the level columns.
+    let inline_order_cols: Vec<_> = inline_drills.iter()
+        .flat_map(|d| {
+            d.level_columns.iter()
+                .filter_map(|l| l.order_column.as_ref())
+                .map(move |o| format!("min({}) as {}", o, d.order_alias(o)))
+        })
+        .collect();
+
     let mut fact_sql = format!("SELECT {}", all_fact_dim_cols);
 
+    if !inline_order_cols.is_empty() {
+        fact_sql.push_str(&format!(", {}", join(&inline_order_cols, ", ")));
+    }
+
     // done separately so that it isn't projected up the subqueries
     if !hidden_drills.is_empty() {
         fact_sql.push_str(&format!(", {}", hidden_dim_cols));
@@ -174,6 +191,11 @@ pub fn primary_agg(
     // initialize current dim cols with inline drills and idx cols (all dim cols)
     let mut current_dim_cols = vec![all_fact_dim_aliass];
 
+    // order cols are carried separately, because they're not grouped on
+    let mut current_order_cols: Vec<String> = inline_drills.iter()
+        .flat_map(|d| d.order_alias_only_vec())
+        .collect();
+
     // Create sql string for the measures that are carried up from the
     // fact table query
     let select_mea_cols = meas
@@ -193,9 +215,10 @@ pub fn primary_agg(
         if let Some(cols) = dim_subquery.dim_cols {
             current_dim_cols.push(cols);
         }
+        current_order_cols.extend(dim_subquery.order_cols);
 
         let sub_queries_dim_cols = if !current_dim_cols.is_empty() {
-            format!("{}, ", join(current_dim_cols.iter(), ", "))
+            format!("{}, ", join(current_dim_cols.iter().chain(current_order_cols.iter()), ", "))
         } else {
             "".to_owned()
         };
@@ -222,10 +245,17 @@ pub fn primary_agg(
         });
     let final_mea_cols = join(final_mea_cols, ", ");
 
+    // Each member is sorted by its min order. These cols are only used for
+    // the final sort, see `wrap_options`.
+    let final_order_cols = drills.iter().flat_map(|drill| drill.min_order_alias_vec());
+    let final_order_cols = join(final_order_cols, ", ");
+
     // This is the final result of the groupings.
-    let final_sql = format!("SELECT {}, {} FROM ({}) GROUP BY {}",
+    let final_sql = format!("SELECT {}, {}{}{} FROM ({}) GROUP BY {}",
         final_drill_cols,
         final_mea_cols,
+        if final_order_cols.is_empty() { "" } else { ", " },
+        final_order_cols,
         sub_queries,
         final_drill_cols,
     );
@@ -11,6 +11,7 @@ use super::{
 use crate::sql::primary_agg::primary_agg;
 
 use tesseract_core::{Aggregator};
+use tesseract_core::query_ir::col_alias;
 
 
 pub fn rate_calculation(
@@ -58,7 +59,7 @@ pub fn rate_calculation(
 
     let rate_drill_cols = rate.drilldown_sql.col_alias_only_vec();
     for rate_drill_col in &rate_drill_cols {
-        rate_sql = format!("{}, groupArray({}) as {}_group", rate_sql, rate_drill_col, rate_drill_col);
+        rate_sql = format!("{}, groupArray({}) as {}", rate_sql, rate_drill_col, col_alias(rate_drill_col, "group"));
     }
 
     rate_sql = format!("{} from ({}) group by {}", rate_sql, final_sql, original_drill_cols);
@@ -67,7 +68,7 @@ pub fn rate_calculation(
     let mut rate_sql_unpivot = format!("select {}, ", original_drill_cols);
 
     for rate_drill_col in &rate_drill_cols {
-        rate_sql_unpivot = format!("{}{}_group, ", rate_sql_unpivot, rate_drill_col);
+        rate_sql_unpivot = format!("{}{}, ", rate_sql_unpivot, col_alias(rate_drill_col, "group"));
     }
 
     rate_sql = format!("{}final_m0_agg as final_m0, final_m0_rate from ({}) array join",
@@ -75,17 +76,18 @@ pub fn rate_calculation(
     );
 
     for rate_drill_col in &rate_drill_cols {
-        rate_sql = format!("{} {}_group as {}_group,", rate_sql, rate_drill_col, rate_drill_col);
+        let group_alias = col_alias(rate_drill_col, "group");
+        rate_sql = format!("{} {} as {},", rate_sql, group_alias, group_alias);
     }
 
     rate_sql = format!("{} final_m0_rate as final_m0_rate", rate_sql);
 
     // Final aggregation
-    rate_sql = format!("select {}, final_m0, {}(final_m0_rate) / avg(final_m0) from ({}) where {}_group in ({}) group by {}, final_m0",
+    rate_sql = format!("select {}, final_m0, {}(final_m0_rate) / avg(final_m0) from ({}) where {} in ({}) group by {}, final_m0",
         original_drill_cols,
         rate_aggregator,
         rate_sql,
-        rate_drill_cols[0],
+        col_alias(&rate_drill_cols[0], "group"),
         join(rate.members.clone(), ", "),
         original_drill_cols
     );
@@ -38,6 +38,8 @@
 
 use itertools::join;
 
+use tesseract_core::query_ir::col_alias;
+
 use crate::sql::primary_agg::primary_agg;
 use super::{
     TableSql,
@@ -150,9 +152,9 @@ pub fn calculate(
             let alias_postfix = &d.alias_postfix;
             d.level_columns.iter().map(move |l| {
                 if let Some(ref name_col) = l.name_column {
-                    format!("groupArray({key_col}_{alias_postfix}) as {key_col}_{alias_postfix}_s, groupArray({name_col}_{alias_postfix}) as {name_col}_{alias_postfix}_s", key_col=l.key_column, name_col=name_col, alias_postfix=alias_postfix)
+                    format!("groupArray({key_alias}) as {key_alias}_s, groupArray({name_alias}) as {name_alias}_s", key_alias=col_alias(&l.key_column, alias_postfix), name_alias=col_alias(name_col, alias_postfix))
                 } else {
-                    format!("groupArray({col}_{alias_postfix}) as {col}_{alias_postfix}_s", col=l.key_column, alias_postfix=alias_postfix)
+                    format!("groupArray({key_alias}) as {key_alias}_s", key_alias=col_alias(&l.key_column, alias_postfix))
                 }
             })
         });
@@ -163,9 +165,9 @@ pub fn calculate(
             let alias_postfix = &d.alias_postfix;
             d.level_columns.iter().map(move |l| {
                 if let Some(ref name_col) = l.name_column {
-                    format!("{key_col}_{alias_postfix}_s as {key_col}_{alias_postfix}, {name_col}_{alias_postfix}_s as {name_col}_{alias_postfix}", key_col=l.key_column, name_col=name_col, alias_postfix=alias_postfix)
+                    format!("{key_alias}_s as {key_alias}, {name_alias}_s as {name_alias}", key_alias=col_alias(&l.key_column, alias_postfix), name_alias=col_alias(name_col, alias_postfix))
                 } else {
-                    format!("{col}_{alias_postfix}_s as {col}_{alias_postfix}", col=l.key_column, alias_postfix=alias_postfix)
+                    format!("{key_alias}_s as {key_alias}", key_alias=col_alias(&l.key_column, alias_postfix))
                 }
             })
         });
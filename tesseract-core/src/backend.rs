@@ -1,8 +1,9 @@
-use failure::Error;
-use futures::{Future, Stream};
+use failure::{Error, format_err};
+use futures::{future, Future, Stream};
 
 use crate::dataframe::DataFrame;
-use crate::query_ir::QueryIr;
+use crate::query_ir::{BoundSql, IdentifierStyle, LiteralStyle, QueryIr};
+use crate::schema::{DateLevel, Table};
 use crate::sql;
 
 
@@ -18,13 +19,26 @@ pub trait Backend {
         unimplemented!()
     }
 
+    /// Takes in SQL with bound parameters. Backends which bind parameters
+    /// (see `generate_bound_sql`) must override this; the default only runs
+    /// SQL without parameters.
+    fn exec_bound_sql(&self, bound_sql: BoundSql) -> Box<dyn Future<Item=DataFrame, Error=Error>> {
+        if !bound_sql.params.is_empty() {
+            return Box::new(future::err(format_err!("Backend does not support bound parameters")));
+        }
+
+        self.exec_sql(bound_sql.sql)
+    }
+
     fn box_clone(&self) -> Box<dyn Backend + Send + Sync>;
 
     /// Receives an intermediate representation of the Query
     /// (the table, col, and relationship info needed for each drill,
     /// mea, cut, etc.) and generates a `String` of sql. Cannot error,
     /// and all checks should be done before calling this.
-    fn generate_sql(&self, query_ir: QueryIr) -> String {
+    fn generate_sql(&self, mut query_ir: QueryIr) -> String {
+        query_ir.quote_identifiers(self.identifier_style());
+
         // standard sql implementation
         sql::standard_sql(
             &query_ir.table,
@@ -36,8 +50,52 @@ pub trait Backend {
             &query_ir.limit,
             &query_ir.rca,
             &query_ir.growth,
+            self.literal_style(),
         )
     }
+
+    /// Like `generate_sql`, but with cut members passed as bound parameters
+    /// instead of being written into the sql, for `exec_bound_sql`. The
+    /// default has no parameters; backends whose drivers bind parameters
+    /// should override, using `QueryIr::bind_cut_members`.
+    fn generate_bound_sql(&self, query_ir: QueryIr) -> BoundSql {
+        BoundSql::unbound(self.generate_sql(query_ir))
+    }
+
+    /// How table and column names are quoted. Defaults to standard sql double
+    /// quotes.
+    fn identifier_style(&self) -> IdentifierStyle {
+        IdentifierStyle::DoubleQuote
+    }
+
+    /// How quotes are escaped in string literals. Defaults to standard sql,
+    /// where quotes are doubled.
+    fn literal_style(&self) -> LiteralStyle {
+        LiteralStyle::Standard
+    }
+
+    /// Sql expression for the members of a level generated from a date column.
+    /// Defaults to standard sql; backends with their own date functions
+    /// should override.
+    fn date_level_sql(&self, date_level: &DateLevel) -> String {
+        date_level.standard_sql(self.identifier_style())
+    }
+
+    /// Sql listing the columns of a table in the database catalog: the
+    /// column names as the first column of the result, and their types as
+    /// the second. Used to check a schema against the database and to
+    /// scaffold schemas; `None` if the backend doesn't support it.
+    fn table_columns_sql(&self, _table: &Table) -> Option<String> {
+        None
+    }
+
+    /// Sql listing the foreign keys of a table in the database catalog, as
+    /// rows of column, referenced table schema, referenced table and
+    /// referenced column. `None` if the database doesn't have foreign key
+    /// constraints.
+    fn foreign_keys_sql(&self, _table: &Table) -> Option<String> {
+        None
+    }
 }
 
 impl Clone for Box<dyn Backend + Send + Sync> {
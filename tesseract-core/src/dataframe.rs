@@ -49,6 +49,13 @@ impl DataFrame {
             0
         }
     }
+
+    /// Keeps only the rows for which `keep` is true, in all columns.
+    pub fn retain_rows(&mut self, keep: &[bool]) {
+        for column in self.columns.iter_mut() {
+            column.retain_rows(keep);
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -69,6 +76,34 @@ impl Column {
         &mut self.column_data
     }
 
+    /// Keeps only the entries for which `keep` is true.
+    pub fn retain_rows(&mut self, keep: &[bool]) {
+        match self.column_data {
+            ColumnData::Int8(ref mut v) => retain(v, keep),
+            ColumnData::Int16(ref mut v) => retain(v, keep),
+            ColumnData::Int32(ref mut v) => retain(v, keep),
+            ColumnData::Int64(ref mut v) => retain(v, keep),
+            ColumnData::UInt8(ref mut v) => retain(v, keep),
+            ColumnData::UInt16(ref mut v) => retain(v, keep),
+            ColumnData::UInt32(ref mut v) => retain(v, keep),
+            ColumnData::UInt64(ref mut v) => retain(v, keep),
+            ColumnData::Float32(ref mut v) => retain(v, keep),
+            ColumnData::Float64(ref mut v) => retain(v, keep),
+            ColumnData::Text(ref mut v) => retain(v, keep),
+            ColumnData::NullableInt8(ref mut v) => retain(v, keep),
+            ColumnData::NullableInt16(ref mut v) => retain(v, keep),
+            ColumnData::NullableInt32(ref mut v) => retain(v, keep),
+            ColumnData::NullableInt64(ref mut v) => retain(v, keep),
+            ColumnData::NullableUInt8(ref mut v) => retain(v, keep),
+            ColumnData::NullableUInt16(ref mut v) => retain(v, keep),
+            ColumnData::NullableUInt32(ref mut v) => retain(v, keep),
+            ColumnData::NullableUInt64(ref mut v) => retain(v, keep),
+            ColumnData::NullableFloat32(ref mut v) => retain(v, keep),
+            ColumnData::NullableFloat64(ref mut v) => retain(v, keep),
+            ColumnData::NullableText(ref mut v) => retain(v, keep),
+        }
+    }
+
     /// Sort column entries for all types, but floats.
     pub fn sort_column_data(&mut self) -> Result<(), Error> {
         match self.column_data {
@@ -214,6 +249,16 @@ impl Column {
     }
 }
 
+/// Rows past the end of `keep` are dropped.
+fn retain<T>(values: &mut Vec<T>, keep: &[bool]) {
+    let mut idx = 0;
+    values.retain(|_| {
+        let keep_row = keep.get(idx).cloned().unwrap_or(false);
+        idx += 1;
+        keep_row
+    });
+}
+
 #[derive(Debug, PartialEq)]
 pub enum ColumnData {
     Int8(Vec<i8>),
@@ -9,12 +9,11 @@ pub mod query;
 pub mod query_ir;
 
 use failure::{Error, format_err, bail};
+use itertools::join;
 use log::*;
-use serde_xml_rs as serde_xml;
-use serde_xml::from_reader;
 use std::collections::{HashSet, HashMap};
 use std::str::FromStr;
-use crate::schema::{SchemaConfigJson, SchemaConfigXML};
+use crate::schema::SchemaConfigJson;
 
 pub use self::backend::Backend;
 pub use self::dataframe::{DataFrame, Column, ColumnData, is_same_columndata_type};
@@ -39,6 +38,8 @@ use self::query_ir::{
     MemberType,
     TableSql,
     LevelColumn,
+    PropertyColumn,
+    CalculatedMemberSql,
     TopSql,
     TopWhereSql,
     SortSql,
@@ -46,9 +47,10 @@ use self::query_ir::{
     GrowthSql,
     RateSql,
     FilterSql,
+    quote_identifier,
 };
 pub use self::query::{Query, MeaOrCalc, FilterQuery};
-pub use self::query_ir::QueryIr;
+pub use self::query_ir::{BoundSql, IdentifierStyle, LiteralStyle, QueryIr, SqlParam};
 macro_rules! mea_or_calc {
     ($m_or_c:expr, $query:expr) => {
         match $m_or_c {
@@ -74,20 +76,14 @@ macro_rules! mea_or_calc {
 impl Schema {
     /// Deserializes JSON schema into a `Schema`.
     pub fn from_json(raw_schema: &str) -> Result<Self, Error> {
-        let schema_config = serde_json::from_str::<SchemaConfigJson>(raw_schema)?;
+        let schema_config = SchemaConfigJson::from_json(raw_schema)?;
         Ok(schema_config.into())
     }
 
     /// Deserializes XML schema into a `Schema`.
     pub fn from_xml(raw_schema: &str) -> Result<Self, Error> {
-        let schema_config: SchemaConfigXML = match from_reader(raw_schema.as_bytes()) {
-            Ok(schema_config_xml) => schema_config_xml,
-            Err(err) => return Err(format_err!("Error reading XML schema: {}", err))
-        };
-
-        // Serialize XML to JSON as intermediary step
-        let serialized = serde_json::to_string(&schema_config)?;
-        Schema::from_json(&serialized)
+        let schema_config = SchemaConfigJson::from_xml(raw_schema)?;
+        Ok(schema_config.into())
     }
 
     /// schema validation
@@ -147,24 +143,279 @@ impl Schema {
             }
         }
 
+        // Calculated members must have distinct keys within a level, and must
+        // be made up of at least one member. They can't be nested.
+        for cube in &self.cubes {
+            for dim in &cube.dimensions {
+                for hier in &dim.hierarchies {
+                    for level in &hier.levels {
+                        if let Some(ref cms) = level.calculated_members {
+                            let level_str = format!("{}.{}.{}.{}", cube.name, dim.name, hier.name, level.name);
+
+                            let set = cms.iter()
+                                .map(|cm| &cm.key)
+                                .collect::<HashSet<_>>();
+
+                            if set.len() != cms.len() {
+                                bail!("Duplicate calculated member keys not allowed in level {}", level_str);
+                            }
+
+                            for cm in cms {
+                                if cm.members.is_empty() {
+                                    bail!("Calculated member {} in level {} must have at least one member", cm.key, level_str);
+                                }
+                                if cm.members.iter().any(|m| set.contains(m)) {
+                                    bail!("Calculated member {} in level {} cannot contain another calculated member", cm.key, level_str);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // Table and column names are quoted in sql when needed, so they can
+        // have spaces, dashes, uppercase letters etc., but not quotes.
+        // Columns declared as expressions are written as is; tables can't
+        // be expressions.
+        for cube in &self.cubes {
+            validate_table(&cube.table, &cube.name)?;
+
+            for measure in &cube.measures {
+                let path = format!("{}.{}", cube.name, measure.name);
+                validate_column(&measure.column, measure.expression, &path)?;
+            }
+
+            for dim in &cube.dimensions {
+                let dim_path = format!("{}.{}", cube.name, dim.name);
+
+                if let Some(ref foreign_key) = dim.foreign_key {
+                    validate_column(foreign_key, dim.foreign_key_expression, &dim_path)?;
+                }
+
+                for hier in &dim.hierarchies {
+                    let hier_path = format!("{}.{}", dim_path, hier.name);
+
+                    if let Some(ref table) = hier.table {
+                        validate_table(table, &hier_path)?;
+                    }
+                    if let Some(ref inline_table) = hier.inline_table {
+                        validate_identifier("inline table alias", &inline_table.alias, &hier_path)?;
+                        for col_def in &inline_table.column_definitions {
+                            validate_identifier("inline table column", &col_def.name, &hier_path)?;
+                        }
+                    }
+                    validate_column(&hier.primary_key, false, &hier_path)?;
+
+                    for level in &hier.levels {
+                        let level_path = format!("{}.{}", hier_path, level.name);
+
+                        // the key column of a date level is generated
+                        match level.date_level {
+                            Some(ref date_level) => validate_column(&date_level.date_column, false, &level_path)?,
+                            None => validate_column(&level.key_column, level.expression, &level_path)?,
+                        }
+                        if let Some(ref name_column) = level.name_column {
+                            validate_column(name_column, level.name_expression, &level_path)?;
+                        }
+                        if let Some(ref order_column) = level.order_column {
+                            validate_column(order_column, level.order_expression, &level_path)?;
+                        }
+                        for property in level.properties.iter().flat_map(|ps| ps.iter()) {
+                            let property_path = format!("{}.{}", level_path, property.name);
+                            validate_column(&property.column, property.expression, &property_path)?;
+                        }
+                    }
+                }
+            }
+        }
+
         Ok(())
     }
 
-    pub fn cube_metadata(&self, cube_name: &str) -> Option<CubeMetadata> {
+    /// Sets the key column of levels generated from a date column to the
+    /// backend's sql expression. Should be called after reading a schema,
+    /// otherwise standard sql is used.
+    pub fn resolve_date_levels(&mut self, backend: &dyn Backend) {
+        for cube in self.cubes.iter_mut() {
+            for dim in cube.dimensions.iter_mut() {
+                for hier in dim.hierarchies.iter_mut() {
+                    for level in hier.levels.iter_mut() {
+                        if let Some(ref date_level) = level.date_level {
+                            level.key_column = backend.date_level_sql(date_level);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Fact and dimension tables used in the schema, without duplicates.
+    /// Inline tables are not included.
+    pub fn catalog_tables(&self) -> Vec<Table> {
+        let mut tables: Vec<Table> = vec![];
+
+        for cube in &self.cubes {
+            let hier_tables = cube.dimensions.iter()
+                .flat_map(|dim| dim.hierarchies.iter())
+                .filter_map(|hier| hier.table.as_ref());
+
+            for table in Some(&cube.table).into_iter().chain(hier_tables) {
+                if !tables.iter().any(|t| t.full_name() == table.full_name()) {
+                    tables.push(table.clone());
+                }
+            }
+        }
+
+        tables
+    }
+
+    /// Checks the tables and columns used in the schema against the
+    /// database catalog, which maps the full name of each table in
+    /// `catalog_tables` to its column names; a table missing from the
+    /// catalog doesn't exist.
+    ///
+    /// Checks fact tables, foreign keys, primary keys, level key, name and
+    /// order columns, property columns and measure columns. Columns declared
+    /// as sql expressions are skipped, and so are hierarchies on inline
+    /// tables. Column names are compared
+    /// case-insensitively, since some databases fold unquoted identifiers.
+    ///
+    /// All problems are reported in one error, one per line.
+    pub fn validate_catalog(&self, catalog: &HashMap<String, Vec<String>>) -> Result<(), Error> {
+        let mut problems = vec![];
+
+        {
+            let mut check_column = |path: &str, table: &Table, column: &str| {
+                match catalog.get(&table.full_name()) {
+                    Some(columns) => {
+                        if !columns.iter().any(|c| c.eq_ignore_ascii_case(column)) {
+                            problems.push(format!("{}: column {} not found in table {}", path, column, table.full_name()));
+                        }
+                    },
+                    // missing tables are reported once, below
+                    None => (),
+                }
+            };
+
+            for cube in &self.cubes {
+                for measure in &cube.measures {
+                    if measure.expression {
+                        continue;
+                    }
+
+                    let path = format!("{}.{}", cube.name, measure.name);
+                    check_column(&path, &cube.table, &measure.column);
+                }
+
+                for dim in &cube.dimensions {
+                    let dim_path = format!("{}.{}", cube.name, dim.name);
+
+                    if let (Some(ref foreign_key), false) = (&dim.foreign_key, dim.foreign_key_expression) {
+                        check_column(&dim_path, &cube.table, foreign_key);
+                    }
+
+                    for hier in &dim.hierarchies {
+                        // columns of inline tables are defined in the schema
+                        if hier.inline_table.is_some() {
+                            continue;
+                        }
+
+                        let hier_path = format!("{}.{}", dim_path, hier.name);
+                        let table = hier.table.as_ref().unwrap_or(&cube.table);
+
+                        // primary key defaults to the last level's key column,
+                        // which is checked with the level
+                        if !hier.levels.iter().any(|l| l.key_column == hier.primary_key) {
+                            check_column(&hier_path, table, &hier.primary_key);
+                        }
+
+                        for level in &hier.levels {
+                            let level_path = format!("{}.{}", hier_path, level.name);
+
+                            if let Some(ref date_level) = level.date_level {
+                                check_column(&level_path, table, &date_level.date_column);
+                            } else if !level.expression {
+                                check_column(&level_path, table, &level.key_column);
+                            }
+                            if let (Some(ref name_column), false) = (&level.name_column, level.name_expression) {
+                                check_column(&level_path, table, name_column);
+                            }
+                            if let (Some(ref order_column), false) = (&level.order_column, level.order_expression) {
+                                check_column(&level_path, table, order_column);
+                            }
+
+                            for property in level.properties.iter().flat_map(|ps| ps.iter()) {
+                                if property.expression {
+                                    continue;
+                                }
+
+                                let property_path = format!("{}.{}", level_path, property.name);
+                                check_column(&property_path, table, &property.column);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        for cube in &self.cubes {
+            if !catalog.contains_key(&cube.table.full_name()) {
+                problems.push(format!("{}: table {} not found", cube.name, cube.table.full_name()));
+            }
+
+            for dim in &cube.dimensions {
+                for hier in &dim.hierarchies {
+                    if let Some(ref table) = hier.table {
+                        if !catalog.contains_key(&table.full_name()) {
+                            problems.push(format!("{}.{}.{}: table {} not found", cube.name, dim.name, hier.name, table.full_name()));
+                        }
+                    }
+                }
+            }
+        }
+
+        if !problems.is_empty() {
+            bail!("Schema does not match the database:\n{}", problems.join("\n"));
+        }
+
+        Ok(())
+    }
+
+    /// Captions are localized to `locale`, falling back to the default locale.
+    /// Elements which need a higher auth level than `user_auth_level` are
+    /// hidden; None means auth is not set on the server.
+    pub fn cube_metadata(&self, cube_name: &str, user_auth_level: Option<i32>, locale: Option<&str>) -> Option<CubeMetadata> {
         // Takes the first cube with the name.
         // TODO we still have to check that the cube names are distinct
         // before this.
-        self.cubes.iter().find(|c| c.name == cube_name).map(|c| c.into())
+        let locales = self.metadata_locales(locale);
+        let mut cube_metadata = self.cubes.iter().find(|c| c.name == cube_name).map(|c| CubeMetadata::from_cube(c, &locales))?;
+        if let Some(val) = user_auth_level {
+            cube_metadata.hide_restricted(val);
+        }
+        Some(cube_metadata)
     }
 
-    pub fn metadata(&self, user_auth_level: Option<i32>) -> SchemaMetadata {
-        let mut schema_metadata: SchemaMetadata = self.into();
+    /// Captions are localized to `locale`, falling back to the default locale.
+    pub fn metadata(&self, user_auth_level: Option<i32>, locale: Option<&str>) -> SchemaMetadata {
+        let locales = self.metadata_locales(locale);
+        let mut schema_metadata = SchemaMetadata::from_schema(self, &locales);
         if let Some(val) = user_auth_level {
             schema_metadata.cubes = schema_metadata.cubes.drain(..).filter(|c| val >= c.min_auth_level && val >= DEFAULT_ALLOWED_ACCESS).collect();
+            for cube in &mut schema_metadata.cubes {
+                cube.hide_restricted(val);
+            }
         }
         schema_metadata
     }
 
+    fn metadata_locales<'a>(&'a self, locale: Option<&'a str>) -> Vec<&'a str> {
+        let mut locales: Vec<&str> = locale.into_iter().collect();
+        locales.push(&self.default_locale);
+        locales
+    }
+
     pub fn has_unique_levels_properties(&self) -> CubeHasUniqueLevelsAndProperties {
         for cube in &self.cubes {
             let mut levels = HashSet::new();
@@ -212,9 +463,11 @@ impl Schema {
         &self,
         cube: &str,
         level_name: &LevelName,
+        style: IdentifierStyle,
+        literal_style: LiteralStyle,
         ) -> Result<(String, Vec<String>), Error> // Sql and then Header
     {
-        let members_query_ir = self.get_dim_col_table(cube, level_name)?;
+        let members_query_ir = self.get_dim_col_table(cube, level_name, style)?;
 
         let header = if members_query_ir.name_column.is_some() {
             vec!["ID".into(), "Label".into()]
@@ -222,19 +475,25 @@ impl Schema {
             vec!["ID".into()]
         };
 
-        let name_col = if let Some(ref col) = members_query_ir.name_column {
-           col.to_owned()
+        let name_columns: Vec<String> = members_query_ir.name_column.iter().cloned().collect();
+
+        let sql = if let Some(ref order_col) = members_query_ir.order_column {
+            ordered_members_sql(
+                &members_query_ir.table_sql,
+                &members_query_ir.key_column,
+                &name_columns,
+                order_col,
+                &members_query_ir.calculated_members,
+                literal_style,
+            )
         } else {
-            "".into()
+            format!("select distinct {} from {}{}",
+                join(Some(&members_query_ir.key_column).into_iter().chain(name_columns.iter()), ", "),
+                members_query_ir.table_sql,
+                calculated_members_union_sql(&members_query_ir.calculated_members, name_columns.len(), literal_style),
+            )
         };
 
-        let sql = format!("select distinct {}{}{} from {}",
-            members_query_ir.key_column,
-            if members_query_ir.name_column.is_some() { ", " } else { "" },
-            name_col,
-            members_query_ir.table_sql,
-        );
-
         Ok((sql, header))
     }
 
@@ -244,7 +503,9 @@ impl Schema {
         &self,
         cube_name: &str,
         level_name: &LevelName,
-        locale: &str
+        locale: &str,
+        style: IdentifierStyle,
+        literal_style: LiteralStyle,
     ) -> Result<(String, Vec<String>), Error> // Sql and then Header
     {
         let locales: Vec<String> = locale.split(",").map(|s| s.to_string()).collect();
@@ -263,9 +524,10 @@ impl Schema {
             .find(|lvl| lvl.name == level_name.level)
             .ok_or(format_err!("could not find level for level name"))?;
 
-        let table = hier.table.clone().unwrap_or_else(|| cube.table.clone());
+        let mut table = hier.table.clone().unwrap_or_else(|| cube.table.clone());
+        query_ir::quote_table(&mut table, style);
 
-        let key_column = level.key_column.clone();
+        let key_column = level.key_column_sql(style);
         let mut header = vec!["ID".into()];
         let mut name_columns: Vec<String> = vec![];
 
@@ -276,13 +538,16 @@ impl Schema {
                     if let Some(caption_set) = &column_def.caption_set {
                         if caption_set == locale {
                             header.push(format!("{} Label", caption_set.to_uppercase()));
-                            name_columns.push(column_def.name.clone());
+                            name_columns.push(quote_identifier(&column_def.name, style));
                             break;
                         }
                     }
                 }
             }
 
+            let mut inline = inline.clone();
+            query_ir::quote_inline_table(&mut inline, style);
+
             format!("({})", inline.sql_string())
         } else {
 
@@ -292,7 +557,7 @@ impl Schema {
                         if let Some(caption_set) = &property.caption_set {
                             if caption_set == locale {
                                 header.push(format!("{} Label", caption_set.to_uppercase()));
-                                name_columns.push(property.column.clone());
+                                name_columns.push(property.column_sql(style));
                                 break;
                             }
                         }
@@ -300,9 +565,9 @@ impl Schema {
                 }
 
                 if locale == &self.default_locale {
-                    if let Some(level_name_col_val) = &level.name_column {
+                    if let Some(name_column) = level.name_column_sql(style) {
                         header.push(format!("{} Label", locale.to_uppercase()));
-                        name_columns.push(level_name_col_val.to_owned());
+                        name_columns.push(name_column);
                     }
                 }
             }
@@ -310,14 +575,39 @@ impl Schema {
             table.full_name()
         };
 
-        let sql = format!("select distinct {}{}{} from {} order by {}",
+        let calculated_members = level_calculated_members(level);
+
+        let cols = format!("{}{}{}",
             key_column,
             if name_columns.len() > 0 { ", " } else { "" },
             name_columns.join(", "),
-            table_sql,
-            key_column
         );
 
+        let sql = if let Some(ref order_col) = level.order_column_sql(style) {
+            ordered_members_sql(
+                &table_sql,
+                &key_column,
+                &name_columns,
+                order_col,
+                &calculated_members,
+                literal_style,
+            )
+        } else if calculated_members.is_empty() {
+            format!("select distinct {} from {} order by {}",
+                cols,
+                table_sql,
+                key_column
+            )
+        } else {
+            // calculated members have the same caption in every locale
+            format!("select * from (select distinct {} from {}{}) as members order by {}",
+                cols,
+                table_sql,
+                calculated_members_union_sql(&calculated_members, name_columns.len(), literal_style),
+                key_column
+            )
+        };
+
         Ok((sql, header))
     }
 
@@ -521,6 +811,7 @@ impl Schema {
             cut_cols.extend_from_slice(&exclude_default_member_cut_cols);
         }
 
+        self.check_drilldowns_calculated_members(schema_cube, query)?;
 
         let drill_cols = self.cube_drill_cols(&cube, &query.drilldowns, &query.properties, &query.captions, query.parents)
             .map_err(|err| format_err!("Error getting drill cols: {}", err))?;
@@ -550,9 +841,33 @@ impl Schema {
         let hidden_drill_cols: Vec<_> = self.cube_drill_cols(&cube, &hidden_dims, &[], &[], false)
             .map_err(|err| format_err!("Error getting hidden grouping drill cols: {}", err))?
             .iter()
-            .map(|dim_col| HiddenDrilldownSql { drilldown_sql: dim_col.clone() })
+            .map(|dim_col| {
+                // hidden drills are only for grouping, not for output rows
+                let mut drilldown_sql = dim_col.clone();
+                drilldown_sql.calculated_members.clear();
+                HiddenDrilldownSql { drilldown_sql }
+            })
             .collect();
 
+        // Calculated members are aggregated from their children, which is only
+        // correct for additive aggregators.
+        let has_calculated_members = drill_cols.iter().any(|d| !d.calculated_members.is_empty()) ||
+            self.query_cuts_calculated_members(schema_cube, &query.cuts);
+
+        if has_calculated_members {
+            let mut all_meas = query.measures.clone();
+            if let Some(ref rca) = query.rca {
+                all_meas.push(rca.mea.clone());
+            }
+            for mea in self.cube_mea_cols(&cube, &all_meas)? {
+                match mea.aggregator {
+                    Aggregator::Sum => (),
+                    Aggregator::Count => (),
+                    _ => bail!("Calculated members can only be used with measures with sum or count aggregations"),
+                }
+            }
+        }
+
         // Options for sorting and limiting
 
         let limit = query.limit.clone().map(|l| l.into());
@@ -697,8 +1012,12 @@ impl Schema {
                 &query.properties, &query.captions, query.parents
             )?;
 
+            // calculated members would be counted twice in the rate's total
+            let mut drilldown_sql = drilldown_sql[0].clone();
+            drilldown_sql.calculated_members.clear();
+
             Some(RateSql {
-                drilldown_sql: drilldown_sql[0].clone(),
+                drilldown_sql,
                 members: rate.values.clone(),
             })
         } else {
@@ -793,6 +1112,50 @@ impl Schema {
     }
 }
 
+/// A table or column name: not empty, and without quotes or control chars.
+fn validate_identifier(kind: &str, name: &str, path: &str) -> Result<(), Error> {
+    if name.trim().is_empty() {
+        bail!("Empty {} in {}", kind, path);
+    }
+    if name.chars().any(|c| c.is_control()) {
+        bail!("Invalid {} {:?} in {}: control characters are not allowed", kind, name, path);
+    }
+    if name.contains('"') || name.contains('`') {
+        bail!("Invalid {} {} in {}: names are quoted when needed, and can't contain quotes", kind, name, path);
+    }
+
+    Ok(())
+}
+
+fn validate_table(table: &Table, path: &str) -> Result<(), Error> {
+    validate_identifier("table name", &table.name, path)?;
+    // would be quoted as one name
+    if table.name.contains('.') {
+        bail!("Invalid table name {} in {}: set the table's schema with the schema attribute", table.name, path);
+    }
+    if let Some(ref schema) = table.schema {
+        validate_identifier("table schema", schema, path)?;
+    }
+
+    Ok(())
+}
+
+/// Columns declared as sql expressions are written as is, so are only
+/// checked for control chars.
+fn validate_column(column: &str, expression: bool, path: &str) -> Result<(), Error> {
+    if !expression {
+        return validate_identifier("column", column, path);
+    }
+    if column.trim().is_empty() {
+        bail!("Empty column expression in {}", path);
+    }
+    if column.chars().any(|c| c.is_control()) {
+        bail!("Invalid column {:?} in {}: control characters are not allowed", column, path);
+    }
+
+    Ok(())
+}
+
 impl Schema {
     fn cube_table(&self, cube_name: &str) -> Option<TableSql> {
         self.cubes.iter()
@@ -805,6 +1168,41 @@ impl Schema {
             })
     }
 
+    /// Checks whether any of the cuts has a calculated member
+    fn query_cuts_calculated_members(&self, cube: &Cube, cuts: &[Cut]) -> bool {
+        cuts.iter()
+            .filter(|cut| !cut.for_match)
+            .any(|cut| {
+                cube.get_level(&cut.level_name)
+                    .map(|level| cut.members.iter().any(|m| level.get_calculated_member(m).is_some()))
+                    .unwrap_or(false)
+            })
+    }
+
+    /// Calculated members are added as rows, which have no parent or property
+    /// values, so they can't be drilled on with parents or properties.
+    fn check_drilldowns_calculated_members(&self, cube: &Cube, query: &Query) -> Result<(), Error> {
+        for drill in &query.drilldowns {
+            let has_calculated_members = cube.get_level(&drill.0)
+                .and_then(|level| level.calculated_members)
+                .map(|cms| !cms.is_empty())
+                .unwrap_or(false);
+
+            if !has_calculated_members {
+                continue;
+            }
+
+            if query.parents {
+                bail!("Drilldown {} has calculated members, which can't be returned with parents", drill.0);
+            }
+            if query.properties.iter().any(|p| p.level_name == drill.0) {
+                bail!("Drilldown {} has calculated members, which can't be returned with properties", drill.0);
+            }
+        }
+
+        Ok(())
+    }
+
     fn cube_cut_cols(&self, cube_name: &str, cuts: &[Cut]) -> Result<Vec<CutSql>, Error> {
         let cube = self.cubes.iter()
             .find(|cube| &cube.name == &cube_name)
@@ -836,10 +1234,9 @@ impl Schema {
                 .clone()
                 .ok_or(format_err!("No foreign key; it's required for now (until inline dim implemented)"))?;
 
-            let column = if cut.for_match {
-                level.name_column.clone().unwrap_or(level.key_column.clone())
-            } else {
-                level.key_column.clone()
+            let (column, expression) = match (cut.for_match, &level.name_column) {
+                (true, Some(name_column)) => (name_column.clone(), level.name_expression),
+                _ => (level.key_column.clone(), level.expression),
             };
 
             let member_type = if cut.for_match {
@@ -848,16 +1245,39 @@ impl Schema {
                 level.key_type.clone().unwrap_or(MemberType::NonText)
             };
 
+            // calculated members get expanded into the members they're
+            // made up of
+            let mut members = vec![];
+            for member in &cut.members {
+                match level.get_calculated_member(member) {
+                    Some(cm) if !cut.for_match => {
+                        for child in &cm.members {
+                            if !members.contains(child) {
+                                members.push(child.clone());
+                            }
+                        }
+                    },
+                    _ => {
+                        if !members.contains(member) {
+                            members.push(member.clone());
+                        }
+                    },
+                }
+            }
+
             res.push(CutSql {
                 table,
                 primary_key,
                 foreign_key,
+                foreign_key_expression: dim.foreign_key_expression,
                 column,
+                expression,
                 member_type,
-                members: cut.members.clone(),
+                members,
                 mask: cut.mask.clone(),
                 for_match: cut.for_match,
                 inline_table: hier.inline_table.clone(),
+                bound: false,
             });
         }
 
@@ -908,7 +1328,7 @@ impl Schema {
                                 None
                             }
                         })
-                        .map(|p| p.column.clone())
+                        .map(|p| PropertyColumn { column: p.column.clone(), expression: p.expression })
                         .ok_or(format_err!("cannot find property for {}", p))
                 })
                 .collect();
@@ -945,7 +1365,7 @@ impl Schema {
                                 None
                             }
                         })
-                        .map(|(lvl, p)| (lvl.name.clone(), p.column.clone()) )
+                        .map(|(lvl, p)| (lvl.name.clone(), (p.column.clone(), p.expression)) )
                         .ok_or(format_err!("cannot find property-caption for {}", p))
                 })
                 .collect();
@@ -979,30 +1399,57 @@ impl Schema {
             if parents {
                 for i in 0..=level_idx {
                     // caption replaces name_column with the col from property.
-                    let caption = if let Some(caption_col) = caption_cols.get(&levels[i].name) {
-                        Some(caption_col.clone())
-                    } else {
-                        levels[i].name_column.clone()
+                    let (caption, caption_expression) = match caption_cols.get(&levels[i].name) {
+                        Some((caption_col, expression)) => (Some(caption_col.clone()), *expression),
+                        None => (levels[i].name_column.clone(), levels[i].name_expression),
                     };
                     level_columns.push(LevelColumn {
                         key_column: levels[i].key_column.clone(),
                         name_column: caption,
+                        order_column: levels[i].order_column.clone(),
+                        key_expression: levels[i].expression,
+                        name_expression: caption_expression,
+                        order_expression: levels[i].order_expression,
                     });
                 }
             } else {
                 // caption replaces name_column with the col from property.
                 // assertion that caption_col <= 1 above
-                let caption = if let Some(caption_col) = caption_cols.get(&levels[level_idx].name) {
-                    Some(caption_col.clone())
-                } else {
-                    levels[level_idx].name_column.clone()
+                let (caption, caption_expression) = match caption_cols.get(&levels[level_idx].name) {
+                    Some((caption_col, expression)) => (Some(caption_col.clone()), *expression),
+                    None => (levels[level_idx].name_column.clone(), levels[level_idx].name_expression),
                 };
                 level_columns.push(LevelColumn {
                     key_column: levels[level_idx].key_column.clone(),
                     name_column: caption,
+                    order_column: levels[level_idx].order_column.clone(),
+                    key_expression: levels[level_idx].expression,
+                    name_expression: caption_expression,
+                    order_expression: levels[level_idx].order_expression,
                 });
             }
 
+            // Calculated members are only added as rows when there's a single
+            // level column; there are no parent or property values for them.
+            // Queries on the drilldowns are checked in `sql_query`.
+            let calculated_members = if !parents && property_columns.is_empty() {
+                let member_type = levels[level_idx].key_type.clone().unwrap_or(MemberType::NonText);
+
+                levels[level_idx].calculated_members.iter()
+                    .flat_map(|cms| cms.iter())
+                    .map(|cm| {
+                        CalculatedMemberSql {
+                            key: cm.key.clone(),
+                            name: cm.caption(),
+                            members: cm.members.clone(),
+                            member_type: member_type.clone(),
+                        }
+                    })
+                    .collect()
+            } else {
+                vec![]
+            };
+
             let alias_postfix = dim.name.replace(" ", "_");
 
             res.push(DrilldownSql {
@@ -1010,9 +1457,11 @@ impl Schema {
                 table,
                 primary_key,
                 foreign_key,
+                foreign_key_expression: dim.foreign_key_expression,
                 level_columns,
                 property_columns,
-                inline_table: hier.inline_table.clone()
+                inline_table: hier.inline_table.clone(),
+                calculated_members,
             });
         }
 
@@ -1034,6 +1483,7 @@ impl Schema {
             res.push(MeasureSql {
                 column: mea.column.clone(),
                 aggregator: mea.aggregator.clone(),
+                expression: mea.expression,
             });
         }
 
@@ -1201,7 +1651,7 @@ impl Schema {
         Ok(res)
     }
 
-    fn get_dim_col_table(&self, cube_name: &str, level_name: &LevelName) -> Result<MembersQueryIR, Error> {
+    fn get_dim_col_table(&self, cube_name: &str, level_name: &LevelName, style: IdentifierStyle) -> Result<MembersQueryIR, Error> {
         let cube = self.cubes.iter()
             .find(|cube| &cube.name == &cube_name)
             .ok_or(format_err!("Could not find cube"))?;
@@ -1216,23 +1666,31 @@ impl Schema {
             .find(|lvl| lvl.name == level_name.level)
             .ok_or(format_err!("could not find level for level name"))?;
 
-        let table = hier.table.clone().unwrap_or_else(|| cube.table.clone());
+        let mut table = hier.table.clone().unwrap_or_else(|| cube.table.clone());
+        query_ir::quote_table(&mut table, style);
 
         // TODO: have a check that there can't be inline table and regular table at the same time.
         // Inline table has highest precedence.
         let table_sql = if let Some(ref inline) = hier.inline_table {
+            let mut inline = inline.clone();
+            query_ir::quote_inline_table(&mut inline, style);
             format!("({})", inline.sql_string())
         } else {
             table.full_name()
         };
 
-        let key_column = level.key_column.clone();
-        let name_column = level.name_column.clone();
+        let key_column = level.key_column_sql(style);
+        let name_column = level.name_column_sql(style);
+        let order_column = level.order_column_sql(style);
+
+        let calculated_members = level_calculated_members(level);
 
         Ok(MembersQueryIR {
             table_sql,
             key_column,
             name_column,
+            order_column,
+            calculated_members,
         })
     }
 
@@ -1252,7 +1710,7 @@ impl Schema {
             .ok_or(format_err!("could not find level for level name"))?;
 
         // TODO centralize where to get the alias
-        let column = format!("{}_{}", level.key_column, dim.name.replace(" ", "_"));
+        let column = query_ir::col_alias(&level.key_column, &dim.name.replace(" ", "_"));
 
         Ok(column)
     }
@@ -1278,11 +1736,104 @@ impl Schema {
     }
 }
 
+fn level_calculated_members(level: &schema::Level) -> Vec<CalculatedMemberSql> {
+    let member_type = level.key_type.clone().unwrap_or(MemberType::NonText);
+
+    level.calculated_members.iter()
+        .flat_map(|cms| cms.iter())
+        .map(|cm| {
+            CalculatedMemberSql {
+                key: cm.key.clone(),
+                name: cm.caption(),
+                members: cm.members.clone(),
+                member_type: member_type.clone(),
+            }
+        })
+        .collect()
+}
+
+/// `union all` selects for calculated members in members queries, with the
+/// name repeated for each caption column.
+fn calculated_members_union_sql(calculated_members: &[CalculatedMemberSql], caption_count: usize, style: LiteralStyle) -> String {
+    calculated_members.iter()
+        .map(|cm| {
+            let captions = (0..caption_count)
+                .map(|_| format!(", {}", cm.name_sql(style)))
+                .collect::<String>();
+
+            format!(" union all select {}{}", cm.key_sql(style), captions)
+        })
+        .collect()
+}
+
+/// Members query sorted by the level's order column.
+///
+/// Calculated members go after the ordered members. The order of a `union
+/// all` isn't guaranteed, so then the members are sorted in an outer query.
+fn ordered_members_sql(
+    table_sql: &str,
+    key_column: &str,
+    name_columns: &[String],
+    order_column: &str,
+    calculated_members: &[CalculatedMemberSql],
+    style: LiteralStyle,
+    ) -> String
+{
+    let cols = join(Some(key_column).into_iter().chain(name_columns.iter().map(|c| c.as_str())), ", ");
+
+    if calculated_members.is_empty() {
+        return format!("select {} from {} group by {} order by min({})",
+            cols,
+            table_sql,
+            cols,
+            order_column,
+        );
+    }
+
+    let aliases: Vec<_> = (0..=name_columns.len())
+        .map(|i| format!("member_{}", i))
+        .collect();
+
+    let aliased_cols = Some(key_column).into_iter()
+        .chain(name_columns.iter().map(|c| c.as_str()))
+        .zip(aliases.iter())
+        .map(|(col, alias)| format!("{} as {}", col, alias));
+
+    let mut sql = format!("select {}, 0 as calculated, min({}) as member_order from {} group by {}",
+        join(aliased_cols, ", "),
+        order_column,
+        table_sql,
+        cols,
+    );
+
+    for cm in calculated_members {
+        let captions = (0..name_columns.len())
+            .map(|_| format!(", {}", cm.name_sql(style)))
+            .collect::<String>();
+
+        sql.push_str(&format!(" union all select {}{}, 1, min({}) from {} where {} in ({})",
+            cm.key_sql(style),
+            captions,
+            order_column,
+            table_sql,
+            key_column,
+            cm.members_sql(style),
+        ));
+    }
+
+    format!("select {} from ({}) as members order by calculated, member_order",
+        join(aliases, ", "),
+        sql,
+    )
+}
+
 #[derive(Debug)]
 struct MembersQueryIR {
     table_sql: String,
     key_column: String,
     name_column: Option<String>,
+    order_column: Option<String>,
+    calculated_members: Vec<CalculatedMemberSql>,
 }
 
 
@@ -1396,6 +1947,89 @@ mod test {
         println!("{:#?}", schema);
     }
 
+    #[test]
+    fn test_quoted_identifiers() {
+        let s = r##"
+            <Schema name="my_schema">
+                <Cube name="my_cube">
+                    <Table name="Sales Facts" />
+                    <Dimension name="Geo" foreign_key="Geo-ID">
+                        <Hierarchy name="Geo" primary_key="Geo-ID">
+                            <Table name="dim_geo" />
+                            <Level name="Country" key_column="Geo-ID" name_column="Name" />
+                        </Hierarchy>
+                    </Dimension>
+                    <Dimension name="Flag" foreign_key="coalesce(flag_id, 0)" foreign_key_expression="true">
+                        <Hierarchy name="Flag" primary_key="flag_id">
+                            <Level name="Flag" key_column="CASE WHEN flag THEN 1 ELSE 0 END" expression="true"
+                                name_column="upper(flag_name)" name_expression="true"
+                                order_column="-flag_order" order_expression="true"
+                            >
+                                <Property name="Short" column="substr(flag_name, 1, 1)" expression="true" />
+                                <Property name="Code" column="Flag Code" />
+                            </Level>
+                        </Hierarchy>
+                    </Dimension>
+                    <Measure name="Quantity" column="quantity" aggregator="sum" />
+                    <Measure name="Price" column="Unit Price" aggregator="sum" />
+                    <Measure name="Previous" column="x - 1" aggregator="sum" />
+                    <Measure name="Revenue" column="price * quantity" expression="true" aggregator="sum" />
+                </Cube>
+            </Schema>
+        "##;
+        let mut schema = Schema::from_xml(s).unwrap();
+        schema.validate().unwrap();
+
+        let (members_sql, _header) = schema.members_sql("my_cube", &"Geo.Geo.Country".parse().unwrap(), IdentifierStyle::DoubleQuote, LiteralStyle::Standard).unwrap();
+        assert_eq!(members_sql, r#"select distinct "Geo-ID", "Name" from dim_geo"#);
+
+        let mut query = Query::new();
+        query.drilldowns = vec!["Geo.Geo.Country".parse().unwrap()];
+        query.measures = vec!["Price".parse().unwrap()];
+
+        let (mut query_ir, _headers) = schema.sql_query("my_cube", &query, None).unwrap();
+        query_ir.quote_identifiers(IdentifierStyle::Backtick);
+        assert_eq!(query_ir.table.name, "`Sales Facts`");
+        assert_eq!(query_ir.meas[0].column, "`Unit Price`");
+        assert_eq!(query_ir.drills[0].col_alias_only_string(), "Geo_ID_3037bb68_Geo, Name_Geo");
+
+        // only columns declared as expressions are written as is
+        let mut query = Query::new();
+        query.drilldowns = vec!["Flag.Flag.Flag".parse().unwrap()];
+        query.cuts = vec!["Flag.Flag.Flag.1".parse().unwrap()];
+        query.measures = vec!["Previous".parse().unwrap(), "Revenue".parse().unwrap()];
+        query.properties = vec!["Flag.Flag.Flag.Short".parse().unwrap(), "Flag.Flag.Flag.Code".parse().unwrap()];
+
+        let (mut query_ir, _headers) = schema.sql_query("my_cube", &query, None).unwrap();
+        query_ir.quote_identifiers(IdentifierStyle::DoubleQuote);
+        assert_eq!(query_ir.meas[0].column, r#""x - 1""#);
+        assert_eq!(query_ir.meas[1].column, "price * quantity");
+
+        let drill = &query_ir.drills[0];
+        assert_eq!(drill.foreign_key, "coalesce(flag_id, 0)");
+        assert_eq!(drill.level_columns[0], LevelColumn {
+            key_column: "CASE WHEN flag THEN 1 ELSE 0 END".to_owned(),
+            name_column: Some("upper(flag_name)".to_owned()),
+            order_column: Some("-flag_order".to_owned()),
+            key_expression: true,
+            name_expression: true,
+            order_expression: true,
+        });
+        assert_eq!(drill.property_columns.iter().map(|p| p.column.as_str()).collect::<Vec<_>>(), vec!["substr(flag_name, 1, 1)", r#""Flag Code""#]);
+        assert_eq!(query_ir.cuts[0].column, "CASE WHEN flag THEN 1 ELSE 0 END");
+        assert_eq!(query_ir.cuts[0].foreign_key, "coalesce(flag_id, 0)");
+
+        let (members_sql, _header) = schema.members_locale_sql("my_cube", &"Flag.Flag.Flag".parse().unwrap(), "en", IdentifierStyle::DoubleQuote, LiteralStyle::Standard).unwrap();
+        assert_eq!(members_sql, r#"select CASE WHEN flag THEN 1 ELSE 0 END, upper(flag_name) from "Sales Facts" group by CASE WHEN flag THEN 1 ELSE 0 END, upper(flag_name) order by min(-flag_order)"#);
+
+        // tables must be names (with the schema set apart), and names can't
+        // have quotes
+        let invalid = s.replace(r#"<Table name="dim_geo" />"#, r#"<Table name="public.dim_geo" />"#);
+        assert!(Schema::from_xml(&invalid).unwrap().validate().is_err());
+        let invalid = s.replace(r#"name_column="Name""#, r#"name_column="Na`me""#);
+        assert!(Schema::from_xml(&invalid).unwrap().validate().is_err());
+    }
+
     #[test]
     fn test_basic_default_member() {
         let s = r##"
@@ -1742,4 +2376,312 @@ mod test {
             constraint2: None,
         }].to_vec())
     }
+
+    const SCHEMA_STR_CALCULATED_MEMBERS: &str = r##"
+        <Schema name="my_schema">
+            <Cube name="my_cube">
+                <Table name="my_table" />
+                <Dimension name="Geo" foreign_key="country_id">
+                    <Hierarchy name="Geo" primary_key="country_id">
+                        <Table name="dim_geo" />
+                        <Level name="Country" key_column="country_id" name_column="country_name" key_type="text">
+                            <CalculatedMember key="eu" name="European Union">
+                                <Member>fra</Member>
+                                <Member>deu</Member>
+                            </CalculatedMember>
+                        </Level>
+                    </Hierarchy>
+                </Dimension>
+                <Measure name="Quantity" column="quantity" aggregator="sum" />
+                <Measure name="Price" column="price" aggregator="avg" />
+            </Cube>
+        </Schema>
+    "##;
+
+    #[test]
+    fn test_calculated_members() {
+        let mut schema = Schema::from_xml(SCHEMA_STR_CALCULATED_MEMBERS).unwrap();
+        schema.validate().unwrap();
+
+        let mut query = Query::new();
+        query.drilldowns = vec!["Geo.Geo.Country".parse().unwrap()];
+        query.cuts = vec!["Geo.Geo.Country.eu,ita".parse().unwrap()];
+        query.measures = vec![Measure("Quantity".to_string())];
+
+        let (query_ir, _headers) = schema.sql_query("my_cube", &query, None).unwrap();
+        assert_eq!(query_ir.cuts[0].members, vec!["fra".to_string(), "deu".to_string(), "ita".to_string()]);
+        assert_eq!(query_ir.drills[0].calculated_members[0].key, "eu".to_string());
+        assert_eq!(
+            query_ir::dim_subquery(Some(&query_ir.drills[0]), None, LiteralStyle::Standard).sql,
+            "select distinct country_id as country_id_Geo, country_name as country_name_Geo, country_id as country_id from dim_geo \
+            union all select distinct 'eu' as country_id_Geo, 'European Union' as country_name_Geo, country_id as country_id from dim_geo where country_id in ('fra', 'deu')".to_string()
+        );
+
+        let (members_sql, _header) = schema.members_sql("my_cube", &"Geo.Geo.Country".parse().unwrap(), IdentifierStyle::DoubleQuote, LiteralStyle::Standard).unwrap();
+        assert_eq!(
+            members_sql,
+            "select distinct country_id, country_name from dim_geo union all select 'eu', 'European Union'".to_string()
+        );
+
+        // calculated members are rolled up, so non-additive aggregators are not allowed
+        query.measures = vec![Measure("Price".to_string())];
+        assert!(schema.sql_query("my_cube", &query, None).is_err());
+
+        // calculated members have no parents
+        query.measures = vec![Measure("Quantity".to_string())];
+        query.parents = true;
+        assert!(schema.sql_query("my_cube", &query, None).is_err());
+    }
+
+    #[test]
+    fn test_calculated_members_escaped() {
+        let s = SCHEMA_STR_CALCULATED_MEMBERS
+            .replace(r#"name="European Union""#, r#"name="Europe's Union""#)
+            .replace("<Member>deu</Member>", r"<Member>de\'u</Member>");
+        let schema = Schema::from_xml(&s).unwrap();
+
+        let mut query = Query::new();
+        query.drilldowns = vec!["Geo.Geo.Country".parse().unwrap()];
+        query.measures = vec![Measure("Quantity".to_string())];
+
+        let (query_ir, _headers) = schema.sql_query("my_cube", &query, None).unwrap();
+        assert_eq!(
+            query_ir::dim_subquery(Some(&query_ir.drills[0]), None, LiteralStyle::Backslash).sql,
+            "select distinct country_id as country_id_Geo, country_name as country_name_Geo, country_id as country_id from dim_geo \
+            union all select distinct 'eu' as country_id_Geo, 'Europe\\'s Union' as country_name_Geo, country_id as country_id from dim_geo where country_id in ('fra', 'de\\\\\\'u')".to_string()
+        );
+
+        let (members_sql, _header) = schema.members_sql("my_cube", &"Geo.Geo.Country".parse().unwrap(), IdentifierStyle::DoubleQuote, LiteralStyle::Standard).unwrap();
+        assert_eq!(
+            members_sql,
+            "select distinct country_id, country_name from dim_geo union all select 'eu', 'Europe''s Union'".to_string()
+        );
+    }
+
+    #[test]
+    fn test_order_column() {
+        let s = r##"
+            <Schema name="my_schema">
+                <Cube name="my_cube">
+                    <Table name="my_table" />
+                    <Dimension name="Month" foreign_key="month_id">
+                        <Hierarchy name="Month" primary_key="month_id">
+                            <Table name="dim_month" />
+                            <Level name="Month" key_column="month_id" name_column="month_name" order_column="month_num" />
+                        </Hierarchy>
+                    </Dimension>
+                    <Measure name="Quantity" column="quantity" aggregator="sum" />
+                </Cube>
+            </Schema>
+        "##;
+        let schema = Schema::from_xml(s).unwrap();
+
+        let mut query = Query::new();
+        query.drilldowns = vec!["Month.Month.Month".parse().unwrap()];
+        query.measures = vec![Measure("Quantity".to_string())];
+
+        let (query_ir, _headers) = schema.sql_query("my_cube", &query, None).unwrap();
+        assert_eq!(query_ir.drills[0].level_columns[0].order_column, Some("month_num".to_string()));
+
+        let (members_sql, _header) = schema.members_sql("my_cube", &"Month.Month.Month".parse().unwrap(), IdentifierStyle::DoubleQuote, LiteralStyle::Standard).unwrap();
+        assert_eq!(
+            members_sql,
+            "select month_id, month_name from dim_month group by month_id, month_name order by min(month_num)".to_string()
+        );
+
+        let metadata = schema.cube_metadata("my_cube", None, None).unwrap();
+        assert_eq!(metadata.dimensions[0].hierarchies[0].levels[0].order_column, Some("month_num".to_string()));
+
+        // calculated members are sorted after the others, in the outer query
+        let s = s.replace(r#"order_column="month_num" />"#, r#"order_column="month_num">
+                                <CalculatedMember key="q1" name="Q1">
+                                    <Member>1</Member>
+                                    <Member>2</Member>
+                                    <Member>3</Member>
+                                </CalculatedMember>
+                            </Level>"#);
+        let schema = Schema::from_xml(&s).unwrap();
+
+        let (members_sql, _header) = schema.members_sql("my_cube", &"Month.Month.Month".parse().unwrap(), IdentifierStyle::DoubleQuote, LiteralStyle::Standard).unwrap();
+        assert_eq!(
+            members_sql,
+            "select member_0, member_1 from (\
+            select month_id as member_0, month_name as member_1, 0 as calculated, min(month_num) as member_order from dim_month group by month_id, month_name \
+            union all select 'q1', 'Q1', 1, min(month_num) from dim_month where month_id in (1, 2, 3)\
+            ) as members order by calculated, member_order".to_string()
+        );
+    }
+
+    #[test]
+    fn test_date_column_dimension() {
+        let s = r##"
+            <Schema name="my_schema">
+                <Cube name="my_cube">
+                    <Table name="my_table" />
+                    <Dimension name="Sale Date" date_column="sale_date">
+                        <DateLevel>year</DateLevel>
+                        <DateLevel>month</DateLevel>
+                    </Dimension>
+                    <Measure name="Quantity" column="quantity" aggregator="sum" />
+                </Cube>
+            </Schema>
+        "##;
+        let mut schema = Schema::from_xml(s).unwrap();
+        schema.validate().unwrap();
+
+        let dim = &schema.cubes[0].dimensions[0];
+        assert_eq!(dim.dim_type, schema::DimensionType::Time);
+        assert_eq!(dim.foreign_key, Some("sale_date".to_string()));
+        let level_names: Vec<_> = dim.hierarchies[0].levels.iter().map(|l| l.name.clone()).collect();
+        assert_eq!(level_names, vec!["Year".to_string(), "Month".to_string()]);
+
+        let mut query = Query::new();
+        query.drilldowns = vec!["Sale Date.Sale Date.Month".parse().unwrap()];
+        query.cuts = vec!["Sale Date.Sale Date.Year.2019".parse().unwrap()];
+        query.measures = vec![Measure("Quantity".to_string())];
+
+        // date levels are expressions, which aren't quoted
+        let (mut query_ir, _headers) = schema.sql_query("my_cube", &query, None).unwrap();
+        query_ir.quote_identifiers(IdentifierStyle::DoubleQuote);
+        assert_eq!(query_ir.cuts[0].column, "EXTRACT(YEAR FROM sale_date)".to_string());
+        assert_eq!(
+            query_ir.drills[0].col_alias_string(),
+            "EXTRACT(YEAR FROM sale_date) * 100 + EXTRACT(MONTH FROM sale_date) as EXTRACT_YEAR_FROM_sale_date____100___EXTRACT_MONTH_FROM_sale_date__999f4738_Sale_Date".to_string()
+        );
+    }
+
+    #[test]
+    fn test_metadata_captions() {
+        let s = r##"
+            <Schema name="my_schema" default_locale="en">
+                <Cube name="sales">
+                    <Caption locale="en">Sales</Caption>
+                    <Caption locale="es">Ventas</Caption>
+                    <Table name="sales" />
+                    <Dimension name="Geo" foreign_key="geo_id">
+                        <Caption locale="es">Geografía</Caption>
+                        <Hierarchy name="Geo">
+                            <Table name="dim_geo" />
+                            <Level name="Country" key_column="country_id">
+                                <Caption locale="pt">País</Caption>
+                            </Level>
+                        </Hierarchy>
+                    </Dimension>
+                    <Measure name="Quantity" column="quantity" aggregator="sum">
+                        <Caption locale="es">Cantidad</Caption>
+                    </Measure>
+                </Cube>
+            </Schema>
+        "##;
+        let schema = Schema::from_xml(s).unwrap();
+
+        let metadata = schema.cube_metadata("sales", None, Some("es")).unwrap();
+        assert_eq!(metadata.name, "sales");
+        assert_eq!(metadata.caption, "Ventas");
+        assert_eq!(metadata.dimensions[0].caption, "Geografía");
+        assert_eq!(metadata.dimensions[0].hierarchies[0].levels[0].caption, "Country");
+        assert_eq!(metadata.measures[0].caption, "Cantidad");
+
+        // falls back to default locale, then to the name
+        let metadata = schema.metadata(None, Some("fr"));
+        assert_eq!(metadata.cubes[0].caption, "Sales");
+        assert_eq!(metadata.cubes[0].dimensions[0].caption, "Geo");
+    }
+
+    #[test]
+    fn test_element_auth_levels() {
+        let s = r##"
+            <Schema name="my_schema">
+                <Cube name="sales" min_auth_level="1">
+                    <Table name="sales" />
+                    <Dimension name="Geo" foreign_key="geo_id">
+                        <Hierarchy name="Geo">
+                            <Table name="dim_geo" />
+                            <Level name="Country" key_column="country_id" />
+                            <Level name="City" key_column="city_id" min_auth_level="3" />
+                        </Hierarchy>
+                    </Dimension>
+                    <Dimension name="Customer" foreign_key="customer_id" min_auth_level="2">
+                        <Hierarchy name="Customer">
+                            <Table name="dim_customer" />
+                            <Level name="Customer" key_column="customer_id" />
+                        </Hierarchy>
+                    </Dimension>
+                    <Measure name="Quantity" column="quantity" aggregator="sum" />
+                    <Measure name="Price" column="price" aggregator="avg" min_auth_level="2" />
+                </Cube>
+            </Schema>
+        "##;
+        let schema = Schema::from_xml(s).unwrap();
+        let cube = schema.get_cube_by_name("sales").unwrap();
+
+        let mut query = Query::new();
+        query.drilldowns = vec!["Geo.Country".parse().unwrap(), "Geo.City".parse().unwrap()];
+        query.cuts = vec!["Customer.Customer.1".parse().unwrap()];
+        query.measures = vec!["Quantity".parse().unwrap()];
+        query.sort = Some("Price.desc".parse().unwrap());
+
+        assert_eq!(cube.query_auth_levels(&query), vec![
+            (r#"Level "Geo.Geo.City""#.to_owned(), 3),
+            (r#"Dimension "Customer""#.to_owned(), 2),
+            (r#"Measure "Price""#.to_owned(), 2),
+        ]);
+
+        let metadata = schema.metadata(Some(2), None);
+        let cube_metadata = &metadata.cubes[0];
+        assert_eq!(cube_metadata.dimensions.len(), 2);
+        assert_eq!(cube_metadata.dimensions[0].hierarchies[0].levels.len(), 1);
+        assert_eq!(cube_metadata.measures.len(), 2);
+
+        let cube_metadata = schema.cube_metadata("sales", Some(1), None).unwrap();
+        assert_eq!(cube_metadata.dimensions.len(), 1);
+        assert_eq!(cube_metadata.measures.len(), 1);
+
+        // no auth on the server, nothing hidden
+        let cube_metadata = schema.cube_metadata("sales", None, None).unwrap();
+        assert_eq!(cube_metadata.dimensions[0].hierarchies[0].levels.len(), 2);
+    }
+
+    #[test]
+    fn test_validate_catalog() {
+        let s = r##"
+            <Schema name="my_schema">
+                <Cube name="sales">
+                    <Table name="sales" />
+                    <Dimension name="Geo" foreign_key="geo_id">
+                        <Hierarchy name="Geo">
+                            <Table name="dim_geo" />
+                            <Level name="Country" key_column="country_idd" name_column="country_name">
+                                <Property name="Country ES" column="country_name_es" caption_set="es" />
+                            </Level>
+                        </Hierarchy>
+                    </Dimension>
+                    <Dimension name="Product" foreign_key="product_id">
+                        <Hierarchy name="Product">
+                            <Table name="dim_product" />
+                            <Level name="Product" key_column="product_id" />
+                        </Hierarchy>
+                    </Dimension>
+                    <Measure name="Quantity" column="quantity" aggregator="sum" />
+                    <Measure name="Revenue" column="revenu" aggregator="sum" />
+                </Cube>
+            </Schema>
+        "##;
+        let schema = Schema::from_xml(s).unwrap();
+
+        let tables: Vec<_> = schema.catalog_tables().iter().map(|t| t.full_name()).collect();
+        assert_eq!(tables, vec!["sales".to_string(), "dim_geo".to_string(), "dim_product".to_string()]);
+
+        let mut catalog = HashMap::new();
+        catalog.insert("sales".to_string(), vec!["geo_id".to_string(), "product_id".to_string(), "quantity".to_string(), "revenue".to_string()]);
+        catalog.insert("dim_geo".to_string(), vec!["country_id".to_string(), "country_name".to_string(), "country_name_es".to_string()]);
+
+        let err = schema.validate_catalog(&catalog).unwrap_err().to_string();
+        let problems: Vec<_> = err.lines().skip(1).collect();
+        assert_eq!(problems, vec![
+            "sales.Revenue: column revenu not found in table sales",
+            "sales.Geo.Geo.Country: column country_idd not found in table dim_geo",
+            "sales.Product.Product: table dim_product not found",
+        ]);
+    }
 }
@@ -26,6 +26,59 @@ pub struct QueryIr {
     pub sparse: bool,
 }
 
+impl QueryIr {
+    /// Replaces the members of all cuts with placeholders for bound
+    /// parameters, and returns the parameters in placeholder order. The
+    /// placeholder for a parameter is made by `placeholder`, from its 1-based
+    /// index and value.
+    pub fn bind_cut_members<F>(&mut self, placeholder: F) -> Vec<SqlParam>
+        where F: Fn(usize, &SqlParam) -> String
+    {
+        let mut params = vec![];
+
+        for cut in self.cuts.iter_mut() {
+            cut.bind_members(&mut params, &placeholder);
+        }
+
+        params
+    }
+
+    /// Quotes the table and column names of the query which need it (see
+    /// `quote_identifier`). Called by backends before generating sql; column
+    /// aliases are the same whether names are quoted or not.
+    pub fn quote_identifiers(&mut self, style: IdentifierStyle) {
+        quote_in_place(&mut self.table.name, style);
+        if let Some(ref mut primary_key) = self.table.primary_key {
+            quote_in_place(primary_key, style);
+        }
+
+        for cut in self.cuts.iter_mut() {
+            cut.quote_identifiers(style);
+        }
+        for drill in self.drills.iter_mut() {
+            drill.quote_identifiers(style);
+        }
+        for hidden_drill in self.hidden_drills.iter_mut() {
+            hidden_drill.drilldown_sql.quote_identifiers(style);
+        }
+        for mea in self.meas.iter_mut() {
+            mea.quote_identifiers(style);
+        }
+        if let Some(ref mut rca) = self.rca {
+            for drill in rca.drill_1.iter_mut().chain(rca.drill_2.iter_mut()) {
+                drill.quote_identifiers(style);
+            }
+            rca.mea.quote_identifiers(style);
+        }
+        if let Some(ref mut growth) = self.growth {
+            growth.time_drill.quote_identifiers(style);
+        }
+        if let Some(ref mut rate) = self.rate {
+            rate.drilldown_sql.quote_identifiers(style);
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct TableSql {
     pub name: String,
@@ -38,12 +91,90 @@ pub struct DrilldownSql {
     pub table: Table,
     pub primary_key: String,
     pub foreign_key: String,
+    // the foreign key is an sql expression, and isn't quoted
+    pub foreign_key_expression: bool,
     pub level_columns: Vec<LevelColumn>,
-    pub property_columns: Vec<String>,
+    pub property_columns: Vec<PropertyColumn>,
     pub inline_table: Option<InlineTable>,
+    // Only set when drilling on a single level (no parents or properties)
+    pub calculated_members: Vec<CalculatedMemberSql>,
 }
 
 impl DrilldownSql {
+    pub fn quote_identifiers(&mut self, style: IdentifierStyle) {
+        quote_table(&mut self.table, style);
+        quote_in_place(&mut self.primary_key, style);
+        if !self.foreign_key_expression {
+            quote_in_place(&mut self.foreign_key, style);
+        }
+
+        for l in self.level_columns.iter_mut() {
+            if !l.key_expression {
+                quote_in_place(&mut l.key_column, style);
+            }
+            if let Some(ref mut name_column) = l.name_column {
+                if !l.name_expression {
+                    quote_in_place(name_column, style);
+                }
+            }
+            if let Some(ref mut order_column) = l.order_column {
+                if !l.order_expression {
+                    quote_in_place(order_column, style);
+                }
+            }
+        }
+        for p in self.property_columns.iter_mut() {
+            if !p.expression {
+                quote_in_place(&mut p.column, style);
+            }
+        }
+        if let Some(ref mut inline_table) = self.inline_table {
+            quote_inline_table(inline_table, style);
+        }
+    }
+
+    /// For each calculated member, a select which maps every primary key of
+    /// its child members to the calculated member's key and name. Meant to be
+    /// `union all`ed onto the dim table select, so that the fact rows of the
+    /// children are also aggregated into the calculated member.
+    ///
+    /// Column aliases mirror `col_alias_string`.
+    pub fn calculated_members_sql(&self, drill_table: &str, style: LiteralStyle) -> Vec<String> {
+        let level_column = match self.level_columns.last() {
+            Some(l) => l,
+            None => return vec![],
+        };
+
+        self.calculated_members.iter()
+            .map(|cm| {
+                let name_col = if let Some(ref name_col) = level_column.name_column {
+                    format!(", {} as {}", cm.name_sql(style), col_alias(name_col, &self.alias_postfix))
+                } else {
+                    "".to_owned()
+                };
+
+                // each row keeps the order of the member it's made from
+                let order_col = if let Some(ref order_col) = level_column.order_column {
+                    format!(", {} as {}", order_col, self.order_alias(order_col))
+                } else {
+                    "".to_owned()
+                };
+
+                format!("select distinct {} as {}{}{}, {} as {} from {} where {} in ({})",
+                    cm.key_sql(style),
+                    col_alias(&level_column.key_column, &self.alias_postfix),
+                    name_col,
+                    order_col,
+                    self.primary_key,
+                    self.foreign_key,
+                    drill_table,
+                    level_column.key_column,
+                    cm.members_sql(style),
+                )
+            })
+            .collect()
+    }
+
     pub fn col_alias_string(&self) -> String {
         let cols = self.col_alias_vec();
         join(cols, ", ")
@@ -57,26 +188,23 @@ impl DrilldownSql {
         let mut cols: Vec<_> = self.level_columns.iter()
             .map(|l| {
                 if let Some(ref name_col) = l.name_column {
-                    format!("{} as {}_{}, {} as {}_{}",
+                    format!("{} as {}, {} as {}",
                         l.key_column,
-                        l.key_column,
-                        self.alias_postfix,
-                        name_col,
+                        col_alias(&l.key_column, &self.alias_postfix),
                         name_col,
-                        self.alias_postfix,
+                        col_alias(name_col, &self.alias_postfix),
                     )
                 } else {
-                    format!("{} as {}_{}",
-                        l.key_column,
+                    format!("{} as {}",
                         l.key_column,
-                        self.alias_postfix,
+                        col_alias(&l.key_column, &self.alias_postfix),
                     )
                 }
             }).collect();
 
         if self.property_columns.len() != 0 {
             cols.push(
-                join(&self.property_columns, ", ")
+                join(self.property_columns.iter().map(|p| &p.column), ", ")
             );
         }
 
@@ -96,22 +224,16 @@ impl DrilldownSql {
         // This matters because growth needs the vec
         // version to map onto single cols.
         for l in self.level_columns.iter() {
-            cols.push(format!("{}_{}",
-                l.key_column,
-                self.alias_postfix,
-            ));
+            cols.push(col_alias(&l.key_column, &self.alias_postfix));
 
             if let Some(ref name_col) = l.name_column {
-                cols.push(format!("{}_{}",
-                    name_col,
-                    self.alias_postfix,
-                ));
+                cols.push(col_alias(name_col, &self.alias_postfix));
             }
         }
 
         if self.property_columns.len() != 0 {
             cols.push(
-                join(&self.property_columns, ", ")
+                join(self.property_columns.iter().map(|p| &p.column), ", ")
             );
         }
 
@@ -136,7 +258,7 @@ impl DrilldownSql {
         if self.property_columns.len() != 0 {
             let prop_cols_qual = self.property_columns.iter()
                 .map(|p| {
-                    format!("{}.{}", self.table.name, p)
+                    format!("{}.{}", self.table.name, p.column)
                 });
 
             cols.push(
@@ -146,6 +268,126 @@ impl DrilldownSql {
 
         cols
     }
+
+    /// Alias for the order column of a level. Order columns are kept apart
+    /// from the `col_alias` cols, because they're sorted on but not returned.
+    pub fn order_alias(&self, order_column: &str) -> String {
+        col_alias(order_column, &format!("{}_order", self.alias_postfix))
+    }
+
+    pub fn order_col_alias_vec(&self) -> Vec<String> {
+        self.level_columns.iter()
+            .filter_map(|l| l.order_column.as_ref())
+            .map(|o| format!("{} as {}", o, self.order_alias(o)))
+            .collect()
+    }
+
+    pub fn order_alias_only_vec(&self) -> Vec<String> {
+        self.level_columns.iter()
+            .filter_map(|l| l.order_column.as_ref())
+            .map(|o| self.order_alias(o))
+            .collect()
+    }
+
+    /// Order columns aggregated to one value per member, for the final
+    /// grouping. Calculated members take the first order of their members.
+    pub fn min_order_alias_vec(&self) -> Vec<String> {
+        self.order_alias_only_vec().iter()
+            .map(|alias| format!("min({}) as {}_min", alias, alias))
+            .collect()
+    }
+
+    /// Cols to sort the drilldown on: like `col_alias_only_vec`, but with
+    /// the min order of a level before its key.
+    pub fn sort_alias_vec(&self) -> Vec<String> {
+        let mut cols = vec![];
+
+        for l in self.level_columns.iter() {
+            if let Some(ref order_col) = l.order_column {
+                cols.push(format!("{}_min", self.order_alias(order_col)));
+            }
+
+            cols.push(col_alias(&l.key_column, &self.alias_postfix));
+
+            if let Some(ref name_col) = l.name_column {
+                cols.push(col_alias(name_col, &self.alias_postfix));
+            }
+        }
+
+        if self.property_columns.len() != 0 {
+            cols.push(
+                join(self.property_columns.iter().map(|p| &p.column), ", ")
+            );
+        }
+
+        cols
+    }
+}
+
+/// Alias for a level key or name column in a drilldown.
+///
+/// Columns may have any char (or be sql expressions, e.g. for levels
+/// generated from a date column), so chars that aren't valid in an
+/// identifier are replaced. The alias of a quoted column is the same as the
+/// alias of its name.
+///
+/// Replacing chars could make two columns share an alias (`my col` and
+/// `my_col`), so a hash of the column is added when chars were replaced.
+pub fn col_alias(column: &str, alias_postfix: &str) -> String {
+    let column = unquote(column);
+
+    let alias: String = column.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect();
+
+    if alias == column {
+        format!("{}_{}", alias, alias_postfix)
+    } else {
+        format!("{}_{:08x}_{}", alias, fnv1a(&column), alias_postfix)
+    }
+}
+
+/// A name without its quotes, if it's quoted with either style
+fn unquote(name: &str) -> String {
+    for quote in &['"', '`'] {
+        if name.len() > 1 && name.starts_with(*quote) && name.ends_with(*quote) {
+            let doubled = format!("{}{}", quote, quote);
+            return name[1..name.len() - 1].replace(&doubled, &quote.to_string());
+        }
+    }
+
+    name.to_owned()
+}
+
+/// 32-bit FNV-1a hash, which is stable across platforms and releases
+fn fnv1a(value: &str) -> u32 {
+    value.bytes().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalculatedMemberSql {
+    pub key: String,
+    pub name: String,
+    pub members: Vec<String>,
+    pub member_type: MemberType,
+}
+
+impl CalculatedMemberSql {
+    pub fn key_sql(&self, style: LiteralStyle) -> String {
+        member_literal(&self.key, &self.member_type, style)
+    }
+
+    pub fn name_sql(&self, style: LiteralStyle) -> String {
+        text_literal(&self.name, style)
+    }
+
+    pub fn members_sql(&self, style: LiteralStyle) -> String {
+        let literals = self.members.iter()
+            .map(|m| member_literal(m, &self.member_type, style));
+        join(literals, ", ")
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -159,6 +401,20 @@ pub struct HiddenDrilldownSql {
 pub struct LevelColumn {
     pub key_column: String,
     pub name_column: Option<String>,
+    // only used for sorting, never projected
+    pub order_column: Option<String>,
+    // the key column is an sql expression, and isn't quoted
+    pub key_expression: bool,
+    // same, for the name and order columns
+    pub name_expression: bool,
+    pub order_expression: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropertyColumn {
+    pub column: String,
+    // the column is an sql expression, and isn't quoted
+    pub expression: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -166,7 +422,11 @@ pub struct CutSql {
     pub table: Table,
     pub primary_key: String,
     pub foreign_key: String,
+    // the foreign key is an sql expression, and isn't quoted
+    pub foreign_key_expression: bool,
     pub column: String,
+    // the column is an sql expression, and isn't quoted
+    pub expression: bool,
     pub members: Vec<String>,
     pub member_type: MemberType,
     // Mask is Includes or Excludes on set of cut members
@@ -174,44 +434,93 @@ pub struct CutSql {
     // if for_match, then use LIKE syntax
     pub for_match: bool,
     pub inline_table: Option<InlineTable>,
+    // if bound, members are placeholders for bound parameters, and are
+    // written into the sql as is
+    pub bound: bool,
 }
 
 impl CutSql {
+    /// Members as standard sql literals.
     pub fn members_string(&self) -> String {
-        let members = match self.member_type {
-            MemberType::NonText => join(&self.members, ", "),
-            MemberType::Text => {
-                let quoted = self.members.iter()
-                .map(|m| format!("'{}'", m));
-                join(quoted, ", ")
-            }
-        };
-
-        format!("{}", members)
+        self.members_sql(LiteralStyle::Standard)
     }
 
-    pub fn members_like_string(&self) -> String {
-        match self.member_type {
-            MemberType::NonText => {
-                // this behavior doesn't really make sense; it should be for
-                // labels only, which are almost always strings.
-                let unquoted = self.members.iter()
-                    .map(|m| format!("{} {} {}", self.column, self.mask_sql_like_string(), m));
+    /// Members as literals, escaped for the dialect. Text members are
+    /// quoted; non-text members which aren't numbers are quoted as well, so
+    /// that no member can inject sql.
+    pub fn members_sql(&self, style: LiteralStyle) -> String {
+        join(self.member_literals(style), ", ")
+    }
 
-                match self.mask {
-                    Mask::Include => format!("({})", join(unquoted, " or ")),
-                    Mask::Exclude => format!("{}", join(unquoted, " and ")),
+    fn member_literals(&self, style: LiteralStyle) -> Vec<String> {
+        self.members.iter()
+            .map(|m| {
+                if self.bound {
+                    m.clone()
+                } else {
+                    member_literal(m, &self.member_type, style)
                 }
-            },
-            MemberType::Text => {
-                let quoted = self.members.iter()
-                    .map(|m| format!("{} {} '%{}%'", self.column, self.mask_sql_like_string(), m));
+            })
+            .collect()
+    }
 
-                match self.mask {
-                    Mask::Include => format!("({})", join(quoted, " or ")),
-                    Mask::Exclude => format!("{}", join(quoted, " and ")),
-                }
-            }
+    pub fn members_like_string(&self, style: LiteralStyle) -> String {
+        let conditions = self.members.iter()
+            .map(|m| {
+                let pattern = match self.member_type {
+                    _ if self.bound => m.clone(),
+                    // this behavior doesn't really make sense; it should be for
+                    // labels only, which are almost always strings.
+                    MemberType::NonText => member_literal(m, &self.member_type, style),
+                    MemberType::Text => text_literal(&format!("%{}%", m), style),
+                };
+
+                format!("{} {} {}", self.column, self.mask_sql_like_string(), pattern)
+            });
+
+        match self.mask {
+            Mask::Include => format!("({})", join(conditions, " or ")),
+            Mask::Exclude => format!("{}", join(conditions, " and ")),
+        }
+    }
+
+    /// Replaces the members with placeholders for bound parameters, adding
+    /// their values to `params`. `placeholder` gets the 1-based index of the
+    /// parameter in the query.
+    pub fn bind_members<F>(&mut self, params: &mut Vec<SqlParam>, placeholder: &F)
+        where F: Fn(usize, &SqlParam) -> String
+    {
+        if self.bound {
+            return;
+        }
+
+        let mut placeholders = vec![];
+
+        for member in &self.members {
+            let param = match self.member_type {
+                MemberType::Text if self.for_match => SqlParam::Text(format!("%{}%", member)),
+                _ => SqlParam::from_member(member, &self.member_type),
+            };
+            params.push(param);
+            placeholders.push(placeholder(params.len(), &params[params.len() - 1]));
+        }
+
+        self.members = placeholders;
+        self.bound = true;
+    }
+
+    pub fn quote_identifiers(&mut self, style: IdentifierStyle) {
+        quote_table(&mut self.table, style);
+        quote_in_place(&mut self.primary_key, style);
+        if !self.foreign_key_expression {
+            quote_in_place(&mut self.foreign_key, style);
+        }
+        if !self.expression {
+            quote_in_place(&mut self.column, style);
+        }
+
+        if let Some(ref mut inline_table) = self.inline_table {
+            quote_inline_table(inline_table, style);
         }
     }
 
@@ -234,6 +543,177 @@ impl CutSql {
     }
 }
 
+/// How quotes are escaped in sql string literals.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LiteralStyle {
+    /// `'` is doubled, and backslashes are not special (standard sql,
+    /// postgres)
+    Standard,
+    /// `'` and `\` are escaped with a backslash (clickhouse, mysql)
+    Backslash,
+}
+
+/// Quotes a string as a sql literal.
+pub fn text_literal(value: &str, style: LiteralStyle) -> String {
+    let escaped = match style {
+        LiteralStyle::Standard => value.replace('\'', "''"),
+        LiteralStyle::Backslash => value.replace('\\', "\\\\").replace('\'', "\\'"),
+    };
+
+    format!("'{}'", escaped)
+}
+
+/// A member as a literal of its type. Non-text members are only written
+/// as is if they're numbers.
+pub fn member_literal(member: &str, member_type: &MemberType, style: LiteralStyle) -> String {
+    match member_type {
+        MemberType::NonText if is_number(member) => member.to_owned(),
+        _ => text_literal(member, style),
+    }
+}
+
+fn is_number(value: &str) -> bool {
+    value.parse::<i64>().is_ok()
+        || value.parse::<f64>().map(|n| n.is_finite()).unwrap_or(false)
+}
+
+/// How table and column names are quoted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IdentifierStyle {
+    /// `"name"` (standard sql, postgres)
+    DoubleQuote,
+    /// `` `name` `` (clickhouse, mysql)
+    Backtick,
+}
+
+impl IdentifierStyle {
+    fn quote_char(self) -> char {
+        match self {
+            IdentifierStyle::DoubleQuote => '"',
+            IdentifierStyle::Backtick => '`',
+        }
+    }
+}
+
+// Common reserved words of the supported dialects. Quoting a word which
+// isn't reserved does no harm.
+const RESERVED_WORDS: &[&str] = &[
+    "all", "and", "any", "array", "as", "asc", "between", "both", "by", "case",
+    "cast", "check", "collate", "column", "constraint", "create", "cross",
+    "current_date", "current_time", "current_timestamp", "current_user",
+    "default", "delete", "desc", "distinct", "do", "drop", "else", "end",
+    "except", "exists", "false", "fetch", "for", "foreign", "from", "full",
+    "grant", "group", "having", "in", "index", "inner", "insert", "intersect",
+    "interval", "into", "is", "join", "key", "leading", "left", "like",
+    "limit", "natural", "not", "null", "offset", "on", "only", "or", "order",
+    "outer", "primary", "references", "right", "schema", "select", "table",
+    "then", "to", "trailing", "true", "union", "unique", "update", "user",
+    "using", "values", "when", "where", "window", "with",
+];
+
+/// Quotes a table or column name for the dialect, if it needs it: names
+/// which aren't lowercase letters, digits and underscores (e.g. with spaces,
+/// dashes or uppercase letters), or which are reserved words. Names which
+/// are already quoted are left as is.
+///
+/// Every name is treated as a name; columns which are sql expressions must
+/// be declared as such, see `quote_column`.
+pub fn quote_identifier(name: &str, style: IdentifierStyle) -> String {
+    let quote = style.quote_char();
+
+    let is_plain = name.chars().next().map(|c| c.is_ascii_lowercase() || c == '_').unwrap_or(false)
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        && !RESERVED_WORDS.contains(&name);
+    let is_quoted = name.len() > 1 && name.starts_with(quote) && name.ends_with(quote);
+
+    if is_plain || is_quoted {
+        return name.to_owned();
+    }
+
+    let escaped = name.replace(quote, &format!("{}{}", quote, quote));
+    format!("{}{}{}", quote, escaped, quote)
+}
+
+/// A column as written in sql: expressions (declared in the schema, or
+/// generated like date levels) as is, names quoted if needed.
+pub fn quote_column(column: &str, expression: bool, style: IdentifierStyle) -> String {
+    if expression {
+        column.to_owned()
+    } else {
+        quote_identifier(column, style)
+    }
+}
+
+pub(crate) fn quote_in_place(name: &mut String, style: IdentifierStyle) {
+    *name = quote_identifier(name, style);
+}
+
+pub(crate) fn quote_table(table: &mut Table, style: IdentifierStyle) {
+    quote_in_place(&mut table.name, style);
+    if let Some(ref mut schema) = table.schema {
+        quote_in_place(schema, style);
+    }
+}
+
+pub(crate) fn quote_inline_table(inline_table: &mut InlineTable, style: IdentifierStyle) {
+    quote_in_place(&mut inline_table.alias, style);
+
+    for col_def in inline_table.column_definitions.iter_mut() {
+        quote_in_place(&mut col_def.name, style);
+    }
+    for row in inline_table.rows.iter_mut() {
+        for row_value in row.row_values.iter_mut() {
+            quote_in_place(&mut row_value.column, style);
+        }
+    }
+}
+
+/// Value of a bound parameter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SqlParam {
+    Int(i64),
+    Float(f64),
+    Text(String),
+}
+
+impl SqlParam {
+    /// Non-text members are bound as numbers if they are numbers, so that
+    /// the database compares them with the column's type.
+    pub fn from_member(member: &str, member_type: &MemberType) -> Self {
+        if let MemberType::NonText = member_type {
+            if let Ok(n) = member.parse::<i64>() {
+                return SqlParam::Int(n);
+            }
+            if let Ok(n) = member.parse::<f64>() {
+                if n.is_finite() {
+                    return SqlParam::Float(n);
+                }
+            }
+        }
+
+        SqlParam::Text(member.to_owned())
+    }
+}
+
+/// Sql with bound parameters. The placeholders in `sql` depend on the
+/// backend (`$1` for postgres, `?` for mysql); `params` are in the order of
+/// the placeholders.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundSql {
+    pub sql: String,
+    pub params: Vec<SqlParam>,
+}
+
+impl BoundSql {
+    /// Sql without parameters.
+    pub fn unbound(sql: String) -> Self {
+        BoundSql {
+            sql,
+            params: vec![],
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub enum MemberType {
     #[serde(rename="text")]
@@ -246,6 +726,35 @@ pub enum MemberType {
 pub struct MeasureSql {
     pub aggregator: Aggregator,
     pub column: String,
+    // the column is an sql expression, and isn't quoted
+    pub expression: bool,
+}
+
+impl MeasureSql {
+    pub fn quote_identifiers(&mut self, style: IdentifierStyle) {
+        if !self.expression {
+            quote_in_place(&mut self.column, style);
+        }
+
+        match self.aggregator {
+            Aggregator::WeightedSum { ref mut weight_column } |
+            Aggregator::WeightedAverage { ref mut weight_column } => {
+                quote_in_place(weight_column, style);
+            },
+            Aggregator::ReplicateWeightMoe { ref mut secondary_columns, .. } => {
+                for col in secondary_columns.iter_mut() {
+                    quote_in_place(col, style);
+                }
+            },
+            Aggregator::WeightedAverageMoe { ref mut primary_weight, ref mut secondary_weight_columns, .. } => {
+                quote_in_place(primary_weight, style);
+                for col in secondary_weight_columns.iter_mut() {
+                    quote_in_place(col, style);
+                }
+            },
+            _ => (),
+        }
+    }
 }
 
 // NOTE: This is now specific to each db, because of the custom aggregators
@@ -328,6 +837,7 @@ pub struct DimSubquery {
     pub sql: String,
     pub foreign_key: String,
     pub dim_cols: Option<String>,
+    pub order_cols: Vec<String>,
 }
 
 
@@ -335,7 +845,7 @@ pub struct DimSubquery {
 /// Collects a drilldown and cut together to create a subquery for the dimension table
 /// Does not check for matching name, because that had to have been done
 /// before submitting to this fn.
-pub fn dim_subquery(drill: Option<&DrilldownSql>, cut: Option<&CutSql>) -> DimSubquery {
+pub fn dim_subquery(drill: Option<&DrilldownSql>, cut: Option<&CutSql>, style: LiteralStyle) -> DimSubquery {
     match drill {
         Some(drill) => {
             let drill_table = match &drill.inline_table {
@@ -355,12 +865,28 @@ pub fn dim_subquery(drill: Option<&DrilldownSql>, cut: Option<&CutSql>) -> DimSu
             // Or will just making an alias for the primary key work?
             // Then don't add primary key here.
             // Also, make primary key optional?
-            let sql = format!("select {}, {} as {} from {}",
+            // Calculated members add extra rows to the dim table. The distinct
+            // is needed because the "dim table" may be the fact table.
+            let has_calculated_members = !drill.calculated_members.is_empty();
+
+            let order_cols = drill.order_col_alias_vec().iter()
+                .map(|col| format!(", {}", col))
+                .collect::<String>();
+
+            let mut sql = format!("select {}{}{}, {} as {} from {}",
+                if has_calculated_members { "distinct " } else { "" },
                 drill.col_alias_string(),
+                order_cols,
                 drill.primary_key.clone(),
                 drill.foreign_key.clone(),
                 drill_table,
             );
+
+            if has_calculated_members {
+                for cm_sql in drill.calculated_members_sql(&drill_table, style) {
+                    sql.push_str(&format!(" union all {}", cm_sql));
+                }
+            }
             // TODO can I delete this cut?
 //            if let Some(cut) = cut {
 //                sql.push_str(&format!(" where {} in ({})",
@@ -372,6 +898,7 @@ pub fn dim_subquery(drill: Option<&DrilldownSql>, cut: Option<&CutSql>) -> DimSu
                 sql,
                 foreign_key: drill.foreign_key.clone(),
                 dim_cols: Some(drill.col_alias_only_string()),
+                order_cols: drill.order_alias_only_vec(),
             };
         },
         // TODO remove this? This path should never be hit now.
@@ -382,13 +909,14 @@ pub fn dim_subquery(drill: Option<&DrilldownSql>, cut: Option<&CutSql>) -> DimSu
                     cut.foreign_key.clone(),
                     cut.table.full_name(),
                     cut.column.clone(),
-                    cut.members_string(),
+                    cut.members_sql(style),
                 );
 
                 return DimSubquery {
                     sql,
                     foreign_key: cut.foreign_key.clone(),
                     dim_cols: None,
+                    order_cols: vec![],
                 }
             }
         }
@@ -398,5 +926,6 @@ pub fn dim_subquery(drill: Option<&DrilldownSql>, cut: Option<&CutSql>) -> DimSu
         sql: "".to_owned(),
         foreign_key: "".to_owned(),
         dim_cols: None,
+        order_cols: vec![],
     }
 }
@@ -1,9 +1,12 @@
 use serde_derive::{Serialize, Deserialize};
+use serde_xml_rs::from_reader;
 use std::convert::From;
 use failure::{Error, format_err};
 
 pub mod aggregator;
+pub mod diff;
 pub mod metadata;
+pub mod mondrian;
 mod json;
 mod xml;
 
@@ -19,10 +22,15 @@ pub use crate::schema::{
     json::TableConfigJson,
     json::PropertyConfigJson,
     json::AnnotationConfigJson,
+    json::CaptionConfigJson,
     json::InlineTableJson,
     json::InlineTableColumnDefinitionJson,
     json::InlineTableRowJson,
     json::InlineTableRowValueJson,
+    json::CalculatedMemberConfigJson,
+    json::IncludeConfigJson,
+    json::CubeConfigJson,
+    json::SharedDimensionConfigJson,
     xml::SchemaConfigXML,
     xml::DimensionConfigXML,
     xml::HierarchyConfigXML,
@@ -32,7 +40,8 @@ pub use crate::schema::{
     xml::PropertyConfigXML,
 };
 use crate::names::{LevelName, Measure as MeasureName, Property as TsProperty};
-use crate::query_ir::MemberType;
+use crate::query::{Query, MeaOrCalc};
+use crate::query_ir::{IdentifierStyle, MemberType, quote_column, quote_identifier};
 pub use self::aggregator::Aggregator;
 use crate::DEFAULT_ALLOWED_ACCESS;
 
@@ -44,6 +53,45 @@ pub struct Schema {
     pub default_locale: String,
 }
 
+impl SchemaConfigJson {
+    /// Deserializes a JSON schema config.
+    pub fn from_json(raw_schema: &str) -> Result<Self, Error> {
+        Ok(serde_json::from_str::<SchemaConfigJson>(raw_schema)?)
+    }
+
+    /// Deserializes an XML schema config, going through JSON.
+    pub fn from_xml(raw_schema: &str) -> Result<Self, Error> {
+        let schema_config: SchemaConfigXML = match from_reader(raw_schema.as_bytes()) {
+            Ok(schema_config_xml) => schema_config_xml,
+            Err(err) => return Err(format_err!("Error reading XML schema: {}", err))
+        };
+
+        // Serialize XML to JSON as intermediary step
+        let serialized = serde_json::to_string(&schema_config)?;
+        SchemaConfigJson::from_json(&serialized)
+    }
+
+    /// Merges in the shared dimensions, cubes and annotations of another
+    /// schema config (e.g. an included file). Name and default locale are kept.
+    ///
+    /// No checks for collisions are done here.
+    pub fn merge(&mut self, other: SchemaConfigJson) {
+        if let Some(shared_dims) = other.shared_dimensions {
+            self.shared_dimensions.get_or_insert_with(|| vec![]).extend(shared_dims);
+        }
+
+        self.cubes.extend(other.cubes);
+
+        if let Some(anns) = other.annotations {
+            self.annotations.get_or_insert_with(|| vec![]).extend(anns);
+        }
+
+        if self.default_locale.is_none() {
+            self.default_locale = other.default_locale;
+        }
+    }
+}
+
 impl From<SchemaConfigJson> for Schema {
     fn from(schema_config: SchemaConfigJson) -> Self {
         // TODO
@@ -68,6 +116,12 @@ impl From<SchemaConfigJson> for Schema {
                         .map(|ann| ann.into())
                         .collect()
                 });
+            let cube_captions = cube_config.captions
+                .map(|caps| {
+                    caps.into_iter()
+                        .map(|cap| cap.into())
+                        .collect()
+                });
 
             // special case: check for dimension_usages
             //
@@ -108,15 +162,29 @@ impl From<SchemaConfigJson> for Schema {
                                         })
                                     });
 
+                                // captions of the usage replace the shared dim's, since
+                                // the usage can rename the dimension
+                                let dim_captions: Option<Vec<Caption>> = dim_usage.captions.as_ref()
+                                    .or(shared_dim_config.captions.as_ref())
+                                    .map(|caps| {
+                                        caps.iter()
+                                            .map(|cap| cap.clone().into())
+                                            .collect()
+                                    });
+
                                 let dim_type = shared_dim_config.dim_type.clone().unwrap_or(DimensionType::default());
+                                let min_auth_level = shared_dim_config.min_auth_level.unwrap_or(DEFAULT_ALLOWED_ACCESS);
 
                                 dimensions.push(Dimension {
                                     name: dim_name.clone(),
                                     foreign_key: Some(dim_usage.foreign_key.clone()),
+                                    foreign_key_expression: is_true(&dim_usage.foreign_key_expression),
+                                    min_auth_level,
                                     hierarchies,
                                     default_hierarchy: shared_dim_config.default_hierarchy.clone(),
                                     dim_type,
                                     annotations: dim_annotations,
+                                    captions: dim_captions,
                                     is_shared: true
                                 });
                             }
@@ -142,6 +210,7 @@ impl From<SchemaConfigJson> for Schema {
                 dimensions,
                 measures,
                 annotations: cube_annotations,
+                captions: cube_captions,
             });
         }
 
@@ -173,6 +242,7 @@ pub struct Cube {
     pub dimensions: Vec<Dimension>,
     pub measures: Vec<Measure>,
     pub annotations: Option<Vec<Annotation>>,
+    pub captions: Option<Vec<Caption>>,
 }
 
 impl Cube {
@@ -337,6 +407,102 @@ impl Cube {
         }
         None
     }
+
+    /// Dimension, hierarchy and level of `level_name` which need a higher
+    /// auth level than the cube, described as e.g. `Level "Geo.Geo.City"`,
+    /// with the auth level they need. Parents come first.
+    pub fn level_auth_levels(&self, level_name: &LevelName) -> Vec<(String, i32)> {
+        let mut auth_levels = vec![];
+
+        let dimension = match self.dimensions.iter().find(|d| d.name == level_name.dimension) {
+            Some(dimension) => dimension,
+            None => return auth_levels,
+        };
+        auth_levels.push((format!("Dimension \"{}\"", dimension.name), dimension.min_auth_level));
+
+        if let Some(hierarchy) = dimension.hierarchies.iter().find(|h| h.name == level_name.hierarchy) {
+            auth_levels.push((format!("Hierarchy \"{}.{}\"", dimension.name, hierarchy.name), hierarchy.min_auth_level));
+
+            if let Some(level) = hierarchy.levels.iter().find(|l| l.name == level_name.level) {
+                auth_levels.push((format!("Level \"{}.{}.{}\"", dimension.name, hierarchy.name, level.name), level.min_auth_level));
+            }
+        }
+
+        auth_levels.retain(|(_, min_auth_level)| *min_auth_level > self.min_auth_level);
+        auth_levels
+    }
+
+    /// Elements used by `query` which need a higher auth level than the
+    /// cube, as in `level_auth_levels`. Elements which aren't in the cube are
+    /// left to query validation.
+    pub fn query_auth_levels(&self, query: &Query) -> Vec<(String, i32)> {
+        let mut level_names: Vec<&LevelName> = vec![];
+        level_names.extend(query.drilldowns.iter().map(|d| &d.0));
+        level_names.extend(query.cuts.iter().map(|c| &c.level_name));
+        level_names.extend(query.properties.iter().map(|p| &p.level_name));
+        level_names.extend(query.captions.iter().map(|p| &p.level_name));
+        if let Some(ref top) = query.top {
+            level_names.push(&top.by_dimension);
+        }
+        if let Some(ref rca) = query.rca {
+            level_names.push(&rca.drill_1.0);
+            level_names.push(&rca.drill_2.0);
+        }
+        if let Some(ref growth) = query.growth {
+            level_names.push(&growth.time_drill.0);
+        }
+        if let Some(ref rate) = query.rate {
+            level_names.push(&rate.level_name);
+        }
+
+        let mut measures: Vec<&MeasureName> = query.measures.iter().collect();
+        let mut mea_or_calcs: Vec<&MeaOrCalc> = query.filters.iter().map(|f| &f.by_mea_or_calc).collect();
+        if let Some(ref top) = query.top {
+            mea_or_calcs.extend(top.sort_mea_or_calc.iter());
+        }
+        if let Some(ref top_where) = query.top_where {
+            mea_or_calcs.push(&top_where.by_mea_or_calc);
+        }
+        if let Some(ref sort) = query.sort {
+            mea_or_calcs.push(&sort.measure);
+        }
+        for mea_or_calc in mea_or_calcs {
+            if let MeaOrCalc::Mea(measure) = mea_or_calc {
+                measures.push(measure);
+            }
+        }
+        if let Some(ref rca) = query.rca {
+            measures.push(&rca.mea);
+        }
+        if let Some(ref growth) = query.growth {
+            measures.push(&growth.mea);
+        }
+
+        let mut auth_levels: Vec<(String, i32)> = vec![];
+
+        for level_name in level_names {
+            for auth_level in self.level_auth_levels(level_name) {
+                if !auth_levels.contains(&auth_level) {
+                    auth_levels.push(auth_level);
+                }
+            }
+        }
+
+        for measure_name in measures {
+            let measure = self.measures.iter()
+                .find(|m| m.name == measure_name.0)
+                .filter(|m| m.min_auth_level > self.min_auth_level);
+
+            if let Some(measure) = measure {
+                let auth_level = (format!("Measure \"{}\"", measure.name), measure.min_auth_level);
+                if !auth_levels.contains(&auth_level) {
+                    auth_levels.push(auth_level);
+                }
+            }
+        }
+
+        auth_levels
+    }
 }
 
 
@@ -344,34 +510,73 @@ impl Cube {
 pub struct Dimension {
     pub name: String,
     pub foreign_key: Option<String>,
+    /// The foreign key is an sql expression on the fact table, which is
+    /// written as is instead of being quoted
+    #[serde(default)]
+    pub foreign_key_expression: bool,
+    pub min_auth_level: i32,
     pub hierarchies: Vec<Hierarchy>,
     pub default_hierarchy: Option<String>,
     pub dim_type: DimensionType,
     pub annotations: Option<Vec<Annotation>>,
+    pub captions: Option<Vec<Caption>>,
     pub is_shared: bool,
 }
 
 impl From<DimensionConfigJson> for Dimension {
     fn from(dimension_config: DimensionConfigJson) -> Self {
-        let hierarchies = dimension_config.hierarchies.into_iter()
-            .map(|h| h.into())
-            .collect();
         let annotations = dimension_config.annotations
             .map(|anns| {
                 anns.into_iter()
                     .map(|ann| ann.into())
                     .collect()
             });
+        let captions = dimension_config.captions
+            .map(|caps| {
+                caps.into_iter()
+                    .map(|cap| cap.into())
+                    .collect()
+            });
+
+        let min_auth_level = dimension_config.min_auth_level.unwrap_or(DEFAULT_ALLOWED_ACCESS);
+        let foreign_key_expression = is_true(&dimension_config.foreign_key_expression);
+
+        if let Some(date_column) = dimension_config.date_column {
+            // Time dimension generated from a date column in the fact table.
+            let date_levels = dimension_config.date_levels
+                .unwrap_or_else(|| DatePart::all());
+            let hierarchy = Hierarchy::from_date_column(&dimension_config.name, &date_column, &date_levels);
+
+            return Dimension {
+                name: dimension_config.name,
+                foreign_key: dimension_config.foreign_key.or(Some(date_column)),
+                foreign_key_expression,
+                min_auth_level,
+                default_hierarchy: None,
+                hierarchies: vec![hierarchy],
+                dim_type: dimension_config.dim_type.unwrap_or(DimensionType::Time),
+                annotations,
+                captions,
+                is_shared: false
+            };
+        }
+
+        let hierarchies = dimension_config.hierarchies.into_iter()
+            .map(|h| h.into())
+            .collect();
 
         let dim_type = dimension_config.dim_type.unwrap_or(DimensionType::default());
 
         Dimension {
             name: dimension_config.name,
             foreign_key: dimension_config.foreign_key,
+            foreign_key_expression,
+            min_auth_level,
             default_hierarchy: dimension_config.default_hierarchy,
             hierarchies,
             dim_type,
             annotations,
+            captions,
             is_shared: false
         }
     }
@@ -395,14 +600,120 @@ impl std::default::Default for DimensionType {
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Hierarchy {
     pub name: String,
+    pub min_auth_level: i32,
     pub table: Option<Table>,
     pub primary_key: String,
     pub levels: Vec<Level>,
     pub annotations: Option<Vec<Annotation>>,
+    pub captions: Option<Vec<Caption>>,
     pub inline_table: Option<InlineTable>,
     pub default_member: Option<String>,
 }
 
+impl Hierarchy {
+    /// Generates a hierarchy on the fact table, with a level for each date part.
+    ///
+    /// Level key columns are standard sql expressions on the date column; they
+    /// can be swapped for backend specific expressions with
+    /// `Schema::resolve_date_levels`. Level names are the ones the logic layer
+    /// expects for time cuts (Year, Quarter, Month, Day).
+    pub fn from_date_column(name: &str, date_column: &str, date_parts: &[DatePart]) -> Self {
+        let mut date_parts = date_parts.to_vec();
+        date_parts.sort();
+        date_parts.dedup();
+
+        let levels = date_parts.into_iter()
+            .map(|part| {
+                let date_level = DateLevel {
+                    part,
+                    date_column: date_column.to_owned(),
+                };
+
+                Level {
+                    name: date_level.part.level_name().to_owned(),
+                    key_column: date_level.standard_sql(IdentifierStyle::DoubleQuote),
+                    name_column: None,
+                    order_column: None,
+                    expression: true,
+                    name_expression: false,
+                    order_expression: false,
+                    skip_members_cache: false,
+                    properties: None,
+                    key_type: Some(MemberType::NonText),
+                    min_auth_level: DEFAULT_ALLOWED_ACCESS,
+                    annotations: None,
+                    captions: None,
+                    calculated_members: None,
+                    date_level: Some(date_level),
+                }
+            })
+            .collect();
+
+        Hierarchy {
+            name: name.to_owned(),
+            min_auth_level: DEFAULT_ALLOWED_ACCESS,
+            table: None,
+            primary_key: date_column.to_owned(),
+            levels,
+            annotations: None,
+            captions: None,
+            inline_table: None,
+            default_member: None,
+        }
+    }
+}
+
+/// Parts of a date that a level can be generated from.
+///
+/// Members are integers which sort in time order: years are `yyyy`, quarters
+/// `yyyyq`, months `yyyymm` and days `yyyymmdd`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum DatePart {
+    #[serde(rename="year")]
+    Year,
+    #[serde(rename="quarter")]
+    Quarter,
+    #[serde(rename="month")]
+    Month,
+    #[serde(rename="day")]
+    Day,
+}
+
+impl DatePart {
+    pub fn all() -> Vec<DatePart> {
+        vec![DatePart::Year, DatePart::Quarter, DatePart::Month, DatePart::Day]
+    }
+
+    pub fn level_name(&self) -> &'static str {
+        match self {
+            DatePart::Year => "Year",
+            DatePart::Quarter => "Quarter",
+            DatePart::Month => "Month",
+            DatePart::Day => "Day",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DateLevel {
+    pub part: DatePart,
+    pub date_column: String,
+}
+
+impl DateLevel {
+    /// Standard sql expression for the level's members
+    pub fn standard_sql(&self, style: IdentifierStyle) -> String {
+        let col = &quote_identifier(&self.date_column, style);
+
+        match self.part {
+            DatePart::Year => format!("EXTRACT(YEAR FROM {})", col),
+            DatePart::Quarter => format!("EXTRACT(YEAR FROM {col}) * 10 + EXTRACT(QUARTER FROM {col})", col=col),
+            DatePart::Month => format!("EXTRACT(YEAR FROM {col}) * 100 + EXTRACT(MONTH FROM {col})", col=col),
+            DatePart::Day => format!("EXTRACT(YEAR FROM {col}) * 10000 + EXTRACT(MONTH FROM {col}) * 100 + EXTRACT(DAY FROM {col})", col=col),
+        }
+    }
+}
+
 impl From<HierarchyConfigJson> for Hierarchy {
     fn from(hierarchy_config: HierarchyConfigJson) -> Self {
         let levels: Vec<Level> = hierarchy_config.levels.into_iter()
@@ -415,6 +726,12 @@ impl From<HierarchyConfigJson> for Hierarchy {
                     .map(|ann| ann.into())
                     .collect()
             });
+        let captions = hierarchy_config.captions
+            .map(|caps| {
+                caps.into_iter()
+                    .map(|cap| cap.into())
+                    .collect()
+            });
 
         let primary_key = hierarchy_config.primary_key
             .unwrap_or_else(|| {
@@ -427,10 +744,12 @@ impl From<HierarchyConfigJson> for Hierarchy {
 
         Hierarchy {
             name: hierarchy_config.name,
+            min_auth_level: hierarchy_config.min_auth_level.unwrap_or(DEFAULT_ALLOWED_ACCESS),
             table: hierarchy_config.table.map(|t| t.into()),
             primary_key,
             levels,
             annotations,
+            captions,
             inline_table: hierarchy_config.inline_table.map(|t| t.into()),
             default_member: hierarchy_config.default_member
         }
@@ -557,12 +876,53 @@ pub struct Level {
     pub name: String,
     pub key_column: String,
     pub name_column: Option<String>,
+    /// Column used to sort members, instead of the key or name column
+    pub order_column: Option<String>,
+    /// The key column is an sql expression, which is written as is instead
+    /// of being quoted
+    #[serde(default)]
+    pub expression: bool,
+    /// Same as `expression`, for the name column
+    #[serde(default)]
+    pub name_expression: bool,
+    /// Same as `expression`, for the order column
+    #[serde(default)]
+    pub order_expression: bool,
+    /// Members aren't cached, and cut members on this level aren't
+    /// validated, e.g. for levels with too many members to cache.
+    #[serde(default)]
+    pub skip_members_cache: bool,
     pub properties: Option<Vec<Property>>,
     pub key_type: Option<MemberType>,
+    pub min_auth_level: i32,
     pub annotations: Option<Vec<Annotation>>,
+    pub captions: Option<Vec<Caption>>,
+    pub calculated_members: Option<Vec<CalculatedMember>>,
+    /// Set for levels generated from a date column; the key column is then
+    /// an sql expression on the date column.
+    pub date_level: Option<DateLevel>,
 }
 
 impl Level {
+    /// Key column as written in sql: quoted if needed, unless it's an
+    /// expression.
+    pub fn key_column_sql(&self, style: IdentifierStyle) -> String {
+        quote_column(&self.key_column, self.expression, style)
+    }
+
+    pub fn name_column_sql(&self, style: IdentifierStyle) -> Option<String> {
+        self.name_column.as_ref().map(|col| quote_column(col, self.name_expression, style))
+    }
+
+    pub fn order_column_sql(&self, style: IdentifierStyle) -> Option<String> {
+        self.order_column.as_ref().map(|col| quote_column(col, self.order_expression, style))
+    }
+
+    pub fn get_calculated_member(&self, key: &str) -> Option<&CalculatedMember> {
+        self.calculated_members.as_ref()
+            .and_then(|cms| cms.iter().find(|cm| cm.key == key))
+    }
+
     pub fn get_captions(&self, level_name: &LevelName, locales: &Vec<String>) -> Vec<TsProperty> {
         let mut captions: Vec<TsProperty> = vec![];
 
@@ -603,14 +963,65 @@ impl From<LevelConfigJson> for Level {
                     .map(|ann| ann.into())
                     .collect()
             });
+        let captions = level_config.captions
+            .map(|caps| {
+                caps.into_iter()
+                    .map(|cap| cap.into())
+                    .collect()
+            });
+        let calculated_members = level_config.calculated_members
+            .map(|cms| {
+                cms.into_iter()
+                    .map(|cm| cm.into())
+                    .collect()
+            });
 
         Level {
             name: level_config.name,
             key_column: level_config.key_column,
             name_column: level_config.name_column,
+            order_column: level_config.order_column,
+            expression: is_true(&level_config.expression),
+            name_expression: is_true(&level_config.name_expression),
+            order_expression: is_true(&level_config.order_expression),
+            skip_members_cache: is_true(&level_config.skip_members_cache),
             properties,
             key_type: level_config.key_type,
+            min_auth_level: level_config.min_auth_level.unwrap_or(DEFAULT_ALLOWED_ACCESS),
             annotations,
+            captions,
+            calculated_members,
+            date_level: None,
+        }
+    }
+}
+
+/// A member of a level which is defined in the schema instead of the
+/// database, as the aggregate of a set of members of the same level
+/// (e.g. "European Union" as an aggregate of its member countries).
+///
+/// Since the value is computed by rolling up the child members, it is
+/// only valid for additive aggregators (sum and count).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CalculatedMember {
+    pub key: String,
+    pub name: Option<String>,
+    pub members: Vec<String>,
+}
+
+impl CalculatedMember {
+    /// Falls back to the key if there's no name set
+    pub fn caption(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.key.clone())
+    }
+}
+
+impl From<CalculatedMemberConfigJson> for CalculatedMember {
+    fn from(calculated_member_config: CalculatedMemberConfigJson) -> Self {
+        CalculatedMember {
+            key: calculated_member_config.key,
+            name: calculated_member_config.name,
+            members: calculated_member_config.members,
         }
     }
 }
@@ -620,9 +1031,15 @@ impl From<LevelConfigJson> for Level {
 pub struct Measure{
     pub name: String,
     pub column: String,
+    /// The column is an sql expression, which is written as is instead of
+    /// being quoted
+    #[serde(default)]
+    pub expression: bool,
     pub aggregator: Aggregator,
     pub measure_type: MeasureType,
+    pub min_auth_level: i32,
     pub annotations: Option<Vec<Annotation>>,
+    pub captions: Option<Vec<Caption>>,
 }
 
 impl From<MeasureConfigJson> for Measure {
@@ -633,13 +1050,22 @@ impl From<MeasureConfigJson> for Measure {
                     .map(|ann| ann.into())
                     .collect()
             });
+        let captions = measure_config.captions
+            .map(|caps| {
+                caps.into_iter()
+                    .map(|cap| cap.into())
+                    .collect()
+            });
 
         Measure {
             name: measure_config.name,
             column: measure_config.column,
+            expression: is_true(&measure_config.expression),
             aggregator: measure_config.aggregator,
             measure_type: measure_config.measure_type.unwrap_or_else(|| MeasureType::default()),
+            min_auth_level: measure_config.min_auth_level.unwrap_or(DEFAULT_ALLOWED_ACCESS),
             annotations,
+            captions,
         }
     }
 }
@@ -697,10 +1123,21 @@ impl Table {
 pub struct Property {
     pub name: String,
     pub column: String,
+    /// The column is an sql expression, which is written as is instead of
+    /// being quoted
+    #[serde(default)]
+    pub expression: bool,
     pub caption_set: Option<String>,
     pub annotations: Option<Vec<Annotation>>,
 }
 
+impl Property {
+    /// Column as written in sql: quoted if needed, unless it's an expression.
+    pub fn column_sql(&self, style: IdentifierStyle) -> String {
+        quote_column(&self.column, self.expression, style)
+    }
+}
+
 impl From<PropertyConfigJson> for Property {
     fn from(property_config: PropertyConfigJson) -> Self {
         let annotations = property_config.annotations
@@ -713,6 +1150,7 @@ impl From<PropertyConfigJson> for Property {
         Property {
             name: property_config.name,
             column: property_config.column,
+            expression: is_true(&property_config.expression),
             caption_set: property_config.caption_set,
             annotations,
         }
@@ -734,6 +1172,43 @@ impl From<AnnotationConfigJson> for Annotation {
     }
 }
 
+/// Name of a cube, dimension, hierarchy, level or measure in a given locale.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Caption {
+    pub locale: String,
+    pub text: String,
+}
+
+impl Caption {
+    /// Picks the caption for the first of `locales` that has one, falling
+    /// back to `name`.
+    pub fn localize(captions: &Option<Vec<Caption>>, name: &str, locales: &[&str]) -> String {
+        if let Some(captions) = captions {
+            for locale in locales {
+                if let Some(caption) = captions.iter().find(|c| &c.locale == locale) {
+                    return caption.text.clone();
+                }
+            }
+        }
+
+        name.to_owned()
+    }
+}
+
+impl From<CaptionConfigJson> for Caption {
+    fn from(caption_config: CaptionConfigJson) -> Self {
+        Caption {
+            locale: caption_config.locale,
+            text: caption_config.text,
+        }
+    }
+}
+
+/// Flags in schema configs are strings; only "true" sets them.
+fn is_true(flag: &Option<String>) -> bool {
+    flag.as_ref().map(|f| f == "true").unwrap_or(false)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -755,9 +1230,11 @@ mod test {
             shared_dimensions: Some(vec![
                 SharedDimensionConfigJson {
                     name: "geo".into(),
+                    min_auth_level: None,
                     hierarchies: vec![
                         HierarchyConfigJson {
                             name: "geo".into(),
+                            min_auth_level: None,
                             table: Some(TableConfigJson {
                                 name: "geo_table".into(),
                                 schema: None,
@@ -769,18 +1246,28 @@ mod test {
                                     name: "tract".into(),
                                     key_column: "geoid".into(),
                                     name_column: None,
+                                    order_column: None,
+                                    expression: None,
+                                    name_expression: None,
+                                    order_expression: None,
+                                    skip_members_cache: None,
                                     properties: None,
                                     key_type: None,
+                                    min_auth_level: None,
                                     annotations: None,
+                                    captions: None,
+                                    calculated_members: None,
                                 },
                             ],
                             annotations: None,
+                            captions: None,
                             inline_table: None,
                             default_member: None,
                         },
                     ],
                     default_hierarchy: None,
                     annotations: None,
+                    captions: None,
                     dim_type: None,
                 }
             ]),
@@ -800,14 +1287,18 @@ mod test {
                             source: "geo".into(),
                             name: Some("geo".into()),
                             foreign_key: "fact_geoid".into(),
+                            foreign_key_expression: None,
                             annotations: None,
+                            captions: None,
                         }
                     ]),
                     measures: vec![],
                     annotations: None,
+                    captions: None,
                 }
             ],
             annotations: None,
+            includes: None,
         };
 
         let schema: Schema = schema_config.into();
@@ -0,0 +1,281 @@
+//! Differences between two versions of a schema, to detect changes which
+//! break existing queries.
+//!
+//! Cubes, dimensions, hierarchies, levels, properties and measures are
+//! matched by name within their parent. Breaking changes are removed names,
+//! and changed measure aggregators or level key types; everything else
+//! (additions, and changes to columns, tables, annotations...) keeps
+//! existing queries working.
+
+use std::fmt;
+
+use crate::query_ir::MemberType;
+use super::{Schema, Cube, Dimension, Hierarchy, Level, Measure, Property};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChangeKind::Added => write!(f, "added"),
+            ChangeKind::Removed => write!(f, "removed"),
+            ChangeKind::Changed => write!(f, "changed"),
+        }
+    }
+}
+
+/// A change to one item of the schema.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaChange {
+    pub kind: ChangeKind,
+    /// e.g. `Cube "sales", Dimension "Geo", Hierarchy "Geo", Level "Country"`
+    pub path: String,
+    /// What changed, for `Changed`
+    pub detail: Option<String>,
+    pub breaking: bool,
+}
+
+impl fmt::Display for SchemaChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.kind, self.path)?;
+
+        if let Some(ref detail) = self.detail {
+            write!(f, ": {}", detail)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaDiff {
+    pub changes: Vec<SchemaChange>,
+}
+
+impl SchemaDiff {
+    /// Diff from the `old` schema to the `new` one.
+    pub fn new(old: &Schema, new: &Schema) -> Self {
+        let mut diff = SchemaDiff { changes: vec![] };
+
+        diff.items("", "Cube", &old.cubes, &new.cubes, |c| &c.name, SchemaDiff::cube);
+
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn breaking(&self) -> impl Iterator<Item=&SchemaChange> {
+        self.changes.iter().filter(|change| change.breaking)
+    }
+
+    pub fn non_breaking(&self) -> impl Iterator<Item=&SchemaChange> {
+        self.changes.iter().filter(|change| !change.breaking)
+    }
+
+    pub fn has_breaking(&self) -> bool {
+        self.breaking().next().is_some()
+    }
+
+    fn push(&mut self, kind: ChangeKind, path: String, detail: Option<String>, breaking: bool) {
+        self.changes.push(SchemaChange { kind, path, detail, breaking });
+    }
+
+    /// Matches items by name: removed items break queries, added items
+    /// don't, and items in both are compared by `compare`.
+    fn items<T, N, C>(
+        &mut self,
+        parent: &str,
+        item_type: &str,
+        old: &[T],
+        new: &[T],
+        name: N,
+        compare: C,
+    )
+        where N: Fn(&T) -> &String,
+              C: Fn(&mut SchemaDiff, &str, &T, &T),
+    {
+        let path = |item: &T| {
+            if parent.is_empty() {
+                format!("{} \"{}\"", item_type, name(item))
+            } else {
+                format!("{}, {} \"{}\"", parent, item_type, name(item))
+            }
+        };
+
+        for old_item in old {
+            match new.iter().find(|new_item| name(new_item) == name(old_item)) {
+                Some(new_item) => compare(self, &path(old_item), old_item, new_item),
+                None => self.push(ChangeKind::Removed, path(old_item), None, true),
+            }
+        }
+
+        for new_item in new {
+            if !old.iter().any(|old_item| name(old_item) == name(new_item)) {
+                self.push(ChangeKind::Added, path(new_item), None, false);
+            }
+        }
+    }
+
+    /// Records a change if `old` and `new` differ.
+    fn value<T: PartialEq>(&mut self, path: &str, field: &str, old: &T, new: &T, show: fn(&T) -> String, breaking: bool) {
+        if old != new {
+            let detail = format!("{} {} -> {}", field, show(old), show(new));
+            self.push(ChangeKind::Changed, path.to_owned(), Some(detail), breaking);
+        }
+    }
+
+    fn cube(&mut self, path: &str, old: &Cube, new: &Cube) {
+        self.value(path, "table", &old.table.full_name(), &new.table.full_name(), |s| s.clone(), false);
+        self.value(path, "public", &old.public, &new.public, |b| b.to_string(), false);
+        self.value(path, "min_auth_level", &old.min_auth_level, &new.min_auth_level, |l| l.to_string(), false);
+
+        self.items(path, "Dimension", &old.dimensions, &new.dimensions, |d| &d.name, SchemaDiff::dimension);
+        self.items(path, "Measure", &old.measures, &new.measures, |m| &m.name, SchemaDiff::measure);
+    }
+
+    fn dimension(&mut self, path: &str, old: &Dimension, new: &Dimension) {
+        self.value(path, "foreign_key", &old.foreign_key, &new.foreign_key, show_option, false);
+        self.value(path, "default_hierarchy", &old.default_hierarchy, &new.default_hierarchy, show_option, false);
+        self.value(path, "min_auth_level", &old.min_auth_level, &new.min_auth_level, |l| l.to_string(), false);
+
+        self.items(path, "Hierarchy", &old.hierarchies, &new.hierarchies, |h| &h.name, SchemaDiff::hierarchy);
+    }
+
+    fn hierarchy(&mut self, path: &str, old: &Hierarchy, new: &Hierarchy) {
+        let old_table = old.table.as_ref().map(|t| t.full_name());
+        let new_table = new.table.as_ref().map(|t| t.full_name());
+        self.value(path, "table", &old_table, &new_table, show_option, false);
+        self.value(path, "primary_key", &old.primary_key, &new.primary_key, |s| s.clone(), false);
+        self.value(path, "min_auth_level", &old.min_auth_level, &new.min_auth_level, |l| l.to_string(), false);
+
+        self.items(path, "Level", &old.levels, &new.levels, |l| &l.name, SchemaDiff::level);
+    }
+
+    fn level(&mut self, path: &str, old: &Level, new: &Level) {
+        self.value(path, "key_type", &old.key_type, &new.key_type, show_key_type, true);
+        self.value(path, "key_column", &old.key_column, &new.key_column, |s| s.clone(), false);
+        self.value(path, "name_column", &old.name_column, &new.name_column, show_option, false);
+        self.value(path, "min_auth_level", &old.min_auth_level, &new.min_auth_level, |l| l.to_string(), false);
+
+        let no_properties = vec![];
+        let old_properties = old.properties.as_ref().unwrap_or(&no_properties);
+        let new_properties = new.properties.as_ref().unwrap_or(&no_properties);
+
+        self.items(path, "Property", old_properties, new_properties, |p| &p.name, SchemaDiff::property);
+    }
+
+    fn property(&mut self, path: &str, old: &Property, new: &Property) {
+        self.value(path, "column", &old.column, &new.column, |s| s.clone(), false);
+    }
+
+    fn measure(&mut self, path: &str, old: &Measure, new: &Measure) {
+        self.value(path, "aggregator", &old.aggregator, &new.aggregator, |a| format!("{:?}", a), true);
+        self.value(path, "column", &old.column, &new.column, |s| s.clone(), false);
+        self.value(path, "measure_type", &old.measure_type, &new.measure_type, |t| format!("{:?}", t), false);
+        self.value(path, "min_auth_level", &old.min_auth_level, &new.min_auth_level, |l| l.to_string(), false);
+    }
+}
+
+impl fmt::Display for SchemaDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No changes");
+        }
+
+        if self.has_breaking() {
+            writeln!(f, "Breaking changes:")?;
+            for change in self.breaking() {
+                writeln!(f, "  {}", change)?;
+            }
+        }
+
+        if self.non_breaking().next().is_some() {
+            writeln!(f, "Other changes:")?;
+            for change in self.non_breaking() {
+                writeln!(f, "  {}", change)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn show_option(value: &Option<String>) -> String {
+    value.clone().unwrap_or_else(|| "none".to_owned())
+}
+
+fn show_key_type(key_type: &Option<MemberType>) -> String {
+    match key_type {
+        Some(MemberType::Text) => "text".to_owned(),
+        Some(MemberType::NonText) => "nontext".to_owned(),
+        None => "none".to_owned(),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn schema(cube: &str) -> Schema {
+        Schema::from_xml(&format!(r##"<Schema name="test">{}</Schema>"##, cube)).unwrap()
+    }
+
+    #[test]
+    fn test_schema_diff() {
+        let old = schema(r##"
+            <Cube name="sales">
+                <Table name="sales" />
+                <Dimension name="Geo" foreign_key="geo_id">
+                    <Hierarchy name="Geo">
+                        <Table name="dim_geo" />
+                        <Level name="Country" key_column="country_id" key_type="nontext" />
+                        <Level name="City" key_column="city_id" />
+                    </Hierarchy>
+                </Dimension>
+                <Measure name="Quantity" column="quantity" aggregator="sum" />
+                <Measure name="Price" column="price" aggregator="avg" />
+            </Cube>
+        "##);
+        let new = schema(r##"
+            <Cube name="sales">
+                <Table name="sales" />
+                <Dimension name="Geo" foreign_key="geo_id">
+                    <Hierarchy name="Geo">
+                        <Table name="dim_geo" />
+                        <Level name="Country" key_column="country_code" key_type="text" />
+                        <Level name="Town" key_column="city_id" />
+                    </Hierarchy>
+                </Dimension>
+                <Measure name="Quantity" column="quantity" aggregator="avg" />
+                <Measure name="Price" column="price" aggregator="avg" />
+                <Measure name="Discount" column="discount" aggregator="sum" />
+            </Cube>
+        "##);
+
+        let diff = SchemaDiff::new(&old, &new);
+
+        let breaking: Vec<_> = diff.breaking().map(|c| c.to_string()).collect();
+        assert_eq!(breaking, vec![
+            r#"changed: Cube "sales", Dimension "Geo", Hierarchy "Geo", Level "Country": key_type nontext -> text"#,
+            r#"removed: Cube "sales", Dimension "Geo", Hierarchy "Geo", Level "City""#,
+            r#"changed: Cube "sales", Measure "Quantity": aggregator Sum -> Average"#,
+        ]);
+
+        let non_breaking: Vec<_> = diff.non_breaking().map(|c| c.to_string()).collect();
+        assert_eq!(non_breaking, vec![
+            r#"changed: Cube "sales", Dimension "Geo", Hierarchy "Geo", Level "Country": key_column country_id -> country_code"#,
+            r#"added: Cube "sales", Dimension "Geo", Hierarchy "Geo", Level "Town""#,
+            r#"added: Cube "sales", Measure "Discount""#,
+        ]);
+
+        assert!(SchemaDiff::new(&old, &old).is_empty());
+    }
+}
@@ -2,16 +2,25 @@ use serde_derive::Deserialize;
 
 use crate::query_ir::MemberType;
 use super::aggregator::Aggregator;
-use super::{DimensionType, MeasureType};
+use super::{DatePart, DimensionType, MeasureType};
 
 
 #[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct SchemaConfigJson {
     pub name: String,
     pub shared_dimensions: Option<Vec<SharedDimensionConfigJson>>,
+    #[serde(default)]
     pub cubes: Vec<CubeConfigJson>,
     pub annotations: Option<Vec<AnnotationConfigJson>>,
     pub default_locale: Option<String>,
+    // other schema files to merge in; resolved when reading schema files,
+    // not when converting into a Schema
+    pub includes: Option<Vec<IncludeConfigJson>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct IncludeConfigJson {
+    pub path: String,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize)]
@@ -24,27 +33,38 @@ pub struct CubeConfigJson {
     pub dimension_usages: Option<Vec<DimensionUsageJson>>,
     pub measures: Vec<MeasureConfigJson>,
     pub annotations: Option<Vec<AnnotationConfigJson>>,
+    pub captions: Option<Vec<CaptionConfigJson>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct DimensionConfigJson {
     pub name: String,
     pub foreign_key: Option<String>, // does not exist for shared dims
+    /// "true" if the foreign key is an sql expression
+    pub foreign_key_expression: Option<String>,
+    pub min_auth_level: Option<i32>,
+    #[serde(default)]
     pub hierarchies: Vec<HierarchyConfigJson>,
     pub default_hierarchy: Option<String>,
     #[serde(rename="type")]
     pub dim_type: Option<DimensionType>,
     pub annotations: Option<Vec<AnnotationConfigJson>>,
+    pub captions: Option<Vec<CaptionConfigJson>>,
+    // generates a time hierarchy from a date column in the fact table
+    pub date_column: Option<String>,
+    pub date_levels: Option<Vec<DatePart>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct SharedDimensionConfigJson {
     pub name: String,
+    pub min_auth_level: Option<i32>,
     pub hierarchies: Vec<HierarchyConfigJson>,
     pub default_hierarchy: Option<String>,
     #[serde(rename="type")]
     pub dim_type: Option<DimensionType>,
     pub annotations: Option<Vec<AnnotationConfigJson>>,
+    pub captions: Option<Vec<CaptionConfigJson>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize)]
@@ -52,16 +72,21 @@ pub struct DimensionUsageJson {
     pub source: String,
     pub name: Option<String>,
     pub foreign_key: String,
+    /// "true" if the foreign key is an sql expression
+    pub foreign_key_expression: Option<String>,
     pub annotations: Option<Vec<AnnotationConfigJson>>,
+    pub captions: Option<Vec<CaptionConfigJson>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct HierarchyConfigJson {
     pub name: String,
+    pub min_auth_level: Option<i32>,
     pub table: Option<TableConfigJson>,
     pub primary_key: Option<String>,
     pub levels: Vec<LevelConfigJson>,
     pub annotations: Option<Vec<AnnotationConfigJson>>,
+    pub captions: Option<Vec<CaptionConfigJson>>,
     pub inline_table: Option<InlineTableJson>,
     pub default_member: Option<String>,
 }
@@ -97,19 +122,43 @@ pub struct LevelConfigJson {
     pub name: String,
     pub key_column: String,
     pub name_column: Option<String>,
+    pub order_column: Option<String>,
+    /// "true" if the key column is an sql expression
+    pub expression: Option<String>,
+    /// "true" if the name column is an sql expression
+    pub name_expression: Option<String>,
+    /// "true" if the order column is an sql expression
+    pub order_expression: Option<String>,
+    /// "true" to not cache the level's members; cuts on it aren't checked
+    /// against the cache
+    pub skip_members_cache: Option<String>,
     pub properties: Option<Vec<PropertyConfigJson>>,
     pub key_type: Option<MemberType>,
+    pub min_auth_level: Option<i32>,
     pub annotations: Option<Vec<AnnotationConfigJson>>,
+    pub captions: Option<Vec<CaptionConfigJson>>,
+    pub calculated_members: Option<Vec<CalculatedMemberConfigJson>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CalculatedMemberConfigJson {
+    pub key: String,
+    pub name: Option<String>,
+    pub members: Vec<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct MeasureConfigJson {
     pub name: String,
     pub column: String,
+    /// "true" if the column is an sql expression
+    pub expression: Option<String>,
     pub aggregator: Aggregator,
     #[serde(rename="type")]
     pub measure_type: Option<MeasureType>,
+    pub min_auth_level: Option<i32>,
     pub annotations: Option<Vec<AnnotationConfigJson>>,
+    pub captions: Option<Vec<CaptionConfigJson>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize)]
@@ -123,6 +172,8 @@ pub struct TableConfigJson {
 pub struct PropertyConfigJson {
     pub name: String,
     pub column: String,
+    /// "true" if the column is an sql expression
+    pub expression: Option<String>,
     pub caption_set: Option<String>,
     pub annotations: Option<Vec<AnnotationConfigJson>>,
 }
@@ -132,3 +183,9 @@ pub struct AnnotationConfigJson {
     pub name: String,
     pub text: String,
 }
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CaptionConfigJson {
+    pub locale: String,
+    pub text: String,
+}
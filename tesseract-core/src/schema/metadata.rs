@@ -13,6 +13,7 @@ use super::{
     MeasureType,
     Property,
     Annotation,
+    Caption,
     aggregator::Aggregator,
 };
 
@@ -23,21 +24,29 @@ pub struct SchemaMetadata {
     pub annotations: AnnotationMetadata,
 }
 
-impl From<&Schema> for SchemaMetadata {
-    fn from(schema: &Schema) -> Self {
+impl SchemaMetadata {
+    /// Captions are taken from the first of `locales` that has one.
+    pub fn from_schema(schema: &Schema, locales: &[&str]) -> Self {
         let annotations = (&schema.annotations).into();
 
         SchemaMetadata {
             name: schema.name.clone(),
-            cubes: schema.cubes.iter().map(|c| c.into()).collect(),
+            cubes: schema.cubes.iter().map(|c| CubeMetadata::from_cube(c, locales)).collect(),
             annotations,
         }
     }
 }
 
+impl From<&Schema> for SchemaMetadata {
+    fn from(schema: &Schema) -> Self {
+        SchemaMetadata::from_schema(schema, &[&schema.default_locale])
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct CubeMetadata {
     pub name: String,
+    pub caption: String,
     pub dimensions: Vec<DimensionMetadata>,
     pub measures: Vec<MeasureMetadata>,
     pub annotations: AnnotationMetadata,
@@ -45,41 +54,62 @@ pub struct CubeMetadata {
     pub min_auth_level: i32,
 }
 
-impl From<&Cube> for CubeMetadata {
-    fn from(cube: &Cube) -> Self {
+impl CubeMetadata {
+    /// Captions are taken from the first of `locales` that has one.
+    pub fn from_cube(cube: &Cube, locales: &[&str]) -> Self {
         let annotations = (&cube.annotations).into();
 
         CubeMetadata {
             name: cube.name.clone(),
-            dimensions: cube.dimensions.iter().map(|d| d.into()).collect(),
-            measures: cube.measures.iter().map(|m| m.into()).collect(),
+            caption: Caption::localize(&cube.captions, &cube.name, locales),
+            dimensions: cube.dimensions.iter().map(|d| DimensionMetadata::from_dimension(d, locales)).collect(),
+            measures: cube.measures.iter().map(|m| MeasureMetadata::from_measure(m, locales)).collect(),
             annotations,
             alias: None,
             min_auth_level: cube.min_auth_level,
         }
     }
+
+    /// Removes the dimensions, hierarchies, levels and measures which need a
+    /// higher auth level than `user_auth_level`.
+    pub fn hide_restricted(&mut self, user_auth_level: i32) {
+        self.dimensions.retain(|d| user_auth_level >= d.min_auth_level);
+        self.measures.retain(|m| user_auth_level >= m.min_auth_level);
+
+        for dimension in &mut self.dimensions {
+            dimension.hierarchies.retain(|h| user_auth_level >= h.min_auth_level);
+
+            for hierarchy in &mut dimension.hierarchies {
+                hierarchy.levels.retain(|l| user_auth_level >= l.min_auth_level);
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct DimensionMetadata {
     pub name: String,
+    pub caption: String,
     pub hierarchies: Vec<HierarchyMetadata>,
     pub default_hierarchy: Option<String>,
     #[serde(rename="type")]
     pub dim_type: DimensionType,
     pub annotations: AnnotationMetadata,
+    pub min_auth_level: i32,
 }
 
-impl From<&Dimension> for DimensionMetadata {
-    fn from(dimension: &Dimension) -> Self {
+impl DimensionMetadata {
+    pub fn from_dimension(dimension: &Dimension, locales: &[&str]) -> Self {
         let annotations = (&dimension.annotations).into();
 
         DimensionMetadata {
             name: dimension.name.clone(),
-            hierarchies: dimension.hierarchies.iter().map(|h| h.into()).collect(),
+            caption: Caption::localize(&dimension.captions, &dimension.name, locales),
+            hierarchies: dimension.hierarchies.iter().map(|h| HierarchyMetadata::from_hierarchy(h, locales)).collect(),
             default_hierarchy: dimension.default_hierarchy.clone(),
             dim_type: dimension.dim_type.clone(),
             annotations,
+            min_auth_level: dimension.min_auth_level,
         }
     }
 }
@@ -87,18 +117,22 @@ impl From<&Dimension> for DimensionMetadata {
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct HierarchyMetadata {
     pub name: String,
+    pub caption: String,
     pub levels: Vec<LevelMetadata>,
     pub annotations: AnnotationMetadata,
+    pub min_auth_level: i32,
 }
 
-impl From<&Hierarchy> for HierarchyMetadata {
-    fn from(hierarchy: &Hierarchy) -> Self {
+impl HierarchyMetadata {
+    pub fn from_hierarchy(hierarchy: &Hierarchy, locales: &[&str]) -> Self {
         let annotations = (&hierarchy.annotations).into();
 
         HierarchyMetadata {
             name: hierarchy.name.clone(),
-            levels: hierarchy.levels.iter().map(|l| l.into()).collect(),
+            caption: Caption::localize(&hierarchy.captions, &hierarchy.name, locales),
+            levels: hierarchy.levels.iter().map(|l| LevelMetadata::from_level(l, locales)).collect(),
             annotations,
+            min_auth_level: hierarchy.min_auth_level,
         }
     }
 }
@@ -106,13 +140,16 @@ impl From<&Hierarchy> for HierarchyMetadata {
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct LevelMetadata {
     pub name: String,
+    pub caption: String,
     pub properties: Option<Vec<PropertyMetadata>>,
     pub annotations: AnnotationMetadata,
     pub unique_name: Option<String>,
+    pub order_column: Option<String>,
+    pub min_auth_level: i32,
 }
 
-impl From<&Level> for LevelMetadata {
-    fn from(level: &Level) -> Self {
+impl LevelMetadata {
+    pub fn from_level(level: &Level, locales: &[&str]) -> Self {
         let properties = level.properties.clone().map(|props| {
                 props.iter().map(|p| p.into()).collect()
             });
@@ -120,9 +157,12 @@ impl From<&Level> for LevelMetadata {
 
         LevelMetadata {
             name: level.name.clone(),
+            caption: Caption::localize(&level.captions, &level.name, locales),
             properties,
             annotations,
             unique_name: None,
+            order_column: level.order_column.clone(),
+            min_auth_level: level.min_auth_level,
         }
     }
 }
@@ -130,20 +170,24 @@ impl From<&Level> for LevelMetadata {
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct MeasureMetadata {
     pub name: String,
+    pub caption: String,
     pub aggregator: AggregatorMetadata,
     pub measure_type: MeasureTypeMetadata,
     pub annotations: AnnotationMetadata,
+    pub min_auth_level: i32,
 }
 
-impl From<&Measure> for MeasureMetadata {
-    fn from(measure: &Measure) -> Self {
+impl MeasureMetadata {
+    pub fn from_measure(measure: &Measure, locales: &[&str]) -> Self {
         let annotations = (&measure.annotations).into();
 
         MeasureMetadata {
             name: measure.name.clone(),
+            caption: Caption::localize(&measure.captions, &measure.name, locales),
             aggregator: (&measure.aggregator).into(),
             measure_type: (&measure.measure_type).into(),
             annotations,
+            min_auth_level: measure.min_auth_level,
         }
     }
 }
@@ -0,0 +1,683 @@
+//! Converts Mondrian 3 XML schemas into tesseract schemas.
+//!
+//! The main differences (see `mondrian-tesseract.md`):
+//! - attributes are camelCase in Mondrian and snake_case in tesseract
+//! - level `column` and `type` are `key_column` and `key_type`
+//! - top-level `Dimension`s are `SharedDimension`s
+//! - annotations are wrapped in `Annotations`
+//! - inline table column definitions and rows are wrapped in `ColumnDefs`
+//!   and `Rows`
+//!
+//! Mondrian XML isn't deserialized with serde, because serde-xml-rs can't
+//! deserialize repeated elements which are not next to each other (e.g.
+//! `Dimension`s mixed with `DimensionUsage`s), which is common in Mondrian
+//! schemas. Instead, the XML is read into a tree of elements.
+//!
+//! Constructs which tesseract can't represent (virtual cubes, roles,
+//! calculated members, sql expressions, joins in hierarchies, distinct
+//! count measures, ...) are skipped, with a warning.
+
+use failure::{Error, format_err, bail};
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::query_ir::MemberType;
+use super::{Schema, DimensionType};
+use super::aggregator::Aggregator;
+use super::xml::{
+    SchemaConfigXML,
+    SharedDimensionConfigXML,
+    CubeConfigXML,
+    DimensionConfigXML,
+    DimensionUsageXML,
+    HierarchyConfigXML,
+    InlineTableXML,
+    InlineTableColumnDefinitionXML,
+    InlineTableRowXML,
+    InlineTableRowValueXML,
+    LevelConfigXML,
+    MeasureConfigXML,
+    PropertyConfigXML,
+    TableConfigXML,
+    AnnotationConfigXML,
+};
+
+
+/// A Mondrian schema converted into a tesseract schema config, with
+/// warnings for everything which could not be converted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MondrianImport {
+    pub schema_config: SchemaConfigXML,
+    pub warnings: Vec<String>,
+}
+
+impl MondrianImport {
+    /// Parses and converts a Mondrian 3 XML schema.
+    pub fn from_xml(raw_schema: &str) -> Result<Self, Error> {
+        let root = Element::parse(raw_schema)?;
+
+        if root.name != "Schema" {
+            bail!("Mondrian schema root must be Schema, found: {}", root.name);
+        }
+
+        let mut warnings = vec![];
+        let schema_config = convert_schema(&root, &mut warnings)?;
+
+        Ok(MondrianImport {
+            schema_config,
+            warnings,
+        })
+    }
+
+    /// Tesseract JSON schema, without empty fields.
+    pub fn to_json(&self) -> Result<String, Error> {
+        let mut value = serde_json::to_value(&self.schema_config)?;
+        remove_nulls(&mut value);
+        Ok(serde_json::to_string_pretty(&value)?)
+    }
+
+    /// Converts into a validated `Schema`.
+    pub fn schema(&self) -> Result<Schema, Error> {
+        // same path as Schema::from_xml
+        let serialized = serde_json::to_string(&self.schema_config)?;
+        let mut schema = Schema::from_json(&serialized)?;
+        schema.validate()?;
+        Ok(schema)
+    }
+}
+
+fn remove_nulls(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            let null_keys: Vec<_> = map.iter()
+                .filter(|(_, v)| v.is_null())
+                .map(|(k, _)| k.clone())
+                .collect();
+            for k in null_keys {
+                map.remove(&k);
+            }
+            for v in map.values_mut() {
+                remove_nulls(v);
+            }
+        },
+        serde_json::Value::Array(values) => {
+            for v in values.iter_mut() {
+                remove_nulls(v);
+            }
+        },
+        _ => (),
+    }
+}
+
+
+/// Minimal xml element tree
+#[derive(Debug, Clone, PartialEq)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    fn parse(raw: &str) -> Result<Self, Error> {
+        let mut stack: Vec<Element> = vec![];
+
+        for event in EventReader::new(raw.as_bytes()) {
+            match event.map_err(|err| format_err!("Error reading Mondrian schema: {}", err))? {
+                XmlEvent::StartElement { name, attributes, .. } => {
+                    stack.push(Element {
+                        name: name.local_name,
+                        attributes: attributes.into_iter()
+                            .map(|attr| (attr.name.local_name, attr.value))
+                            .collect(),
+                        children: vec![],
+                        text: String::new(),
+                    });
+                },
+                XmlEvent::EndElement { .. } => {
+                    let element = stack.pop()
+                        .ok_or_else(|| format_err!("Error reading Mondrian schema: unbalanced tags"))?;
+
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(element),
+                        None => return Ok(element),
+                    }
+                },
+                XmlEvent::Characters(s) | XmlEvent::CData(s) => {
+                    if let Some(element) = stack.last_mut() {
+                        element.text.push_str(&s);
+                    }
+                },
+                _ => (),
+            }
+        }
+
+        bail!("Error reading Mondrian schema: no root element")
+    }
+
+    fn attr(&self, name: &str) -> Option<String> {
+        self.attributes.iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.clone())
+    }
+
+    fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item=&'a Element> + 'a {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    /// Warns about children which aren't in `supported`.
+    fn warn_unsupported_children(&self, path: &str, supported: &[&str], warnings: &mut Vec<String>) {
+        for child in &self.children {
+            if !supported.contains(&child.name.as_str()) {
+                let child_name = child.attr("name")
+                    .map(|n| format!(" \"{}\"", n))
+                    .unwrap_or_else(|| "".to_owned());
+
+                warnings.push(format!("{}: {}{} is not supported, skipped", path, child.name, child_name));
+            }
+        }
+    }
+}
+
+fn convert_schema(schema: &Element, warnings: &mut Vec<String>) -> Result<SchemaConfigXML, Error> {
+    let name = schema.attr("name")
+        .ok_or_else(|| format_err!("Mondrian schema must have a name"))?;
+    let path = format!("Schema \"{}\"", name);
+
+    schema.warn_unsupported_children(&path, &["Dimension", "Cube", "Annotations"], warnings);
+
+    let shared_dimensions: Vec<_> = schema.children_named("Dimension")
+        .filter_map(|dim| {
+            let dim_path = format!("{}, Dimension \"{}\"", path, dim.attr("name").unwrap_or_default());
+            let (hierarchies, default_hierarchy) = convert_hierarchies(dim, &dim_path, warnings);
+
+            if hierarchies.is_empty() {
+                warnings.push(format!("{}: no hierarchies could be converted, skipped", dim_path));
+                return None;
+            }
+
+            Some(SharedDimensionConfigXML {
+                name: dim.attr("name")?,
+                min_auth_level: None,
+                hierarchies,
+                default_hierarchy,
+                dim_type: dimension_type(dim),
+                annotations: convert_annotations(dim),
+                captions: None,
+            })
+        })
+        .collect();
+
+    let cubes = schema.children_named("Cube")
+        .filter_map(|cube| convert_cube(cube, &path, warnings))
+        .collect();
+
+    Ok(SchemaConfigXML {
+        name,
+        shared_dimensions: if shared_dimensions.is_empty() { None } else { Some(shared_dimensions) },
+        cubes,
+        annotations: convert_annotations(schema),
+        default_locale: None,
+        includes: None,
+    })
+}
+
+fn convert_cube(cube: &Element, schema_path: &str, warnings: &mut Vec<String>) -> Option<CubeConfigXML> {
+    let name = cube.attr("name")?;
+    let path = format!("{}, Cube \"{}\"", schema_path, name);
+
+    if cube.attr("enabled").map(|e| e == "false").unwrap_or(false) {
+        warnings.push(format!("{}: cube is not enabled, skipped", path));
+        return None;
+    }
+
+    let table = match cube.child("Table") {
+        Some(table) => convert_table(table)?,
+        None => {
+            warnings.push(format!("{}: cubes without a Table (e.g. View) are not supported, skipped", path));
+            return None;
+        },
+    };
+
+    cube.warn_unsupported_children(&path, &["Table", "Dimension", "DimensionUsage", "Measure", "Annotations"], warnings);
+
+    let dimensions: Vec<_> = cube.children_named("Dimension")
+        .filter_map(|dim| {
+            let dim_path = format!("{}, Dimension \"{}\"", path, dim.attr("name").unwrap_or_default());
+            let (hierarchies, default_hierarchy) = convert_hierarchies(dim, &dim_path, warnings);
+
+            if hierarchies.is_empty() {
+                warnings.push(format!("{}: no hierarchies could be converted, skipped", dim_path));
+                return None;
+            }
+
+            Some(DimensionConfigXML {
+                name: dim.attr("name")?,
+                foreign_key: dim.attr("foreignKey"),
+                foreign_key_expression: None,
+                min_auth_level: None,
+                hierarchies,
+                default_hierarchy,
+                dim_type: dimension_type(dim),
+                annotations: convert_annotations(dim),
+                date_column: None,
+                date_levels: None,
+                captions: None,
+            })
+        })
+        .collect();
+
+    let dimension_usages: Vec<_> = cube.children_named("DimensionUsage")
+        .filter_map(|dim_usage| {
+            let usage_path = format!("{}, DimensionUsage \"{}\"", path, dim_usage.attr("name").unwrap_or_default());
+
+            for attr in &["level", "usagePrefix"] {
+                if dim_usage.attr(attr).is_some() {
+                    warnings.push(format!("{}: {} is not supported, ignored", usage_path, attr));
+                }
+            }
+
+            let foreign_key = match dim_usage.attr("foreignKey") {
+                Some(fk) => fk,
+                None => {
+                    warnings.push(format!("{}: dimension usages without a foreignKey are not supported, skipped", usage_path));
+                    return None;
+                },
+            };
+
+            let source = dim_usage.attr("source")?;
+            let name = dim_usage.attr("name").filter(|n| *n != source);
+
+            Some(DimensionUsageXML {
+                source,
+                name,
+                foreign_key,
+                foreign_key_expression: None,
+                annotations: convert_annotations(dim_usage),
+                captions: None,
+            })
+        })
+        .collect();
+
+    let measures = cube.children_named("Measure")
+        .filter_map(|mea| convert_measure(mea, &path, warnings))
+        .collect();
+
+    let public = cube.attr("visible")
+        .filter(|v| v == "false")
+        .map(|_| "false".to_owned());
+
+    Some(CubeConfigXML {
+        name,
+        public,
+        min_auth_level: None,
+        table,
+        dimensions: if dimensions.is_empty() { None } else { Some(dimensions) },
+        dimension_usages: if dimension_usages.is_empty() { None } else { Some(dimension_usages) },
+        measures,
+        annotations: convert_annotations(cube),
+        captions: None,
+    })
+}
+
+/// Returns the converted hierarchies, and the default hierarchy if there's
+/// more than one (Mondrian uses the first one).
+fn convert_hierarchies(
+    dim: &Element,
+    dim_path: &str,
+    warnings: &mut Vec<String>,
+) -> (Vec<HierarchyConfigXML>, Option<String>)
+{
+    let dim_name = dim.attr("name").unwrap_or_default();
+
+    dim.warn_unsupported_children(dim_path, &["Hierarchy", "Annotations"], warnings);
+
+    let hierarchies: Vec<_> = dim.children_named("Hierarchy")
+        .filter_map(|hier| {
+            // hierarchies without a name take the name of the dimension
+            let name = hier.attr("name").unwrap_or_else(|| dim_name.clone());
+            let path = format!("{}, Hierarchy \"{}\"", dim_path, name);
+
+            if hier.child("Join").is_some() || hier.child("View").is_some() {
+                warnings.push(format!("{}: hierarchies on a Join or View are not supported, skipped", path));
+                return None;
+            }
+
+            hier.warn_unsupported_children(&path, &["Table", "InlineTable", "Level", "Annotations"], warnings);
+
+            if hier.attr("defaultMember").is_some() {
+                warnings.push(format!("{}: defaultMember is not supported, ignored", path));
+            }
+
+            let levels: Vec<_> = hier.children_named("Level")
+                .filter_map(|level| convert_level(level, &path, warnings))
+                .collect();
+
+            if levels.is_empty() {
+                warnings.push(format!("{}: no levels could be converted, skipped", path));
+                return None;
+            }
+
+            let inline_table = match hier.child("InlineTable") {
+                Some(inline_table) => Some(convert_inline_table(inline_table, &path, warnings)?),
+                None => None,
+            };
+
+            Some(HierarchyConfigXML {
+                name,
+                min_auth_level: None,
+                table: hier.child("Table").and_then(convert_table),
+                primary_key: hier.attr("primaryKey"),
+                levels,
+                annotations: convert_annotations(hier),
+                inline_table,
+                default_member: None,
+                captions: None,
+            })
+        })
+        .collect();
+
+    let default_hierarchy = if hierarchies.len() > 1 {
+        Some(hierarchies[0].name.clone())
+    } else {
+        None
+    };
+
+    (hierarchies, default_hierarchy)
+}
+
+fn convert_level(level: &Element, hier_path: &str, warnings: &mut Vec<String>) -> Option<LevelConfigXML> {
+    let name = level.attr("name")?;
+    let path = format!("{}, Level \"{}\"", hier_path, name);
+
+    let key_column = match level.attr("column") {
+        Some(column) => column,
+        None => {
+            warnings.push(format!("{}: levels without a column (e.g. KeyExpression) are not supported, skipped", path));
+            return None;
+        },
+    };
+
+    level.warn_unsupported_children(&path, &["Property", "Annotations"], warnings);
+
+    if level.attr("parentColumn").is_some() {
+        warnings.push(format!("{}: parent-child levels are not supported, imported as a regular level", path));
+    }
+
+    // Mondrian uses the caption column for display if there's no name column
+    let name_column = level.attr("nameColumn")
+        .or_else(|| level.attr("captionColumn"));
+
+    let properties: Vec<_> = level.children_named("Property")
+        .filter_map(|property| {
+            let property_name = property.attr("name")?;
+
+            match property.attr("column") {
+                Some(column) => Some(PropertyConfigXML {
+                    name: property_name,
+                    column,
+                    expression: None,
+                    caption_set: None,
+                    annotations: convert_annotations(property),
+                }),
+                None => {
+                    warnings.push(format!("{}, Property \"{}\": properties without a column are not supported, skipped", path, property_name));
+                    None
+                },
+            }
+        })
+        .collect();
+
+    Some(LevelConfigXML {
+        name,
+        key_column,
+        name_column,
+        order_column: level.attr("ordinalColumn"),
+        expression: None,
+        name_expression: None,
+        order_expression: None,
+        skip_members_cache: None,
+        properties: if properties.is_empty() { None } else { Some(properties) },
+        key_type: level.attr("type").map(|t| member_type(&t)),
+        min_auth_level: None,
+        annotations: convert_annotations(level),
+        calculated_members: None,
+        captions: None,
+    })
+}
+
+fn convert_inline_table(inline_table: &Element, hier_path: &str, warnings: &mut Vec<String>) -> Option<InlineTableXML> {
+    let alias = match inline_table.attr("alias") {
+        Some(alias) => alias,
+        None => {
+            warnings.push(format!("{}: inline tables without an alias are not supported, skipped", hier_path));
+            return None;
+        },
+    };
+
+    let column_definitions = inline_table.child("ColumnDefs")
+        .map(|col_defs| {
+            col_defs.children_named("ColumnDef")
+                .filter_map(|col_def| {
+                    Some(InlineTableColumnDefinitionXML {
+                        name: col_def.attr("name")?,
+                        key_type: member_type(&col_def.attr("type").unwrap_or_default()),
+                        key_column_type: None,
+                        caption_set: None,
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_else(|| vec![]);
+
+    let rows = inline_table.child("Rows")
+        .map(|rows| {
+            rows.children_named("Row")
+                .map(|row| {
+                    InlineTableRowXML {
+                        row_values: row.children_named("Value")
+                            .filter_map(|value| {
+                                Some(InlineTableRowValueXML {
+                                    column: value.attr("column")?,
+                                    value: value.text.clone(),
+                                })
+                            })
+                            .collect(),
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_else(|| vec![]);
+
+    Some(InlineTableXML {
+        alias,
+        column_definitions,
+        rows,
+    })
+}
+
+fn convert_measure(mea: &Element, cube_path: &str, warnings: &mut Vec<String>) -> Option<MeasureConfigXML> {
+    let name = mea.attr("name")?;
+    let path = format!("{}, Measure \"{}\"", cube_path, name);
+
+    let column = match mea.attr("column") {
+        Some(column) => column,
+        None => {
+            warnings.push(format!("{}: measures without a column (e.g. MeasureExpression) are not supported, skipped", path));
+            return None;
+        },
+    };
+
+    let aggregator = match mea.attr("aggregator").unwrap_or_default().as_str() {
+        "sum" => Aggregator::Sum,
+        "count" => Aggregator::Count,
+        "min" => Aggregator::Min,
+        "max" => Aggregator::Max,
+        "avg" => Aggregator::Average,
+        other => {
+            warnings.push(format!("{}: aggregator \"{}\" is not supported, skipped", path, other));
+            return None;
+        },
+    };
+
+    mea.warn_unsupported_children(&path, &["Annotations"], warnings);
+
+    // keep the format string, since there's no equivalent in tesseract
+    let mut annotations = convert_annotations(mea).unwrap_or_else(|| vec![]);
+    if let Some(format_string) = mea.attr("formatString") {
+        annotations.push(AnnotationConfigXML {
+            name: "format_string".to_owned(),
+            text: format_string,
+        });
+    }
+
+    Some(MeasureConfigXML {
+        name,
+        column,
+        expression: None,
+        aggregator,
+        measure_type: None,
+        min_auth_level: None,
+        annotations: if annotations.is_empty() { None } else { Some(annotations) },
+        captions: None,
+    })
+}
+
+fn convert_table(table: &Element) -> Option<TableConfigXML> {
+    Some(TableConfigXML {
+        name: table.attr("name")?,
+        schema: table.attr("schema"),
+        primary_key: None,
+    })
+}
+
+fn convert_annotations(element: &Element) -> Option<Vec<AnnotationConfigXML>> {
+    let annotations: Vec<_> = element.child("Annotations")
+        .map(|anns| {
+            anns.children_named("Annotation")
+                .filter_map(|ann| {
+                    Some(AnnotationConfigXML {
+                        name: ann.attr("name")?,
+                        text: ann.text.trim().to_owned(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_else(|| vec![]);
+
+    if annotations.is_empty() { None } else { Some(annotations) }
+}
+
+fn dimension_type(dim: &Element) -> Option<DimensionType> {
+    match dim.attr("type").as_ref().map(|t| t.as_str()) {
+        Some("TimeDimension") => Some(DimensionType::Time),
+        _ => None,
+    }
+}
+
+/// Mondrian level and column types: String, Numeric, Integer, Boolean,
+/// Date, Time, Timestamp. Only numbers are nontext.
+fn member_type(mondrian_type: &str) -> MemberType {
+    match mondrian_type {
+        "Numeric" | "Integer" => MemberType::NonText,
+        _ => MemberType::Text,
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mondrian_import() {
+        let s = r##"
+            <Schema name="legacy">
+                <Dimension name="Geography">
+                    <Hierarchy hasAll="true" primaryKey="geo_id">
+                        <Table name="dim_geo" schema="public"/>
+                        <Level name="Country" column="country_id" nameColumn="country_name" type="String" uniqueMembers="true">
+                            <Property name="Population" column="population" type="Numeric"/>
+                        </Level>
+                        <Level name="City" column="geo_id" captionColumn="city_name" type="Integer"/>
+                    </Hierarchy>
+                </Dimension>
+                <Cube name="Sales">
+                    <Table name="sales_fact"/>
+                    <DimensionUsage name="Geography" source="Geography" foreignKey="geo_id"/>
+                    <Dimension name="Channel" foreignKey="channel_id">
+                        <Hierarchy hasAll="true" primaryKey="id">
+                            <InlineTable alias="channels">
+                                <ColumnDefs>
+                                    <ColumnDef name="id" type="Numeric"/>
+                                    <ColumnDef name="label" type="String"/>
+                                </ColumnDefs>
+                                <Rows>
+                                    <Row><Value column="id">1</Value><Value column="label">Online</Value></Row>
+                                    <Row><Value column="id">2</Value><Value column="label">Store</Value></Row>
+                                </Rows>
+                            </InlineTable>
+                            <Level name="Channel" column="id" nameColumn="label" type="Numeric"/>
+                        </Hierarchy>
+                    </Dimension>
+                    <DimensionUsage name="Origin" source="Geography" foreignKey="origin_geo_id"/>
+                    <Dimension name="Product" foreignKey="product_id">
+                        <Hierarchy hasAll="true" primaryKeyTable="product" primaryKey="id">
+                            <Join leftKey="category_id" rightKey="id">
+                                <Table name="product"/>
+                                <Table name="category"/>
+                            </Join>
+                            <Level name="Product" table="product" column="id"/>
+                        </Hierarchy>
+                    </Dimension>
+                    <Measure name="Quantity" column="quantity" aggregator="sum" formatString="#,###"/>
+                    <Measure name="Average Price" column="price" aggregator="avg">
+                        <Annotations>
+                            <Annotation name="units">usd</Annotation>
+                        </Annotations>
+                    </Measure>
+                    <Measure name="Customers" column="customer_id" aggregator="distinct-count"/>
+                    <CalculatedMember name="Revenue" dimension="Measures" formula="[Measures].[Quantity] * [Measures].[Average Price]"/>
+                </Cube>
+                <VirtualCube name="All Sales"/>
+            </Schema>
+        "##;
+
+        let import = MondrianImport::from_xml(s).unwrap();
+
+        assert_eq!(import.warnings, vec![
+            "Schema \"legacy\": VirtualCube \"All Sales\" is not supported, skipped",
+            "Schema \"legacy\", Cube \"Sales\": CalculatedMember \"Revenue\" is not supported, skipped",
+            "Schema \"legacy\", Cube \"Sales\", Dimension \"Product\", Hierarchy \"Product\": hierarchies on a Join or View are not supported, skipped",
+            "Schema \"legacy\", Cube \"Sales\", Dimension \"Product\": no hierarchies could be converted, skipped",
+            "Schema \"legacy\", Cube \"Sales\", Measure \"Customers\": aggregator \"distinct-count\" is not supported, skipped",
+        ]);
+
+        let schema = import.schema().unwrap();
+        let cube = &schema.cubes[0];
+        let dim_names: Vec<_> = cube.dimensions.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(dim_names, vec!["Channel", "Geography", "Origin"]);
+
+        let geo = &cube.dimensions[1];
+        assert_eq!(geo.foreign_key, Some("geo_id".to_owned()));
+        assert_eq!(geo.hierarchies[0].name, "Geography");
+        assert_eq!(geo.hierarchies[0].table.as_ref().unwrap().full_name(), "public.dim_geo");
+        assert_eq!(geo.hierarchies[0].levels[0].key_type, Some(MemberType::Text));
+        assert_eq!(geo.hierarchies[0].levels[0].properties.as_ref().unwrap()[0].column, "population");
+        assert_eq!(geo.hierarchies[0].levels[1].name_column, Some("city_name".to_owned()));
+
+        let channel = &cube.dimensions[0];
+        assert_eq!(channel.hierarchies[0].inline_table.as_ref().unwrap().rows.len(), 2);
+
+        let aggregators: Vec<_> = cube.measures.iter().map(|m| m.aggregator.clone()).collect();
+        assert_eq!(aggregators, vec![Aggregator::Sum, Aggregator::Average]);
+
+        // json output can be read back
+        let json_schema = Schema::from_json(&import.to_json().unwrap()).unwrap();
+        assert_eq!(json_schema.cubes[0].measures.len(), 2);
+    }
+}
@@ -22,11 +22,18 @@ pub struct SchemaConfigXML {
     pub name: String,
     #[serde(rename(deserialize="SharedDimension"))]
     pub shared_dimensions: Option<Vec<SharedDimensionConfigXML>>,
-    #[serde(rename(deserialize="Cube"))]
+    #[serde(rename(deserialize="Cube"), default)]
     pub cubes: Vec<CubeConfigXML>,
     #[serde(rename(deserialize="Annotation"))]
     pub annotations: Option<Vec<AnnotationConfigXML>>,
     pub default_locale: Option<String>,
+    #[serde(rename(deserialize="Include"))]
+    pub includes: Option<Vec<IncludeConfigXML>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct IncludeConfigXML {
+    pub path: String,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
@@ -45,24 +52,34 @@ pub struct CubeConfigXML {
     pub measures: Vec<MeasureConfigXML>,
     #[serde(rename(deserialize="Annotation"))]
     pub annotations: Option<Vec<AnnotationConfigXML>>,
+    #[serde(rename(deserialize="Caption"))]
+    pub captions: Option<Vec<CaptionConfigXML>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct DimensionConfigXML {
     pub name: String,
     pub foreign_key: Option<String>, // does not exist for shared dims
-    #[serde(rename(deserialize="Hierarchy"))]
+    pub foreign_key_expression: Option<String>,
+    pub min_auth_level: Option<i32>,
+    #[serde(rename(deserialize="Hierarchy"), default)]
     pub hierarchies: Vec<HierarchyConfigXML>,
     pub default_hierarchy: Option<String>,
     #[serde(rename="type")]
     pub dim_type: Option<DimensionType>,
     #[serde(rename(deserialize="Annotation"))]
     pub annotations: Option<Vec<AnnotationConfigXML>>,
+    #[serde(rename(deserialize="Caption"))]
+    pub captions: Option<Vec<CaptionConfigXML>>,
+    pub date_column: Option<String>,
+    #[serde(rename(deserialize="DateLevel"))]
+    pub date_levels: Option<Vec<String>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct SharedDimensionConfigXML {
     pub name: String,
+    pub min_auth_level: Option<i32>,
     #[serde(rename(deserialize="Hierarchy"))]
     pub hierarchies: Vec<HierarchyConfigXML>,
     pub default_hierarchy: Option<String>,
@@ -70,6 +87,8 @@ pub struct SharedDimensionConfigXML {
     pub dim_type: Option<DimensionType>,
     #[serde(rename(deserialize="Annotation"))]
     pub annotations: Option<Vec<AnnotationConfigXML>>,
+    #[serde(rename(deserialize="Caption"))]
+    pub captions: Option<Vec<CaptionConfigXML>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
@@ -77,13 +96,17 @@ pub struct DimensionUsageXML {
     pub source: String,
     pub name: Option<String>,
     pub foreign_key: String,
+    pub foreign_key_expression: Option<String>,
     #[serde(rename(deserialize="Annotation"))]
     pub annotations: Option<Vec<AnnotationConfigXML>>,
+    #[serde(rename(deserialize="Caption"))]
+    pub captions: Option<Vec<CaptionConfigXML>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct HierarchyConfigXML {
     pub name: String,
+    pub min_auth_level: Option<i32>,
     #[serde(rename(deserialize="Table"))]
     pub table: Option<TableConfigXML>,
     pub primary_key: Option<String>,
@@ -91,6 +114,8 @@ pub struct HierarchyConfigXML {
     pub levels: Vec<LevelConfigXML>,
     #[serde(rename(deserialize="Annotation"))]
     pub annotations: Option<Vec<AnnotationConfigXML>>,
+    #[serde(rename(deserialize="Caption"))]
+    pub captions: Option<Vec<CaptionConfigXML>>,
     #[serde(rename(deserialize="InlineTable"))]
     pub inline_table: Option<InlineTableXML>,
     pub default_member: Option<String>,
@@ -131,22 +156,44 @@ pub struct LevelConfigXML {
     pub name: String,
     pub key_column: String,
     pub name_column: Option<String>,
+    pub order_column: Option<String>,
+    pub expression: Option<String>,
+    pub name_expression: Option<String>,
+    pub order_expression: Option<String>,
+    pub skip_members_cache: Option<String>,
     #[serde(rename(deserialize="Property"))]
     pub properties: Option<Vec<PropertyConfigXML>>,
     pub key_type: Option<MemberType>,
+    pub min_auth_level: Option<i32>,
     #[serde(rename(deserialize="Annotation"))]
     pub annotations: Option<Vec<AnnotationConfigXML>>,
+    #[serde(rename(deserialize="Caption"))]
+    pub captions: Option<Vec<CaptionConfigXML>>,
+    #[serde(rename(deserialize="CalculatedMember"))]
+    pub calculated_members: Option<Vec<CalculatedMemberConfigXML>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CalculatedMemberConfigXML {
+    pub key: String,
+    pub name: Option<String>,
+    #[serde(rename(deserialize="Member"))]
+    pub members: Vec<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct MeasureConfigXML {
     pub name: String,
     pub column: String,
+    pub expression: Option<String>,
     pub aggregator: Aggregator,
     #[serde(rename="type")]
     pub measure_type: Option<MeasureType>,
+    pub min_auth_level: Option<i32>,
     #[serde(rename(deserialize="Annotation"))]
     pub annotations: Option<Vec<AnnotationConfigXML>>,
+    #[serde(rename(deserialize="Caption"))]
+    pub captions: Option<Vec<CaptionConfigXML>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
@@ -160,6 +207,7 @@ pub struct TableConfigXML {
 pub struct PropertyConfigXML {
     pub name: String,
     pub column: String,
+    pub expression: Option<String>,
     pub caption_set: Option<String>,
     #[serde(rename(deserialize="Annotation"))]
     pub annotations: Option<Vec<AnnotationConfigXML>>,
@@ -172,6 +220,13 @@ pub struct AnnotationConfigXML {
     pub text: String,
 }
 
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CaptionConfigXML {
+    pub locale: String,
+    #[serde(rename(deserialize="$value"))]
+    pub text: String,
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
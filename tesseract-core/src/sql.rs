@@ -11,6 +11,7 @@ use crate::query_ir::{
     LimitSql,
     RcaSql,
     GrowthSql,
+    LiteralStyle,
 };
 
 /// Error checking is done before this point. This string formatter
@@ -28,6 +29,7 @@ pub(crate) fn standard_sql(
     _limit: &Option<LimitSql>,
     _rca: &Option<RcaSql>,
     _growth: &Option<GrowthSql>,
+    literal_style: LiteralStyle,
     ) -> String
 {
     // hack for now... remove later
@@ -83,32 +85,112 @@ pub(crate) fn standard_sql(
     if !ext_drills.is_empty() {
         let join_ext_dim_clauses = join(ext_drills.iter()
             .map(|d| {
-                format!("inner join {} on {}.{} = {}.{}",
-                    d.table.full_name(),
-                    d.table.full_name(),
-                    d.primary_key,
-                    table.name,
-                    d.foreign_key,
-                )
+                if d.calculated_members.is_empty() {
+                    format!("inner join {} on {}.{} = {}.{}",
+                        d.table.full_name(),
+                        d.table.full_name(),
+                        d.primary_key,
+                        table.name,
+                        d.foreign_key,
+                    )
+                } else {
+                    // the dim table gets extra rows for calculated members,
+                    // aliased back to the table name so that qualified cols
+                    // still work
+                    format!("inner join ({}) as {} on {}.{} = {}.{}",
+                        calculated_members_dim_table(d, literal_style),
+                        d.table.name,
+                        d.table.name,
+                        d.primary_key,
+                        table.name,
+                        d.foreign_key,
+                    )
+                }
         }), ", ");
 
         final_sql = format!("{} {}", final_sql, join_ext_dim_clauses);
     }
 
     if !cuts.is_empty() {
-        let cut_clauses = join(cuts.iter().map(|c| format!("{} in ({})", c.col_qual_string(), c.members_string())), " and ");
+        let cut_clauses = join(cuts.iter().map(|c| format!("{} in ({})", c.col_qual_string(), c.members_sql(literal_style))), " and ");
         final_sql = format!("{} where {}", final_sql, cut_clauses);
     }
 
-    final_sql = format!("{} group by {};", final_sql, drill_cols);
+    // order columns aren't projected; each member is sorted by its first order,
+    // which also places calculated members by their first member.
+    let order_cols: Vec<_> = drills.iter()
+        .flat_map(|d| {
+            d.level_columns.iter()
+                .filter_map(move |l| l.order_column.as_ref().map(|o| format!("min({}.{})", d.table.name, o)))
+        })
+        .collect();
+
+    if order_cols.is_empty() {
+        final_sql = format!("{} group by {};", final_sql, drill_cols);
+    } else {
+        final_sql = format!("{} group by {} order by {};", final_sql, drill_cols, join(order_cols, ", "));
+    }
+
     final_sql
 }
 
+/// Dim table for a drilldown with calculated members: the level columns and
+/// primary key of the dim table, plus one row for each primary key of the
+/// children of each calculated member.
+fn calculated_members_dim_table(drill: &DrilldownSql, style: LiteralStyle) -> String {
+    let level_column = match drill.level_columns.last() {
+        Some(l) => l,
+        None => return drill.table.full_name(),
+    };
+
+    let name_col = if let Some(ref name_col) = level_column.name_column {
+        format!(", {}", name_col)
+    } else {
+        "".to_owned()
+    };
+
+    // each row keeps the order of the member it's made from
+    let order_col = if let Some(ref order_col) = level_column.order_column {
+        format!(", {}", order_col)
+    } else {
+        "".to_owned()
+    };
+
+    let mut sql = format!("select distinct {}{}{}, {} from {}",
+        level_column.key_column,
+        name_col,
+        order_col,
+        drill.primary_key,
+        drill.table.full_name(),
+    );
+
+    for cm in &drill.calculated_members {
+        let cm_name_col = if let Some(ref name_col) = level_column.name_column {
+            format!(", {} as {}", cm.name_sql(style), name_col)
+        } else {
+            "".to_owned()
+        };
+
+        sql.push_str(&format!(" union all select distinct {} as {}{}{}, {} from {} where {} in ({})",
+            cm.key_sql(style),
+            level_column.key_column,
+            cm_name_col,
+            order_col,
+            drill.primary_key,
+            drill.table.full_name(),
+            level_column.key_column,
+            cm.members_sql(style),
+        ));
+    }
+
+    sql
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::names::Mask;
-    use crate::query_ir::{MemberType, LevelColumn};
+    use crate::query_ir::{IdentifierStyle, MemberType, LevelColumn, SqlParam, col_alias, quote_column, quote_identifier};
     use crate::Table;
 
     #[test]
@@ -127,14 +209,17 @@ mod test {
         let cuts = vec![
             CutSql {
                 foreign_key: "project_id".into(),
+                foreign_key_expression: false,
                 primary_key: "id".into(),
                 inline_table: None,
                 table: Table { name: "valid_projects".into(), schema: None, primary_key: None },
                 column: "id".into(),
+                expression: false,
                 members: vec!["3".into()],
                 member_type: MemberType::NonText,
                 mask: Mask::Include,
-                for_match: false
+                for_match: false,
+                bound: false,
             },
         ];
         let drills = vec![
@@ -143,6 +228,7 @@ mod test {
             DrilldownSql {
                 alias_postfix: "".into(),
                 foreign_key: "project_id".into(),
+                foreign_key_expression: false,
                 primary_key: "id".into(),
                 inline_table: None,
                 table: Table { name: "valid_projects".into(), schema: None, primary_key: None },
@@ -150,19 +236,145 @@ mod test {
                     LevelColumn {
                         key_column: "id".into(),
                         name_column: Some("name".to_owned()),
+                        order_column: None,
+                        key_expression: false,
+                        name_expression: false,
+                        order_expression: false,
                     },
                 ],
                 property_columns: vec![],
+                calculated_members: vec![],
             },
         ];
         let meas = vec![
-            MeasureSql { aggregator: Aggregator::Sum, column: "commits".into() }
+            MeasureSql { aggregator: Aggregator::Sum, column: "commits".into(), expression: false }
         ];
 
         assert_eq!(
-            standard_sql(&table, &cuts, &drills, &meas, &None, &None, &None, &None, &None),
+            standard_sql(&table, &cuts, &drills, &meas, &None, &None, &None, &None, &None, LiteralStyle::Standard),
             "select valid_projects.id, valid_projects.name, sum(commits) from project_facts inner join valid_projects on valid_projects.id = project_facts.project_id where valid_projects.id in (3) group by valid_projects.id, valid_projects.name;".to_owned()
         );
     }
+
+    #[test]
+    fn test_standard_sql_cut_members() {
+        let table = TableSql {
+            name: "project_facts".into(),
+            primary_key: Some("id".into()),
+        };
+        let mut cut = CutSql {
+            foreign_key: "project_id".into(),
+            foreign_key_expression: false,
+            primary_key: "id".into(),
+            inline_table: None,
+            table: Table { name: "valid_projects".into(), schema: None, primary_key: None },
+            column: "name".into(),
+            expression: false,
+            members: vec!["O'Brien".into(), "x') or ('1'='1".into()],
+            member_type: MemberType::Text,
+            mask: Mask::Include,
+            for_match: false,
+            bound: false,
+        };
+        let drills = vec![
+            DrilldownSql {
+                alias_postfix: "".into(),
+                foreign_key: "project_id".into(),
+                foreign_key_expression: false,
+                primary_key: "id".into(),
+                inline_table: None,
+                table: Table { name: "valid_projects".into(), schema: None, primary_key: None },
+                level_columns: vec![
+                    LevelColumn {
+                        key_column: "id".into(),
+                        name_column: None,
+                        order_column: None,
+                        key_expression: false,
+                        name_expression: false,
+                        order_expression: false,
+                    },
+                ],
+                property_columns: vec![],
+                calculated_members: vec![],
+            },
+        ];
+        let meas = vec![
+            MeasureSql { aggregator: Aggregator::Sum, column: "commits".into(), expression: false }
+        ];
+
+        // escaped literals
+        assert_eq!(
+            standard_sql(&table, &[cut.clone()], &drills, &meas, &None, &None, &None, &None, &None, LiteralStyle::Standard),
+            "select valid_projects.id, sum(commits) from project_facts inner join valid_projects on valid_projects.id = project_facts.project_id where valid_projects.name in ('O''Brien', 'x'') or (''1''=''1') group by valid_projects.id;".to_owned()
+        );
+
+        // bound parameters
+        let mut params = vec![];
+        cut.bind_members(&mut params, &|idx, _| format!("${}", idx));
+        assert_eq!(
+            params,
+            vec![SqlParam::Text("O'Brien".into()), SqlParam::Text("x') or ('1'='1".into())],
+        );
+        assert_eq!(
+            standard_sql(&table, &[cut], &drills, &meas, &None, &None, &None, &None, &None, LiteralStyle::Standard),
+            "select valid_projects.id, sum(commits) from project_facts inner join valid_projects on valid_projects.id = project_facts.project_id where valid_projects.name in ($1, $2) group by valid_projects.id;".to_owned()
+        );
+    }
+
+    #[test]
+    fn test_quote_identifier() {
+        assert_eq!(quote_identifier("geo_id", IdentifierStyle::DoubleQuote), "geo_id");
+        assert_eq!(quote_identifier("Geo ID", IdentifierStyle::DoubleQuote), r#""Geo ID""#);
+        assert_eq!(quote_identifier("geo-id", IdentifierStyle::Backtick), "`geo-id`");
+        assert_eq!(quote_identifier("GeoID", IdentifierStyle::Backtick), "`GeoID`");
+        assert_eq!(quote_identifier("order", IdentifierStyle::DoubleQuote), r#""order""#);
+        assert_eq!(quote_identifier("2019", IdentifierStyle::DoubleQuote), r#""2019""#);
+        // already quoted
+        assert_eq!(quote_identifier("`Geo ID`", IdentifierStyle::Backtick), "`Geo ID`");
+
+        // names are never taken for expressions
+        assert_eq!(quote_identifier("x - 1", IdentifierStyle::DoubleQuote), r#""x - 1""#);
+        assert_eq!(quote_identifier("id::text", IdentifierStyle::DoubleQuote), r#""id::text""#);
+        assert_eq!(quote_identifier("toYear(Date)", IdentifierStyle::Backtick), "`toYear(Date)`");
+
+        // only declared expressions are written as is
+        for expression in &["x - 1", "id::text", "CASE WHEN flag THEN 1 ELSE 0 END"] {
+            assert_eq!(quote_column(expression, true, IdentifierStyle::DoubleQuote), *expression);
+            assert_eq!(
+                quote_column(expression, false, IdentifierStyle::Backtick),
+                format!("`{}`", expression),
+            );
+        }
+    }
+
+    #[test]
+    fn test_col_alias() {
+        assert_eq!(col_alias("geo_id", "Geo"), "geo_id_Geo");
+        // the alias of a quoted name is the same as the alias of the name
+        assert_eq!(col_alias(r#""Geo ID""#, "Geo"), col_alias("Geo ID", "Geo"));
+        assert_eq!(col_alias("`Geo ID`", "Geo"), col_alias("Geo ID", "Geo"));
+
+        // names which only differ in replaced chars have different aliases
+        let aliases = vec![
+            col_alias("my col", "Geo"),
+            col_alias("my_col", "Geo"),
+            col_alias("my-col", "Geo"),
+            col_alias("a-b", "Geo"),
+            col_alias("a_b", "Geo"),
+        ];
+        for (i, alias) in aliases.iter().enumerate() {
+            assert!(alias.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+            assert!(!aliases[i + 1..].contains(alias), "duplicate alias {}", alias);
+        }
+    }
+
+    #[test]
+    fn test_member_params() {
+        assert_eq!(SqlParam::from_member("3", &MemberType::NonText), SqlParam::Int(3));
+        assert_eq!(SqlParam::from_member("0.5", &MemberType::NonText), SqlParam::Float(0.5));
+        assert_eq!(SqlParam::from_member("3 or 1=1", &MemberType::NonText), SqlParam::Text("3 or 1=1".into()));
+        assert_eq!(SqlParam::from_member("NaN", &MemberType::NonText), SqlParam::Text("NaN".into()));
+        assert_eq!(SqlParam::from_member("3", &MemberType::Text), SqlParam::Text("3".into()));
+    }
 }
 
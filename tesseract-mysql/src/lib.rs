@@ -1,6 +1,8 @@
 use failure::{Error, format_err};
 use futures::future::Future;
-use tesseract_core::{Backend, DataFrame};
+use tesseract_core::{Backend, BoundSql, DataFrame, IdentifierStyle, LiteralStyle, QueryIr, SqlParam};
+use tesseract_core::query_ir::text_literal;
+use tesseract_core::schema::Table;
 
 extern crate futures;
 extern crate mysql_async as my;
@@ -27,9 +29,16 @@ impl MySql {
 
 impl Backend for MySql {
     fn exec_sql(&self, sql: String) -> Box<Future<Item=DataFrame, Error=Error>> {
+        self.exec_bound_sql(BoundSql::unbound(sql))
+    }
+
+    fn exec_bound_sql(&self, bound_sql: BoundSql) -> Box<dyn Future<Item=DataFrame, Error=Error>> {
+        let BoundSql { sql, params } = bound_sql;
+        let params = mysql_params(params);
+
         let future = self.pool.get_conn()
             .and_then(move |conn| {
-                conn.prep_exec(sql.to_string(), ())
+                conn.prep_exec(sql, params)
             })
             .map_err(|e| {
                 format_err!("{}", e.description().to_string())
@@ -43,6 +52,68 @@ impl Backend for MySql {
     fn box_clone(&self) -> Box<dyn Backend + Send + Sync> {
         Box::new((*self).clone())
     }
+
+    fn identifier_style(&self) -> IdentifierStyle {
+        IdentifierStyle::Backtick
+    }
+
+    fn literal_style(&self) -> LiteralStyle {
+        LiteralStyle::Backslash
+    }
+
+    /// Cut members are bound as parameters. Each cut is written once in the
+    /// sql, in order, so parameters match the `?` placeholders.
+    fn generate_bound_sql(&self, mut query_ir: QueryIr) -> BoundSql {
+        let params = query_ir.bind_cut_members(|_, _| "?".to_owned());
+
+        BoundSql {
+            sql: self.generate_sql(query_ir),
+            params,
+        }
+    }
+
+    fn table_columns_sql(&self, table: &Table) -> Option<String> {
+        let table_schema = match table.schema {
+            Some(ref schema) => text_literal(schema, self.literal_style()),
+            None => "database()".to_owned(),
+        };
+
+        Some(format!("select column_name, data_type from information_schema.columns where table_schema = {} and table_name = {} order by ordinal_position",
+            table_schema,
+            text_literal(&table.name, self.literal_style()),
+        ))
+    }
+
+    fn foreign_keys_sql(&self, table: &Table) -> Option<String> {
+        let table_schema = match table.schema {
+            Some(ref schema) => text_literal(schema, self.literal_style()),
+            None => "database()".to_owned(),
+        };
+
+        Some(format!("select column_name, referenced_table_schema, referenced_table_name, referenced_column_name \
+            from information_schema.key_column_usage \
+            where table_schema = {} and table_name = {} and referenced_table_name is not null",
+            table_schema,
+            text_literal(&table.name, self.literal_style()),
+        ))
+    }
+}
+
+/// Positional parameters for the `?` placeholders of bound sql.
+fn mysql_params(params: Vec<SqlParam>) -> my::Params {
+    if params.is_empty() {
+        return my::Params::Empty;
+    }
+
+    let values = params.into_iter()
+        .map(|param| match param {
+            SqlParam::Int(n) => my::Value::Int(n),
+            SqlParam::Float(n) => my::Value::Float(n),
+            SqlParam::Text(s) => my::Value::Bytes(s.into_bytes()),
+        })
+        .collect();
+
+    my::Params::Positional(values)
 }
 
 
@@ -62,4 +133,27 @@ mod tests {
         let r = mysql.exec_sql(sql.to_string()).wait().unwrap();
         println!("{:?}", r);
     }
+
+    #[test]
+    fn test_mysql_params() {
+        match mysql_params(vec![]) {
+            my::Params::Empty => (),
+            params => panic!("expected no params, found {:?}", params),
+        }
+
+        let params = mysql_params(vec![
+            SqlParam::Int(1),
+            SqlParam::Float(0.5),
+            SqlParam::Text("O'Brien".to_owned()),
+        ]);
+
+        match params {
+            my::Params::Positional(values) => assert_eq!(values.to_vec(), vec![
+                my::Value::Int(1),
+                my::Value::Float(0.5),
+                my::Value::Bytes(b"O'Brien".to_vec()),
+            ]),
+            params => panic!("expected positional params, found {:?}", params),
+        }
+    }
 }
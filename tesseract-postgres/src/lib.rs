@@ -1,7 +1,10 @@
 use failure::{Error, format_err};
-use tesseract_core::{Backend, DataFrame};
+use tesseract_core::{Backend, BoundSql, DataFrame, QueryIr, SqlParam};
+use tesseract_core::query_ir::{quote_identifier, text_literal};
+use tesseract_core::schema::{DateLevel, DatePart, Table};
 use futures::{Future, Stream};
 use tokio_postgres::NoTls;
+use tokio_postgres::types::ToSql;
 extern crate futures;
 extern crate tokio_postgres;
 extern crate bb8;
@@ -56,10 +59,24 @@ impl Postgres {
 
 impl Backend for Postgres {
     fn exec_sql(&self, sql: String) -> Box<Future<Item=DataFrame, Error=Error>> {
+        self.exec_bound_sql(BoundSql::unbound(sql))
+    }
+
+    fn exec_bound_sql(&self, bound_sql: BoundSql) -> Box<dyn Future<Item=DataFrame, Error=Error>> {
+        let BoundSql { sql, params } = bound_sql;
+
         let fut = self.pool.run(move |mut connection| {
-            connection.prepare(&sql).then( |r| match r {
+            connection.prepare(&sql).then(move |r| match r {
                 Ok(select) => {
-                    let f = connection.query(&select, &[])
+                    let param_refs: Vec<&dyn ToSql> = params.iter()
+                        .map(|param| match param {
+                            SqlParam::Int(n) => n as &dyn ToSql,
+                            SqlParam::Float(n) => n as &dyn ToSql,
+                            SqlParam::Text(s) => s as &dyn ToSql,
+                        })
+                        .collect();
+
+                    let f = connection.query(&select, &param_refs)
                         .collect()
                         .then(move |r| {
                             let df = rows_to_df(r.expect("Unable to retrieve rows"), select.columns());
@@ -76,6 +93,64 @@ impl Backend for Postgres {
     fn box_clone(&self) -> Box<dyn Backend + Send + Sync> {
         Box::new((*self).clone())
     }
+
+    /// Cut members are bound as parameters, cast to the type they were parsed
+    /// as, since postgres won't convert parameters to the column's type.
+    fn generate_bound_sql(&self, mut query_ir: QueryIr) -> BoundSql {
+        let params = query_ir.bind_cut_members(|idx, param| {
+            match param {
+                SqlParam::Int(_) => format!("${}::bigint", idx),
+                SqlParam::Float(_) => format!("${}::double precision", idx),
+                SqlParam::Text(_) => format!("${}::text", idx),
+            }
+        });
+
+        BoundSql {
+            sql: self.generate_sql(query_ir),
+            params,
+        }
+    }
+
+    fn date_level_sql(&self, date_level: &DateLevel) -> String {
+        let format = match date_level.part {
+            DatePart::Year => "YYYY",
+            DatePart::Quarter => "YYYYQ",
+            DatePart::Month => "YYYYMM",
+            DatePart::Day => "YYYYMMDD",
+        };
+
+        format!("CAST(to_char({}, '{}') AS INTEGER)", quote_identifier(&date_level.date_column, self.identifier_style()), format)
+    }
+
+    fn table_columns_sql(&self, table: &Table) -> Option<String> {
+        let table_schema = match table.schema {
+            Some(ref schema) => text_literal(schema, self.literal_style()),
+            None => "current_schema()".to_owned(),
+        };
+
+        // information_schema types (e.g. sql_identifier) aren't converted to
+        // dataframe columns, so cast to text
+        Some(format!("select column_name::text, data_type::text from information_schema.columns where table_schema = {} and table_name = {} order by ordinal_position",
+            table_schema,
+            text_literal(&table.name, self.literal_style()),
+        ))
+    }
+
+    fn foreign_keys_sql(&self, table: &Table) -> Option<String> {
+        let table_schema = match table.schema {
+            Some(ref schema) => text_literal(schema, self.literal_style()),
+            None => "current_schema()".to_owned(),
+        };
+
+        Some(format!("select kcu.column_name::text, ccu.table_schema::text, ccu.table_name::text, ccu.column_name::text \
+            from information_schema.table_constraints tc \
+            inner join information_schema.key_column_usage kcu on tc.constraint_name = kcu.constraint_name and tc.table_schema = kcu.table_schema \
+            inner join information_schema.constraint_column_usage ccu on tc.constraint_name = ccu.constraint_name and tc.table_schema = ccu.table_schema \
+            where tc.constraint_type = 'FOREIGN KEY' and tc.table_schema = {} and tc.table_name = {}",
+            table_schema,
+            text_literal(&table.name, self.literal_style()),
+        ))
+    }
 }
 
 
@@ -0,0 +1,214 @@
+//! API keys, for consumers which can't mint JWTs (batch scripts, partner
+//! servers...).
+//!
+//! Keys are read from a json config file, which only holds the sha256 of
+//! each key. A key has an auth level, which is checked like the auth level of
+//! a JWT, and can be limited to some cubes, expire, and have a request quota.
+//!
+//! Quotas are counted in memory, per server, in fixed windows of `period`
+//! seconds (e.g. 1000 requests per day), so they start over when the server
+//! restarts.
+
+use failure::{Error, format_err, bail};
+use serde_derive::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+
+pub const X_TESSERACT_API_KEY: &str = "x-tesseract-api-key";
+
+#[derive(Debug, Deserialize)]
+struct ApiKeysConfig {
+    keys: Vec<ApiKey>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ApiKey {
+    /// Identifies the key in logs and errors, since the key itself is secret
+    pub name: String,
+    /// Hex sha256 of the key
+    pub key_hash: String,
+    pub auth_level: i32,
+    /// Cubes the key can access; all cubes if not set
+    pub cubes: Option<Vec<String>>,
+    /// Unix timestamp, in seconds
+    pub expires: Option<u64>,
+    pub quota: Option<Quota>,
+}
+
+impl ApiKey {
+    pub fn allows_cube(&self, cube_name: &str) -> bool {
+        self.cubes.as_ref()
+            .map(|cubes| cubes.iter().any(|cube| cube == cube_name))
+            .unwrap_or(true)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Quota {
+    pub requests: u64,
+    /// Length of a quota window, in seconds
+    pub period: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApiKeyError {
+    Invalid,
+    Expired,
+    /// Seconds until the quota window ends
+    QuotaExceeded { retry_after: u64 },
+}
+
+#[derive(Debug)]
+pub struct ApiKeys {
+    keys: Vec<ApiKey>,
+    /// Key name to the current quota window and its request count
+    usage: Mutex<HashMap<String, (u64, u64)>>,
+}
+
+impl ApiKeys {
+    pub fn read(path: &str) -> Result<Self, Error> {
+        let json = fs::read_to_string(path)
+            .map_err(|err| format_err!("Could not read API keys {}: {}", path, err))?;
+
+        ApiKeys::from_json(&json)
+            .map_err(|err| format_err!("Invalid API keys {}: {}", path, err))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let config: ApiKeysConfig = serde_json::from_str(json)?;
+
+        let mut names = HashSet::new();
+        let mut keys = vec![];
+
+        for mut key in config.keys {
+            if !names.insert(key.name.clone()) {
+                bail!("Duplicate API key name: {}", key.name);
+            }
+
+            key.key_hash = key.key_hash.to_lowercase();
+            let is_sha256 = key.key_hash.len() == 64
+                && key.key_hash.chars().all(|c| c.is_ascii_hexdigit());
+            if !is_sha256 {
+                bail!("API key {}: key_hash must be a hex sha256", key.name);
+            }
+
+            if let Some(ref quota) = key.quota {
+                if quota.requests == 0 || quota.period == 0 {
+                    bail!("API key {}: quota requests and period must be positive", key.name);
+                }
+            }
+
+            keys.push(key);
+        }
+
+        Ok(ApiKeys {
+            keys,
+            usage: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Finds the key, checks that it's not expired, and counts a request
+    /// against its quota.
+    pub fn authenticate(&self, raw_key: &str) -> Result<&ApiKey, ApiKeyError> {
+        self.authenticate_at(raw_key, unix_now())
+    }
+
+    /// Finds the key, without checking or counting anything, e.g. to identify
+    /// a client.
+    pub fn find(&self, raw_key: &str) -> Option<&ApiKey> {
+        let key_hash = hash_key(raw_key);
+
+        self.keys.iter().find(|key| key.key_hash == key_hash)
+    }
+
+    fn authenticate_at(&self, raw_key: &str, now: u64) -> Result<&ApiKey, ApiKeyError> {
+        let key = self.find(raw_key).ok_or(ApiKeyError::Invalid)?;
+
+        if key.expires.map(|expires| now >= expires).unwrap_or(false) {
+            return Err(ApiKeyError::Expired);
+        }
+
+        if let Some(ref quota) = key.quota {
+            let window = now / quota.period;
+
+            let mut usage = self.usage.lock().unwrap();
+            let (usage_window, count) = usage.entry(key.name.clone()).or_insert((window, 0));
+
+            if *usage_window != window {
+                *usage_window = window;
+                *count = 0;
+            }
+
+            if *count >= quota.requests {
+                return Err(ApiKeyError::QuotaExceeded {
+                    retry_after: (window + 1) * quota.period - now,
+                });
+            }
+
+            *count += 1;
+        }
+
+        Ok(key)
+    }
+}
+
+/// Hex sha256 of a key, as in the `key_hash` of the config (the same as
+/// `printf '%s' <key> | sha256sum`).
+pub fn hash_key(raw_key: &str) -> String {
+    ring::digest::digest(&ring::digest::SHA256, raw_key.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_authenticate() {
+        // sha256 of "batch-key" and "partner-key" (case doesn't matter)
+        let api_keys = ApiKeys::from_json(r#"{ "keys": [
+            {
+                "name": "batch",
+                "key_hash": "9d8db2a67d146638c07fbb5652ad998062e2e2fa857eef026c40c444b07e3872",
+                "auth_level": 1,
+                "quota": { "requests": 2, "period": 60 }
+            },
+            {
+                "name": "partner",
+                "key_hash": "346E50AF211B5135824BB2BB58FE0F9E6DF228ADCF10C58A37FBC46B57BAEE74",
+                "auth_level": 2,
+                "cubes": ["sales"],
+                "expires": 1000
+            }
+        ] }"#).unwrap();
+
+        assert_eq!(api_keys.authenticate_at("unknown", 0), Err(ApiKeyError::Invalid));
+
+        let partner = api_keys.authenticate_at("partner-key", 999).unwrap();
+        assert_eq!(partner.auth_level, 2);
+        assert!(partner.allows_cube("sales"));
+        assert!(!partner.allows_cube("finance"));
+        assert_eq!(api_keys.authenticate_at("partner-key", 1000), Err(ApiKeyError::Expired));
+
+        // 2 requests per minute
+        assert!(api_keys.authenticate_at("batch-key", 100).is_ok());
+        assert!(api_keys.authenticate_at("batch-key", 110).is_ok());
+        assert_eq!(api_keys.authenticate_at("batch-key", 115), Err(ApiKeyError::QuotaExceeded { retry_after: 5 }));
+        assert!(api_keys.authenticate_at("batch-key", 120).is_ok());
+
+        assert!(ApiKeys::from_json(r#"{ "keys": [{ "name": "a", "key_hash": "plain-key", "auth_level": 0 }] }"#).is_err());
+    }
+}
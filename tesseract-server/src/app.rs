@@ -3,8 +3,14 @@ use actix_web::{
     middleware,
     App,
     http::NormalizePath,
+    pred::Predicate,
+    server::Request,
 };
 use tesseract_core::{Backend, Schema, CubeHasUniqueLevelsAndProperties};
+use crate::api_keys::ApiKeys;
+use crate::audit::AuditLog;
+use crate::auth::JwtConfig;
+use crate::cors::CorsConfig;
 use crate::db_config::Database;
 use crate::handlers::{
     aggregate_handler,
@@ -19,10 +25,14 @@ use crate::handlers::{
     logic_layer_non_unique_levels_default_handler,
     logic_layer_members_handler,
     logic_layer_members_default_handler,
+    admin_schema_handler,
+    admin_schema_put_handler,
+    admin_schema_rollback_handler,
     flush_handler,
     index_handler,
     metadata_handler,
     metadata_all_handler,
+    metrics_handler,
     members_handler,
     members_default_handler,
     logic_layer_relations_handler,
@@ -31,8 +41,12 @@ use crate::handlers::{
     logic_layer_relations_non_unique_levels_handler
 };
 use crate::logic_layer::{Cache, LogicLayerConfig};
+use crate::middleware::RateLimit;
+use crate::rate_limit::RateLimiter;
+use crate::tenant::{SchemaVersions, TenantRoute, host_matches};
+use crate::tls::TlsCerts;
 
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 use url::Url;
 
 
@@ -40,8 +54,19 @@ use url::Url;
 #[derive(Debug, Clone)]
 pub enum SchemaSource {
     LocalSchema { filepath: String },
-    #[allow(dead_code)]
     RemoteSchema { endpoint: String },
+    /// Schema stored in the `tesseract_schemas` table of the database
+    DatabaseSchema { name: String },
+}
+
+impl std::fmt::Display for SchemaSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SchemaSource::LocalSchema { filepath } => write!(f, "{}", filepath),
+            SchemaSource::RemoteSchema { endpoint } => write!(f, "{}", endpoint),
+            SchemaSource::DatabaseSchema { name } => write!(f, "database, {}", name),
+        }
+    }
 }
 
 /// Holds a struct of environment variables that will be accessed through the `AppState`.
@@ -50,8 +75,15 @@ pub struct EnvVars {
     pub database_url: String,
     pub geoservice_url: Option<Url>,
     pub schema_source: SchemaSource,
-    pub jwt_secret: Option<String>,
+    pub jwt: Option<JwtConfig>,
+    pub api_keys: Option<Arc<ApiKeys>>,
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    pub cors: Option<CorsConfig>,
+    pub tls: Option<Arc<TlsCerts>>,
+    pub audit_log: Option<Arc<AuditLog>>,
     pub flush_secret: Option<String>,
+    pub admin_auth_level: Option<i32>,
+    pub schema_validation: Option<String>,
 }
 
 /// Holds [ActixWeb State](https://actix.rs/docs/application/).
@@ -69,34 +101,64 @@ pub struct AppState {
     // capture, but the handlers need to implement Fn, not FnOnce (which happens once capturing
     // variables from environment
     pub has_unique_levels_properties: CubeHasUniqueLevelsAndProperties,
+    /// Schemas activated through the admin api, for rollback
+    pub schema_versions: Arc<Mutex<SchemaVersions>>,
+}
+
+/// Routes requests to a tenant app by Host, see `tenant::host_matches`.
+struct TenantHost(String);
+
+impl<S: 'static> Predicate<S> for TenantHost {
+    fn check(&self, req: &Request, _: &S) -> bool {
+        host_matches(&self.0, req.connection_info().host())
+    }
 }
 
-/// Creates an ActixWeb application with an `AppState`.
+/// Creates an ActixWeb application with only the status route, for health
+/// checks over plain HTTP when the API is served over HTTPS.
+pub fn create_health_app() -> App<()> {
+    App::new()
+        .resource("/", |r| {
+            r.method(Method::GET).with(index_handler)
+        })
+}
+
+/// Creates an ActixWeb application with an `AppState`, routed to by `route`.
 pub fn create_app(
-        debug: bool,
-        backend: Box<dyn Backend + Sync + Send>,
-        db_type: Database,
-        env_vars: EnvVars,
-        schema: Arc<RwLock<Schema>>,
-        cache: Arc<RwLock<Cache>>,
-        logic_layer_config: Option<Arc<RwLock<LogicLayerConfig>>>,
+        state: AppState,
+        route: &TenantRoute,
         streaming_response: bool,
-        has_unique_levels_properties: CubeHasUniqueLevelsAndProperties,
     ) -> App<AppState>
 {
-    let app = App::with_state(
-            AppState {
-                debug,
-                backend,
-                db_type,
-                env_vars,
-                schema,
-                cache,
-                logic_layer_config,
-                has_unique_levels_properties: has_unique_levels_properties.clone(),
-        })
+    let has_unique_levels_properties = state.has_unique_levels_properties.clone();
+    let rate_limiter = state.env_vars.rate_limiter.clone();
+    let cors = state.env_vars.cors.clone();
+
+    let app = App::with_state(state);
+
+    // Route must be set before resources are registered
+    let app = match route {
+        TenantRoute::Root => app,
+        TenantRoute::Prefix(prefix) => app.prefix(prefix.as_str()),
+        TenantRoute::Host(host) => app.filter(TenantHost(host.clone())),
+    };
+
+    let app = app
         .middleware(middleware::Logger::default())
-        .middleware(middleware::DefaultHeaders::new().header("Vary", "Accept-Encoding"))
+        .middleware(middleware::DefaultHeaders::new().header("Vary", "Accept-Encoding"));
+
+    // Before rate limiting, so that preflight requests aren't counted
+    let app = match cors {
+        Some(cors) => app.middleware(cors.middleware()),
+        None => app,
+    };
+
+    let app = match rate_limiter {
+        Some(rate_limiter) => app.middleware(RateLimit(rate_limiter)),
+        None => app,
+    };
+
+    let app = app
 
         // Metadata
         .resource("/", |r| {
@@ -125,9 +187,22 @@ pub fn create_app(
             r.method(Method::GET).with(diagnosis_handler)
         })
 
+        .resource("/metrics", |r| {
+            r.method(Method::GET).with(metrics_handler)
+        })
+
         .resource("/flush", |r| {
             r.method(Method::POST).with(flush_handler)
         })
+
+        // Admin
+        .resource("/admin/schema", |r| {
+            r.method(Method::GET).with(admin_schema_handler);
+            r.method(Method::PUT).with(admin_schema_put_handler);
+        })
+        .resource("/admin/schema/rollback", |r| {
+            r.method(Method::POST).with(admin_schema_rollback_handler)
+        })
         // Allow the API to accept /my-path or /my-path/ for all requests
         .default_resource(|r| r.h(NormalizePath::default()));
 
@@ -0,0 +1,341 @@
+//! Audit log of data access: who queried which cube, with which query, and
+//! how many rows came back.
+//!
+//! Entries are written as JSON lines to a file, which is rotated when it
+//! reaches a maximum size (`audit.log` -> `audit.log.1` -> `audit.log.2`...).
+//! Users are identified by their JWT `sub` claim or API key name; tokens and
+//! keys are never written.
+
+use failure::{Error, format_err};
+use log::*;
+use serde_derive::Serialize;
+use std::collections::{BTreeSet, HashSet};
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tesseract_core::Query as TsQuery;
+use tesseract_core::names::LevelName;
+
+
+const DEFAULT_MAX_SIZE: u64 = 100 * 1024 * 1024;
+const DEFAULT_MAX_FILES: usize = 10;
+
+#[derive(Debug)]
+pub struct AuditLog {
+    file: Mutex<RotatingFile>,
+    /// Cubes to audit; all cubes if None
+    cubes: Option<HashSet<String>>,
+}
+
+impl AuditLog {
+    /// Reads the audit log env vars. None if `TESSERACT_AUDIT_LOG_FILEPATH` is
+    /// not set.
+    pub fn from_env() -> Result<Option<Self>, Error> {
+        let path = match env::var("TESSERACT_AUDIT_LOG_FILEPATH") {
+            Ok(path) => path,
+            Err(_) => return Ok(None),
+        };
+
+        let max_size = match env::var("TESSERACT_AUDIT_LOG_MAX_SIZE") {
+            Ok(size) => size.parse::<u64>()
+                .map_err(|_| format_err!("could not parse bytes from env_var TESSERACT_AUDIT_LOG_MAX_SIZE"))?,
+            Err(_) => DEFAULT_MAX_SIZE,
+        };
+
+        let max_files = match env::var("TESSERACT_AUDIT_LOG_MAX_FILES") {
+            Ok(files) => files.parse::<usize>()
+                .map_err(|_| format_err!("could not parse number from env_var TESSERACT_AUDIT_LOG_MAX_FILES"))?,
+            Err(_) => DEFAULT_MAX_FILES,
+        };
+
+        let cubes = env::var("TESSERACT_AUDIT_LOG_CUBES").ok()
+            .map(|cubes| {
+                cubes.split(',')
+                    .map(|cube| cube.trim().to_owned())
+                    .filter(|cube| !cube.is_empty())
+                    .collect()
+            });
+
+        Ok(Some(AuditLog::new(&path, max_size, max_files, cubes)?))
+    }
+
+    pub fn new(path: &str, max_size: u64, max_files: usize, cubes: Option<HashSet<String>>) -> Result<Self, Error> {
+        Ok(AuditLog {
+            file: Mutex::new(RotatingFile::open(path, max_size, max_files)?),
+            cubes,
+        })
+    }
+
+    pub fn audits_cube(&self, cube_name: &str) -> bool {
+        self.cubes.as_ref()
+            .map(|cubes| cubes.contains(cube_name))
+            .unwrap_or(true)
+    }
+
+    /// Starts an entry, which is written when the last clone of the record is
+    /// dropped, e.g. after a streamed response is sent.
+    pub fn record(log: &Arc<Self>, entry: AuditEntry) -> AuditRecord {
+        AuditRecord(Some(Arc::new(Mutex::new(PendingEntry {
+            log: log.clone(),
+            entry,
+            start: Instant::now(),
+        }))))
+    }
+
+    fn write(&self, entry: &AuditEntry) {
+        let line = match serde_json::to_string(entry) {
+            Ok(line) => line,
+            Err(err) => {
+                error!("Could not serialize audit entry: {}", err);
+                return;
+            },
+        };
+
+        if let Err(err) = self.file.lock().unwrap().write_line(&line) {
+            error!("Could not write audit log: {}", err);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+    /// Unix time, in milliseconds
+    pub timestamp: u64,
+    /// JWT `sub` claim
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// API key name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    pub endpoint: String,
+    pub cube: String,
+    pub query: AuditQuery,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rows: Option<usize>,
+    pub status: u16,
+    pub latency_ms: u64,
+}
+
+impl AuditEntry {
+    pub fn new(endpoint: &str, cube: &str, query: AuditQuery) -> Self {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() * 1000 + u64::from(d.subsec_millis()))
+            .unwrap_or(0);
+
+        AuditEntry {
+            timestamp,
+            user: None,
+            api_key: None,
+            endpoint: endpoint.to_owned(),
+            cube: cube.to_owned(),
+            query,
+            rows: None,
+            status: 200,
+            latency_ms: 0,
+        }
+    }
+}
+
+/// The query, with names in their canonical form and sorted, so that the
+/// same query always gets the same entry.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct AuditQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<String>,
+    pub drilldowns: Vec<String>,
+    pub cuts: Vec<String>,
+    pub measures: Vec<String>,
+    pub properties: Vec<String>,
+}
+
+impl AuditQuery {
+    pub fn from_query(query: &TsQuery) -> Self {
+        AuditQuery::from_queries(std::iter::once(query))
+    }
+
+    /// One entry for the queries of a single request, e.g. the queries for
+    /// each combination of cuts of a logic layer request.
+    pub fn from_queries<'a>(queries: impl IntoIterator<Item=&'a TsQuery>) -> Self {
+        let mut drilldowns = BTreeSet::new();
+        let mut cuts = BTreeSet::new();
+        let mut measures = BTreeSet::new();
+        let mut properties = BTreeSet::new();
+
+        for query in queries {
+            drilldowns.extend(query.drilldowns.iter().map(|d| d.to_string()));
+            cuts.extend(query.cuts.iter().map(|c| c.to_string()));
+            measures.extend(query.measures.iter().map(|m| m.to_string()));
+            properties.extend(query.properties.iter().map(|p| p.to_string()));
+        }
+
+        AuditQuery {
+            level: None,
+            drilldowns: drilldowns.into_iter().collect(),
+            cuts: cuts.into_iter().collect(),
+            measures: measures.into_iter().collect(),
+            properties: properties.into_iter().collect(),
+        }
+    }
+
+    pub fn from_level(level_name: &LevelName) -> Self {
+        AuditQuery {
+            level: Some(level_name.to_string()),
+            ..AuditQuery::default()
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PendingEntry {
+    log: Arc<AuditLog>,
+    entry: AuditEntry,
+    start: Instant,
+}
+
+impl Drop for PendingEntry {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        self.entry.latency_ms = elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis());
+        self.log.write(&self.entry);
+    }
+}
+
+/// An entry being recorded; clones can be moved into the futures or stream
+/// of a response. Does nothing if the request is not audited.
+#[derive(Debug, Clone)]
+pub struct AuditRecord(Option<Arc<Mutex<PendingEntry>>>);
+
+impl AuditRecord {
+    pub fn none() -> Self {
+        AuditRecord(None)
+    }
+
+    pub fn set_status(&self, status: u16) {
+        if let Some(ref pending) = self.0 {
+            pending.lock().unwrap().entry.status = status;
+        }
+    }
+
+    pub fn add_rows(&self, rows: usize) {
+        if let Some(ref pending) = self.0 {
+            let mut pending = pending.lock().unwrap();
+            pending.entry.rows = Some(pending.entry.rows.unwrap_or(0) + rows);
+        }
+    }
+}
+
+/// Appends lines to a file, renaming it to `<path>.1` (and older files to
+/// `<path>.2`...) when it would go over `max_size`.
+#[derive(Debug)]
+struct RotatingFile {
+    path: String,
+    max_size: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: &str, max_size: u64, max_files: usize) -> Result<Self, Error> {
+        let file = OpenOptions::new().create(true).append(true).open(path)
+            .map_err(|err| format_err!("Could not open audit log {}: {}", path, err))?;
+        let size = file.metadata()?.len();
+
+        Ok(RotatingFile {
+            path: path.to_owned(),
+            max_size,
+            max_files,
+            file,
+            size,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<(), Error> {
+        let len = line.len() as u64 + 1;
+
+        if self.size > 0 && self.size + len > self.max_size {
+            self.rotate()?;
+        }
+
+        writeln!(self.file, "{}", line)?;
+        self.size += len;
+
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<(), Error> {
+        if self.max_files > 0 {
+            // the oldest file is overwritten
+            for i in (1..self.max_files).rev() {
+                let from = format!("{}.{}", self.path, i);
+                if fs::metadata(&from).is_ok() {
+                    fs::rename(&from, format!("{}.{}", self.path, i + 1))?;
+                }
+            }
+            fs::rename(&self.path, format!("{}.1", self.path))?;
+        } else {
+            fs::remove_file(&self.path)?;
+        }
+
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_audit_log() {
+        let dir = env::temp_dir().join(format!("tesseract-audit-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("audit.log").to_str().unwrap().to_owned();
+
+        let cubes = Some(vec!["sales".to_owned()].into_iter().collect());
+        let log = Arc::new(AuditLog::new(&path, 400, 2, cubes).unwrap());
+        assert!(log.audits_cube("sales"));
+        assert!(!log.audits_cube("finance"));
+
+        let mut query = TsQuery::new();
+        query.drilldowns = vec!["[Geo].[Geo].[State]".parse().unwrap(), "[Date].[Date].[Year]".parse().unwrap()];
+        query.cuts = vec!["[Date].[Date].[Year].&[2019]".parse().unwrap()];
+        query.measures = vec!["Quantity".parse().unwrap()];
+
+        for _ in 0..3 {
+            let mut entry = AuditEntry::new("/cubes/sales/aggregate", "sales", AuditQuery::from_query(&query));
+            entry.user = Some("user-1".to_owned());
+
+            let record = AuditLog::record(&log, entry);
+            let stream_record = record.clone();
+            stream_record.add_rows(10);
+            stream_record.add_rows(5);
+            drop(record);
+            drop(stream_record);
+        }
+
+        // each entry is over 200 bytes, so each file holds one entry, and
+        // only the last two files are kept
+        let current = fs::read_to_string(&path).unwrap();
+        let previous = fs::read_to_string(format!("{}.1", path)).unwrap();
+        assert_eq!(current.lines().count(), 1);
+        assert_eq!(previous.lines().count(), 1);
+        assert!(fs::metadata(format!("{}.2", path)).is_ok());
+        assert!(fs::metadata(format!("{}.3", path)).is_err());
+
+        let entry: serde_json::Value = serde_json::from_str(current.trim()).unwrap();
+        assert_eq!(entry["user"], "user-1");
+        assert_eq!(entry["rows"], 15);
+        assert_eq!(entry["status"], 200);
+        assert_eq!(entry["query"]["drilldowns"][0], "[Date].[Date].[Year]");
+        assert_eq!(entry["query"]["cuts"][0], "[Date].[Date].[Year].&[2019]");
+        assert!(entry.get("api_key").is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,380 @@
+//! Schema and logic layer config checks, for `tesseract-olap check-schema`.
+//!
+//! Runs the same checks as server startup (reading the schema, validation,
+//! unique level and property names), plus checks that logic layer aliases and
+//! named sets point at real cubes, levels and properties. Unlike startup,
+//! checks don't stop at the first problem, and problems are reported with the
+//! file and line of the definition they refer to (best effort, since the
+//! schema is merged before it's checked).
+
+use failure::{Error, bail};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use tesseract_core::{Schema, CubeHasUniqueLevelsAndProperties};
+use tesseract_core::names::{LevelName, Property};
+
+use crate::logic_layer::{LogicLayerConfig, read_config};
+use crate::schema_config::read_schema_files;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub file: Option<PathBuf>,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, "{}:{}: {}", file.display(), line, self.message),
+            (Some(file), None) => write!(f, "{}: {}", file.display(), self.message),
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Checks a schema and, optionally, a logic layer config against it.
+/// Returns all problems found; an empty list means the schema is ok.
+pub fn check_schema(schema_path: &str, logic_layer_config_path: Option<&str>) -> Vec<Problem> {
+    let (schema, file_paths) = match read_schema_files(schema_path) {
+        Ok(res) => res,
+        Err(err) => {
+            return vec![Problem {
+                file: Some(PathBuf::from(schema_path)),
+                line: None,
+                message: err.to_string(),
+            }];
+        },
+    };
+
+    let schema_files = SourceFiles::read(&file_paths);
+    let mut problems = vec![];
+
+    let schema = check_schema_cubes(schema, &schema_files, &mut problems);
+
+    if let Some(config_path) = logic_layer_config_path {
+        let config_files = SourceFiles::read(&[PathBuf::from(config_path)]);
+
+        match read_config(&config_path.to_owned()) {
+            Ok(config) => check_logic_layer_config(&config, &schema, &config_files, &mut problems),
+            Err(err) => problems.push(config_files.problem(&[], err.to_string())),
+        }
+    }
+
+    problems
+}
+
+/// `Schema::validate` and `Schema::has_unique_levels_properties` stop at the
+/// first problem, so they're run one cube at a time to report all of them.
+///
+/// Returns the schema with validated cubes, for the logic layer checks.
+fn check_schema_cubes(schema: Schema, files: &SourceFiles, problems: &mut Vec<Problem>) -> Schema {
+    let mut cubes = vec![];
+
+    for cube in &schema.cubes {
+        let mut cube_schema = Schema {
+            cubes: vec![cube.clone()],
+            ..schema.clone()
+        };
+
+        if let Err(err) = cube_schema.validate() {
+            problems.push(files.problem(&[&cube.name], format!("cube {}: {}", cube.name, err)));
+        }
+
+        if let CubeHasUniqueLevelsAndProperties::False { name, .. } = cube_schema.has_unique_levels_properties() {
+            // the repeated definition is the second one
+            problems.push(files.problem(
+                &[&cube.name, &name, &name],
+                format!("cube {}: level and property names must be unique, found repeated name {}", cube.name, name),
+            ));
+        }
+
+        cubes.extend(cube_schema.cubes);
+    }
+
+    Schema {
+        cubes,
+        ..schema
+    }
+}
+
+fn check_logic_layer_config(
+    config: &LogicLayerConfig,
+    schema: &Schema,
+    files: &SourceFiles,
+    problems: &mut Vec<Problem>,
+)
+{
+    for cube in &schema.cubes {
+        let cube_schema = Schema {
+            cubes: vec![cube.clone()],
+            ..schema.clone()
+        };
+
+        // already reported by the schema checks
+        if let CubeHasUniqueLevelsAndProperties::False { .. } = cube_schema.has_unique_levels_properties() {
+            continue;
+        }
+
+        match config.has_unique_levels_properties(&cube_schema) {
+            Ok(CubeHasUniqueLevelsAndProperties::False { name, .. }) => {
+                problems.push(files.problem(
+                    &[&cube.name, &name],
+                    format!("cube {}: unique names must be unique in the cube, found repeated name {}", cube.name, name),
+                ));
+            },
+            Ok(CubeHasUniqueLevelsAndProperties::True) => (),
+            Err(err) => problems.push(files.problem(&[&cube.name], format!("cube {}: {}", cube.name, err))),
+        }
+    }
+
+    let aliases = config.aliases.as_ref();
+
+    for cube_alias in aliases.and_then(|a| a.cubes.as_ref()).into_iter().flatten() {
+        let cube = match schema.get_cube_by_name(&cube_alias.name) {
+            Ok(cube) => cube,
+            Err(_) => {
+                problems.push(files.problem(&[&cube_alias.name], format!("aliases: cube {} not found in schema", cube_alias.name)));
+                continue;
+            },
+        };
+
+        for level in cube_alias.levels.iter().flatten() {
+            let found = parse_level_name(&level.current_name)
+                .map(|level_name| cube.get_level(&level_name).is_some());
+
+            if let Some(message) = not_found_message("level", &level.current_name, found) {
+                problems.push(files.problem(&[&cube_alias.name, &level.current_name], format!("aliases for cube {}: {}", cube.name, message)));
+            }
+        }
+
+        for property in cube_alias.properties.iter().flatten() {
+            let found = parse_property(&property.current_name)
+                .map(|property_name| has_property(&[cube], &property_name));
+
+            if let Some(message) = not_found_message("property", &property.current_name, found) {
+                problems.push(files.problem(&[&cube_alias.name, &property.current_name], format!("aliases for cube {}: {}", cube.name, message)));
+            }
+        }
+    }
+
+    for dim_alias in aliases.and_then(|a| a.shared_dimensions.as_ref()).into_iter().flatten() {
+        // cubes which use the shared dimension
+        let cubes: Vec<_> = schema.cubes.iter()
+            .filter(|cube| cube.dimensions.iter().any(|dim| dim.is_shared && dim.name == dim_alias.name))
+            .collect();
+
+        if cubes.is_empty() {
+            problems.push(files.problem(&[&dim_alias.name], format!("aliases: shared dimension {} not used by any cube", dim_alias.name)));
+            continue;
+        }
+
+        for level in dim_alias.levels.iter().flatten() {
+            let found = parse_level_name(&level.current_name)
+                .map(|level_name| cubes.iter().any(|cube| cube.get_level(&level_name).is_some()));
+
+            if let Some(message) = not_found_message("level", &level.current_name, found) {
+                problems.push(files.problem(&[&dim_alias.name, &level.current_name], format!("aliases for shared dimension {}: {}", dim_alias.name, message)));
+            }
+        }
+
+        for property in dim_alias.properties.iter().flatten() {
+            let found = parse_property(&property.current_name)
+                .map(|property_name| has_property(&cubes, &property_name));
+
+            if let Some(message) = not_found_message("property", &property.current_name, found) {
+                problems.push(files.problem(&[&dim_alias.name, &property.current_name], format!("aliases for shared dimension {}: {}", dim_alias.name, message)));
+            }
+        }
+    }
+
+    for named_set in config.named_sets.iter().flatten() {
+        let found = parse_level_name(&named_set.level_name)
+            .map(|level_name| schema.cubes.iter().any(|cube| cube.get_level(&level_name).is_some()));
+
+        if let Some(message) = not_found_message("level", &named_set.level_name, found) {
+            problems.push(files.problem(&[&named_set.level_name], format!("named sets: {}", message)));
+        }
+    }
+}
+
+fn has_property(cubes: &[&tesseract_core::schema::Cube], property_name: &Property) -> bool {
+    cubes.iter()
+        .filter_map(|cube| cube.get_level(&property_name.level_name))
+        .any(|level| {
+            level.properties.iter()
+                .flatten()
+                .any(|p| p.name == property_name.property)
+        })
+}
+
+fn not_found_message(kind: &str, name: &str, found: Result<bool, Error>) -> Option<String> {
+    match found {
+        Ok(true) => None,
+        Ok(false) => Some(format!("{} {} not found in schema", kind, name)),
+        Err(err) => Some(format!("could not parse {} name {}: {}", kind, name, err)),
+    }
+}
+
+// LevelName and Property parsing panics on empty strings
+fn parse_level_name(name: &str) -> Result<LevelName, Error> {
+    if name.is_empty() {
+        bail!("name is empty");
+    }
+    name.parse()
+}
+
+fn parse_property(name: &str) -> Result<Property, Error> {
+    if name.is_empty() {
+        bail!("name is empty");
+    }
+    name.parse()
+}
+
+
+/// Source files, to find the lines problems refer to.
+struct SourceFiles {
+    files: Vec<(PathBuf, Vec<String>)>,
+}
+
+impl SourceFiles {
+    fn read(file_paths: &[PathBuf]) -> Self {
+        let files = file_paths.iter()
+            .filter_map(|path| {
+                let contents = std::fs::read_to_string(path).ok()?;
+                Some((path.clone(), contents.lines().map(|l| l.to_owned()).collect()))
+            })
+            .collect();
+
+        SourceFiles { files }
+    }
+
+    /// Problem located at the definition of `names`, where each name is
+    /// looked for after the line of the previous one (e.g. cube, then level).
+    ///
+    /// Uses the file and line which matched the most names, since shared
+    /// dimensions and included files mean that a path isn't always in one
+    /// file. Without any match, only a single file is reported.
+    fn problem(&self, names: &[&str], message: String) -> Problem {
+        let mut best: Option<(&Path, usize, usize)> = None;
+
+        for (path, lines) in &self.files {
+            let mut start = 0;
+            let mut matched = 0;
+            let mut line_idx = None;
+
+            for name in names {
+                match lines[start..].iter().position(|line| defines_name(line, name)) {
+                    Some(pos) => {
+                        line_idx = Some(start + pos);
+                        start += pos + 1;
+                        matched += 1;
+                    },
+                    None => break,
+                }
+            }
+
+            if let Some(line_idx) = line_idx {
+                if best.map(|(_, _, best_matched)| matched > best_matched).unwrap_or(true) {
+                    best = Some((path, line_idx, matched));
+                }
+            }
+        }
+
+        match best {
+            Some((path, line_idx, _)) => Problem {
+                file: Some(path.to_owned()),
+                line: Some(line_idx + 1),
+                message,
+            },
+            None => Problem {
+                file: if self.files.len() == 1 { Some(self.files[0].0.clone()) } else { None },
+                line: None,
+                message,
+            },
+        }
+    }
+}
+
+/// Whether a line names `name`: `name="..."` or `source="..."` in xml, or a
+/// `"..."` string in json.
+fn defines_name(line: &str, name: &str) -> bool {
+    line.contains(&format!("name=\"{}\"", name)) ||
+        line.contains(&format!("source=\"{}\"", name)) ||
+        line.contains(&format!("\"{}\"", name))
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_check_schema() {
+        let schema = r##"<Schema name="test">
+    <Cube name="sales">
+        <Table name="sales" />
+        <Dimension name="Geo" foreign_key="geo_id">
+            <Hierarchy name="Geo">
+                <Table name="dim_geo" />
+                <Level name="Country" key_column="country_id" />
+                <Level name="Country" key_column="city_id" />
+            </Hierarchy>
+        </Dimension>
+        <Measure name="Quantity" column="quantity" aggregator="sum" />
+    </Cube>
+    <Cube name="trade">
+        <Table name="trade" />
+        <Dimension name="Year" foreign_key="year">
+            <Hierarchy name="Year">
+                <Level name="Year" key_column="year" />
+            </Hierarchy>
+            <Hierarchy name="Fiscal Year">
+                <Level name="Fiscal Year" key_column="fiscal_year" />
+            </Hierarchy>
+        </Dimension>
+        <Measure name="Value" column="value" aggregator="sum" />
+    </Cube>
+</Schema>
+"##;
+        let config = r##"{
+    "aliases": {
+        "cubes": [
+            {
+                "name": "trade",
+                "alternatives": [],
+                "levels": [
+                    { "current_name": "Year.Year.Month", "unique_name": "Month" }
+                ]
+            }
+        ]
+    },
+    "named_sets": [
+        { "level_name": "Geo.Geo.Country", "sets": [] }
+    ]
+}
+"##;
+        let dir = std::env::temp_dir().join("tesseract_check_schema");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("schema.xml"), schema).unwrap();
+        fs::write(dir.join("config.json"), config).unwrap();
+
+        let problems: Vec<_> = check_schema(
+                dir.join("schema.xml").to_str().unwrap(),
+                Some(dir.join("config.json").to_str().unwrap()),
+            )
+            .iter()
+            .map(|p| p.to_string().replace(&format!("{}/", dir.display()), ""))
+            .collect();
+
+        assert_eq!(problems, vec![
+            "schema.xml:8: cube sales: level and property names must be unique, found repeated name Country",
+            "schema.xml:13: cube trade: Default hierarchy required for multiple hierarchies in cube: trade dimension: Year",
+            "config.json:8: aliases for cube trade: level Year.Year.Month not found in schema",
+        ]);
+    }
+}
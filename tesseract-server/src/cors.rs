@@ -0,0 +1,119 @@
+use actix_web::http::Method;
+use actix_web::http::header::HeaderName;
+use actix_web::middleware::cors::Cors;
+use failure::{Error, format_err, bail};
+use std::env;
+
+
+const DEFAULT_METHODS: &str = "GET,HEAD";
+
+/// CORS policy, applied to all resources. Preflight `OPTIONS` requests are
+/// answered by the middleware.
+#[derive(Debug, Clone)]
+pub struct CorsConfig {
+    /// None allows any origin
+    allowed_origins: Option<Vec<String>>,
+    allowed_methods: Vec<Method>,
+    /// None allows any header
+    allowed_headers: Option<Vec<HeaderName>>,
+    /// In seconds
+    max_age: Option<usize>,
+    allow_credentials: bool,
+}
+
+impl CorsConfig {
+    /// Reads the CORS env vars. None if `TESSERACT_CORS_ALLOWED_ORIGINS` is not
+    /// set, which leaves CORS headers to a reverse proxy.
+    pub fn from_env() -> Result<Option<Self>, Error> {
+        let allowed_origins = match env::var("TESSERACT_CORS_ALLOWED_ORIGINS") {
+            Ok(ref origins) if origins.trim() == "*" => None,
+            Ok(origins) => Some(split_list(&origins)),
+            Err(_) => return Ok(None),
+        };
+
+        let allowed_methods = split_list(&env::var("TESSERACT_CORS_ALLOWED_METHODS")
+                .unwrap_or_else(|_| DEFAULT_METHODS.to_owned()))
+            .iter()
+            .map(|method| {
+                Method::from_bytes(method.to_uppercase().as_bytes())
+                    .map_err(|_| format_err!("Invalid CORS method: {}", method))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let allowed_headers = match env::var("TESSERACT_CORS_ALLOWED_HEADERS") {
+            Ok(ref headers) if headers.trim() == "*" => None,
+            Ok(headers) => {
+                let headers = split_list(&headers).iter()
+                    .map(|header| {
+                        HeaderName::from_bytes(header.as_bytes())
+                            .map_err(|_| format_err!("Invalid CORS header: {}", header))
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+                Some(headers)
+            },
+            Err(_) => None,
+        };
+
+        let max_age = match env::var("TESSERACT_CORS_MAX_AGE") {
+            Ok(secs) => Some(secs.parse::<usize>()
+                .map_err(|_| format_err!("could not parse seconds from env_var TESSERACT_CORS_MAX_AGE"))?),
+            Err(_) => None,
+        };
+
+        let allow_credentials = match env::var("TESSERACT_CORS_ALLOW_CREDENTIALS") {
+            Ok(credentials) => credentials.parse::<bool>()
+                .map_err(|_| format_err!("could not parse bool from env_var TESSERACT_CORS_ALLOW_CREDENTIALS"))?,
+            Err(_) => false,
+        };
+
+        // The origin would be echoed back, so any site could make requests
+        // with the user's credentials
+        if allow_credentials && allowed_origins.is_none() {
+            bail!("TESSERACT_CORS_ALLOW_CREDENTIALS needs a list of TESSERACT_CORS_ALLOWED_ORIGINS, not *");
+        }
+
+        Ok(Some(CorsConfig {
+            allowed_origins,
+            allowed_methods,
+            allowed_headers,
+            max_age,
+            allow_credentials,
+        }))
+    }
+
+    pub fn middleware(&self) -> Cors {
+        let mut cors = Cors::build();
+
+        match self.allowed_origins {
+            Some(ref origins) => {
+                for origin in origins {
+                    cors.allowed_origin(origin);
+                }
+            },
+            None => {
+                cors.send_wildcard();
+            },
+        }
+
+        cors.allowed_methods(self.allowed_methods.clone());
+
+        if let Some(ref headers) = self.allowed_headers {
+            cors.allowed_headers(headers.clone());
+        }
+        if let Some(max_age) = self.max_age {
+            cors.max_age(max_age);
+        }
+        if self.allow_credentials {
+            cors.supports_credentials();
+        }
+
+        cors.finish()
+    }
+}
+
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|item| item.trim().to_owned())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
@@ -0,0 +1,249 @@
+//! Admin api, to replace the schema of a running server.
+//!
+//! Authorized by the flush secret (`?secret=`), or by a JWT with at least
+//! TESSERACT_ADMIN_AUTH_LEVEL.
+
+use std::str;
+
+use actix_web::{
+    AsyncResponder,
+    FutureResponse,
+    HttpMessage,
+    HttpRequest,
+    HttpResponse,
+};
+use failure::{Error, format_err};
+use futures::future::Future;
+use log::*;
+use serde_derive::Serialize;
+
+use tesseract_core::Schema;
+
+use crate::app::AppState;
+use crate::auth::{extract_token, validate_web_token};
+use crate::schema_source;
+use crate::tenant;
+use super::util::boxed_error_http_response;
+
+
+/// Largest schema accepted in a request body
+const MAX_SCHEMA_SIZE: usize = 16 * 1024 * 1024;
+
+/// Source name of schemas uploaded through the admin api
+const UPLOAD_SOURCE: &str = "upload";
+
+#[derive(Debug, Serialize)]
+struct SchemaVersionInfo {
+    source: String,
+    hash: String,
+    /// Number of previous versions which can be restored by rollback
+    previous_versions: usize,
+}
+
+/// Whether the request has the flush secret, or an admin JWT. Without JWT
+/// auth or an admin auth level, only the flush secret is accepted.
+fn is_admin(req: &HttpRequest<AppState>) -> bool {
+    let env_vars = &req.state().env_vars;
+
+    if let (Some(flush_secret), Some(secret)) = (&env_vars.flush_secret, req.query().get("secret")) {
+        if secret == flush_secret {
+            return true;
+        }
+    }
+
+    match (&env_vars.jwt, env_vars.admin_auth_level) {
+        (Some(_), Some(admin_auth_level)) => {
+            // validate_web_token lets an empty token through at the default
+            // access level, which must never grant admin access
+            let token = extract_token(req);
+            !token.is_empty() && validate_web_token(&env_vars.jwt, &token, admin_auth_level)
+        },
+        _ => false,
+    }
+}
+
+fn version_info(state: &AppState) -> SchemaVersionInfo {
+    let versions = state.schema_versions.lock().unwrap();
+    let hash = tenant::schema_hash(&state.schema.read().unwrap());
+
+    SchemaVersionInfo {
+        source: versions.active_source(&hash, &state.env_vars.schema_source),
+        hash,
+        previous_versions: versions.previous.len(),
+    }
+}
+
+/// Returns the source and version hash of the active schema.
+pub fn admin_schema_handler(req: HttpRequest<AppState>) -> FutureResponse<HttpResponse> {
+    if !is_admin(&req) {
+        return boxed_error_http_response(HttpResponse::Unauthorized().finish());
+    }
+
+    boxed_error_http_response(HttpResponse::Ok().json(version_info(req.state())))
+}
+
+/// Replaces the schema with the JSON or XML schema in the request body.
+///
+/// The schema is validated (also against the database, if
+/// TESSERACT_SCHEMA_VALIDATION is set) and the cache is rebuilt before both
+/// are swapped in; otherwise the current schema keeps being served.
+pub fn admin_schema_put_handler(req: HttpRequest<AppState>) -> FutureResponse<HttpResponse> {
+    if !is_admin(&req) {
+        return boxed_error_http_response(HttpResponse::Unauthorized().finish());
+    }
+
+    let is_json = req.content_type().contains("json");
+
+    let state = req.state();
+    let backend = state.backend.clone();
+    let parse_backend = state.backend.clone();
+    let logic_layer_config = state.logic_layer_config.as_ref()
+        .map(|config| config.read().unwrap().clone());
+    let has_unique_levels_properties = state.has_unique_levels_properties.clone();
+    let schema_validation = state.env_vars.schema_validation.clone();
+
+    let schema_arc = state.schema.clone();
+    let cache_arc = state.cache.clone();
+    let versions_arc = state.schema_versions.clone();
+    let configured_source = state.env_vars.schema_source.clone();
+
+    req.body()
+        .limit(MAX_SCHEMA_SIZE)
+        .map_err(|err| format_err!("{}", err))
+        .and_then(move |body| -> Result<Schema, Error> {
+            let raw_schema = str::from_utf8(&body)?;
+
+            let mut schema = if is_json {
+                Schema::from_json(raw_schema)?
+            } else {
+                schema_source::parse_schema(raw_schema)?
+            };
+            schema.validate()?;
+            schema.resolve_date_levels(&*parse_backend);
+
+            Ok(schema)
+        })
+        .and_then(move |schema| {
+            tenant::run_in_system("tesseract-admin", move |sys| {
+                let cache = tenant::prepare_reload(
+                    &schema,
+                    &logic_layer_config,
+                    backend,
+                    &has_unique_levels_properties,
+                    &schema_validation,
+                    sys,
+                )?;
+
+                Ok((schema, cache))
+            })
+        })
+        .then(move |res| {
+            let response = match res {
+                Ok((schema, cache)) => {
+                    let (hash, previous_versions) = tenant::activate(
+                        &versions_arc,
+                        &schema_arc,
+                        &cache_arc,
+                        None,
+                        &configured_source,
+                        tenant::Reload {
+                            source: Some(UPLOAD_SOURCE.to_owned()),
+                            schema,
+                            cache,
+                            logic_layer_config: None,
+                        },
+                    );
+
+                    info!("Activated uploaded schema {}", hash);
+
+                    HttpResponse::Ok().json(SchemaVersionInfo {
+                        source: UPLOAD_SOURCE.to_owned(),
+                        hash,
+                        previous_versions,
+                    })
+                },
+                Err(err) => {
+                    warn!("Uploaded schema rejected: {}", err);
+                    HttpResponse::BadRequest().json(err.to_string())
+                },
+            };
+
+            Ok::<_, actix_web::Error>(response)
+        })
+        .responder()
+}
+
+/// Restores the previous schema version, with its logic layer config. Its
+/// cache is rebuilt before it's swapped in; if that fails, the current schema
+/// keeps being served.
+pub fn admin_schema_rollback_handler(req: HttpRequest<AppState>) -> FutureResponse<HttpResponse> {
+    if !is_admin(&req) {
+        return boxed_error_http_response(HttpResponse::Unauthorized().finish());
+    }
+
+    let state = req.state();
+
+    let previous = match state.schema_versions.lock().unwrap().previous.last() {
+        Some(previous) => previous.clone(),
+        None => {
+            return boxed_error_http_response(
+                HttpResponse::NotFound().json("No previous schema version".to_string())
+            );
+        },
+    };
+
+    let backend = state.backend.clone();
+    let has_unique_levels_properties = state.has_unique_levels_properties.clone();
+    let schema_validation = state.env_vars.schema_validation.clone();
+
+    let schema_arc = state.schema.clone();
+    let cache_arc = state.cache.clone();
+    let config_arc = state.logic_layer_config.clone();
+    let versions_arc = state.schema_versions.clone();
+    let configured_source = state.env_vars.schema_source.clone();
+
+    tenant::run_in_system("tesseract-admin", move |sys| {
+        tenant::prepare_reload(
+            &previous.schema,
+            &previous.logic_layer_config,
+            backend,
+            &has_unique_levels_properties,
+            &schema_validation,
+            sys,
+        ).map(|cache| (previous.hash, cache))
+    })
+    .then(move |res| {
+        let restored = res.and_then(|(hash, cache)| {
+            tenant::restore(
+                &versions_arc,
+                &schema_arc,
+                &cache_arc,
+                config_arc.as_ref().map(|c| &**c),
+                &hash,
+                cache,
+            ).map(|previous_versions| (hash, previous_versions))
+        });
+
+        let response = match restored {
+            Ok((hash, previous_versions)) => {
+                info!("Rolled back to schema {}", hash);
+
+                let source = versions_arc.lock().unwrap()
+                    .active_source(&hash, &configured_source);
+
+                HttpResponse::Ok().json(SchemaVersionInfo {
+                    source,
+                    hash,
+                    previous_versions,
+                })
+            },
+            Err(err) => {
+                warn!("Rollback failed: {}", err);
+                HttpResponse::InternalServerError().json(err.to_string())
+            },
+        };
+
+        Ok::<_, actix_web::Error>(response)
+    })
+    .responder()
+}
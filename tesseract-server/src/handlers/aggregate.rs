@@ -20,7 +20,8 @@ use crate::handlers::util::validate_members;
 
 use crate::app::AppState;
 use crate::errors::ServerError;
-use super::util::{boxed_error_http_response, verify_authorization, format_to_content_type, generate_source_data};
+use crate::audit::AuditQuery;
+use super::util::{verify_cube_authorization, verify_query_authorization, restrict_query, format_to_content_type, generate_source_data, audit_record, audit_denied};
 
 
 /// Handles default aggregation when a format is not specified.
@@ -55,8 +56,8 @@ pub fn do_aggregate(
     let schema = &req.state().schema.read().unwrap().clone();
     let cube_obj = ok_or_404!(schema.get_cube_by_name(&cube));
 
-    if let Err(err) = verify_authorization(&req, cube_obj.min_auth_level) {
-        return boxed_error_http_response(err);
+    if let Err(err) = verify_cube_authorization(&req, cube_obj) {
+        return audit_denied(&req, &cube, AuditQuery::default(), err);
     }
 
     let format = format.parse::<FormatType>();
@@ -79,10 +80,20 @@ pub fn do_aggregate(
 
     // Turn AggregateQueryOpt into Query
     let ts_query: Result<TsQuery, _> = agg_query.try_into();
-    let ts_query = ok_or_404!(ts_query);
+    let mut ts_query = ok_or_404!(ts_query);
 
-    // sql injection mitigation on query:
-    // - Check that cut members exist in members cache
+    if let Err(err) = verify_query_authorization(&req, cube_obj, &ts_query) {
+        return audit_denied(&req, &cube, AuditQuery::from_query(&ts_query), err);
+    }
+
+    // Row-level security: restrictions from the JWT can't be overridden by user cuts
+    if let Err(err) = restrict_query(&req, &cube_obj, &mut ts_query) {
+        return audit_denied(&req, &cube, AuditQuery::from_query(&ts_query), err);
+    }
+
+    // Check that cut members exist in members cache, unless the level skips
+    // the members cache. Members are bound as parameters or escaped, so this isn't
+    // what prevents sql injection.
     // this is in braces to explicitly the scope in which
     // req is borrowed, since req is moved later in the `map_err`
     {
@@ -96,16 +107,21 @@ pub fn do_aggregate(
 
     let sql = req.state()
         .backend
-        .generate_sql(query_ir);
+        .generate_bound_sql(query_ir);
 
-    info!("Sql query: {}", sql);
+    info!("Sql query: {}", sql.sql);
+    info!("Sql params: {:?}", sql.params);
     info!("Headers: {:?}", headers);
 
+    let audit = audit_record(&req, &cube, AuditQuery::from_query(&ts_query));
+    let audit_err = audit.clone();
+
     req.state()
         .backend
-        .exec_sql(sql)
+        .exec_bound_sql(sql)
         .and_then(move |df| {
             let content_type = format_to_content_type(&format);
+            audit.add_rows(df.len());
 
             match format_records(&headers, df, format, source_data, false) {
                 Ok(res) => {
@@ -113,10 +129,14 @@ pub fn do_aggregate(
                         .set(content_type)
                         .body(res))
                 },
-                Err(err) => Ok(HttpResponse::NotFound().json(err.to_string())),
+                Err(err) => {
+                    audit.set_status(404);
+                    Ok(HttpResponse::NotFound().json(err.to_string()))
+                },
             }
         })
         .map_err(move |e| {
+            audit_err.set_status(500);
             if req.state().debug {
                 ServerError::Db { cause: e.to_string() }.into()
             } else {
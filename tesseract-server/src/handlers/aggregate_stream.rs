@@ -4,7 +4,7 @@ use actix_web::{
     HttpResponse,
     Path,
 };
-use futures::future;
+use futures::{future, Stream};
 use lazy_static::lazy_static;
 use log::*;
 use serde_qs as qs;
@@ -15,7 +15,8 @@ use tesseract_core::Query as TsQuery;
 
 use crate::app::AppState;
 use super::aggregate::AggregateQueryOpt;
-use super::util::{boxed_error_http_response, verify_authorization, format_to_content_type};
+use crate::audit::AuditQuery;
+use super::util::{verify_cube_authorization, verify_query_authorization, restrict_query, format_to_content_type, audit_record, audit_denied};
 
 
 /// Handles default aggregation when a format is not specified.
@@ -50,8 +51,8 @@ pub fn do_aggregate(
     let schema = &req.state().schema.read().unwrap().clone();
     let cube_obj = ok_or_404!(schema.get_cube_by_name(&cube));
 
-    if let Err(err) = verify_authorization(&req, cube_obj.min_auth_level) {
-        return boxed_error_http_response(err);
+    if let Err(err) = verify_cube_authorization(&req, cube_obj) {
+        return audit_denied(&req, &cube, AuditQuery::default(), err);
     }
 
     let format = ok_or_404!(format.parse::<FormatType>());
@@ -69,12 +70,18 @@ pub fn do_aggregate(
 
     // Turn AggregateQueryOpt into Query
     let ts_query: Result<TsQuery, _> = agg_query.try_into();
-    let ts_query = ok_or_404!(ts_query);
+    let mut ts_query = ok_or_404!(ts_query);
 
-    let query_ir_headers = req
-        .state()
-        .schema.read().unwrap()
-        .sql_query(&cube, &ts_query, None);
+    if let Err(err) = verify_query_authorization(&req, cube_obj, &ts_query) {
+        return audit_denied(&req, &cube, AuditQuery::from_query(&ts_query), err);
+    }
+
+    // Row-level security: restrictions from the JWT can't be overridden by user cuts
+    if let Err(err) = restrict_query(&req, &cube_obj, &mut ts_query) {
+        return audit_denied(&req, &cube, AuditQuery::from_query(&ts_query), err);
+    }
+
+    let query_ir_headers = schema.sql_query(&cube, &ts_query, None);
 
     let (query_ir, headers) = ok_or_404!(query_ir_headers);
 
@@ -85,9 +92,22 @@ pub fn do_aggregate(
     info!("Sql query: {}", sql);
     info!("Headers: {:?}", headers);
 
+    // The audit entry is written when the stream is dropped, after the
+    // response is sent
+    let audit = audit_record(&req, &cube, AuditQuery::from_query(&ts_query));
+    let audit_err = audit.clone();
+
     let df_stream = req.state()
         .backend
-        .exec_sql_stream(sql);
+        .exec_sql_stream(sql)
+        .inspect(move |df| match df {
+            Ok(df) => audit.add_rows(df.len()),
+            Err(_) => audit.set_status(500),
+        })
+        .map_err(move |err| {
+            audit_err.set_status(500);
+            err
+        });
 
     let content_type = format_to_content_type(&format);
 
@@ -21,7 +21,7 @@ use tesseract_core::{DataFrame, Column, ColumnData};
 use tesseract_core::schema::{Cube, DimensionType, Level};
 use crate::app::AppState;
 use crate::logic_layer::{LogicLayerConfig, CubeCache};
-use crate::handlers::util::{verify_authorization, format_to_content_type};
+use crate::handlers::util::{verify_cube_authorization, format_to_content_type};
 use crate::handlers::logic_layer::{query_geoservice, GeoserviceQuery};
 
 
@@ -82,7 +82,7 @@ pub fn perform_diagnosis(
         Some(cube_name) => {
             match schema.get_cube_by_name(&cube_name) {
                 Ok(cube) => {
-                    if let Err(err) = verify_authorization(&req, cube.min_auth_level) {
+                    if let Err(err) = verify_cube_authorization(&req, cube) {
                         return Ok(err);
                     }
 
@@ -99,7 +99,7 @@ pub fn perform_diagnosis(
             let mut error_messages: Vec<String> = vec![];
 
             for cube in &schema.cubes {
-                if let Err(err) = verify_authorization(&req, cube.min_auth_level) {
+                if let Err(err) = verify_cube_authorization(&req, cube) {
                     continue;
                 }
 
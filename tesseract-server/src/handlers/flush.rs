@@ -5,13 +5,17 @@ use log::*;
 use serde_qs as qs;
 
 use actix_web::{
+    AsyncResponder,
+    FutureResponse,
     HttpRequest,
     HttpResponse,
-    Result as ActixResult,
 };
+use futures::future::Future;
 
-use crate::app::{AppState, SchemaSource};
-use crate::schema_config;
+use crate::app::AppState;
+use crate::schema_source;
+use crate::tenant;
+use super::util::boxed_error_http_response;
 
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -19,7 +23,7 @@ pub struct FlushQueryOpt {
     pub secret: String,
 }
 
-pub fn flush_handler(req: HttpRequest<AppState>) -> ActixResult<HttpResponse> {
+pub fn flush_handler(req: HttpRequest<AppState>) -> FutureResponse<HttpResponse> {
     let query = req.query_string();
 
     lazy_static!{
@@ -30,53 +34,80 @@ pub fn flush_handler(req: HttpRequest<AppState>) -> ActixResult<HttpResponse> {
     let query = match query_res {
         Ok(q) => q,
         Err(err) => {
-            return Ok(HttpResponse::BadRequest().json(err.to_string()));
+            return boxed_error_http_response(HttpResponse::BadRequest().json(err.to_string()));
         },
     };
 
     let db_secret = match &req.state().env_vars.flush_secret {
         Some(db_secret) => db_secret,
-        None => { return Ok(HttpResponse::Unauthorized().finish()); }
+        None => { return boxed_error_http_response(HttpResponse::Unauthorized().finish()); }
     };
 
-    if query.secret == *db_secret {
-        info!("Flush internal state");
-
-        // Read schema again
-        // NOTE: This logic will change once we start supporting remote schemas
-        let schema_path = match &req.state().env_vars.schema_source {
-            SchemaSource::LocalSchema { ref filepath } => filepath,
-            SchemaSource::RemoteSchema { ref endpoint } => endpoint,
-        };
-
-        let schema = match schema_config::read_schema(&schema_path) {
-            Ok(val) => val,
-            Err(err) => {
-                error!("{}", err);
-                return Ok(HttpResponse::InternalServerError().finish());
-            },
-        };
-
-        // Update shared schema
-        let mut w = req.state().schema.write().unwrap();
-        *w = schema.clone();
-
-        // TODO: Uncomment when issue with SystemRunner is solved
-//        // Re-populate cache with the new schema
-//        let cache = match populate_cache(schema, req.state().backend.clone()) {
-//            Ok(cache) => cache,
-//            Err(err) => {
-//                error!("{}", err);
-//                return Ok(HttpResponse::InternalServerError().finish());
-//            },
-//        };
-//
-//        // Update shared cache
-//        let mut w = req.state().cache.write().unwrap();
-//        *w = cache;
-
-        Ok(HttpResponse::Ok().finish())
-    } else {
-        Ok(HttpResponse::Unauthorized().finish())
+    if query.secret != *db_secret {
+        return boxed_error_http_response(HttpResponse::Unauthorized().finish());
     }
+
+    info!("Flush internal state");
+
+    // Read the TLS certificate again, e.g. after a renewal
+    if let Some(ref tls) = req.state().env_vars.tls {
+        match tls.reload() {
+            Ok(()) => info!("Reloaded TLS certificate"),
+            Err(err) => error!("TLS certificate reload failed, keeping current certificate: {}", err),
+        }
+    }
+
+    // Read schema again from its source. The new schema is validated, and
+    // the cache rebuilt, before both replace the current ones.
+    let state = req.state();
+    let backend = state.backend.clone();
+    let logic_layer_config = state.logic_layer_config.as_ref()
+        .map(|config| config.read().unwrap().clone());
+    let has_unique_levels_properties = state.has_unique_levels_properties.clone();
+    let schema_validation = state.env_vars.schema_validation.clone();
+
+    let schema_arc = state.schema.clone();
+    let cache_arc = state.cache.clone();
+    let versions_arc = state.schema_versions.clone();
+    let configured_source = state.env_vars.schema_source.clone();
+
+    schema_source::fetch_schema(&state.env_vars.schema_source, state.backend.clone())
+        .and_then(move |schema| {
+            tenant::run_in_system("tesseract-flush", move |sys| {
+                let cache = tenant::prepare_reload(
+                    &schema,
+                    &logic_layer_config,
+                    backend,
+                    &has_unique_levels_properties,
+                    &schema_validation,
+                    sys,
+                )?;
+
+                Ok((schema, cache))
+            })
+        })
+        .then(move |res| {
+            let response = match res {
+                Ok((schema, cache)) => {
+                    let (hash, _) = tenant::activate(
+                        &versions_arc,
+                        &schema_arc,
+                        &cache_arc,
+                        None,
+                        &configured_source,
+                        tenant::Reload { source: None, schema, cache, logic_layer_config: None },
+                    );
+                    info!("Reloaded schema {}", hash);
+
+                    HttpResponse::Ok().finish()
+                },
+                Err(err) => {
+                    error!("{}", err);
+                    HttpResponse::InternalServerError().finish()
+                },
+            };
+
+            Ok::<_, actix_web::Error>(response)
+        })
+        .responder()
 }
@@ -6,11 +6,9 @@ use actix_web::{
 use serde_derive::Serialize;
 use structopt::clap::crate_version;
 
-use crate::app::AppState;
-
 
 /// Returns server status and Tesseract version.
-pub fn index_handler(_req: HttpRequest<AppState>) -> ActixResult<HttpResponse> {
+pub fn index_handler<S>(_req: HttpRequest<S>) -> ActixResult<HttpResponse> {
     Ok(HttpResponse::Ok().json(
         Status {
             status: "ok".to_owned(),
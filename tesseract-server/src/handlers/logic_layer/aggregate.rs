@@ -20,16 +20,17 @@ use url::Url;
 use tesseract_core::names::{Cut, Drilldown, Property, Measure, LevelName, Mask};
 use tesseract_core::format::{format_records, FormatType};
 use tesseract_core::query::{FilterQuery, GrowthQuery, RcaQuery, TopQuery, RateQuery};
-use tesseract_core::{Query as TsQuery, MeaOrCalc, DataFrame, Column, ColumnData, is_same_columndata_type};
+use tesseract_core::{Query as TsQuery, MeaOrCalc, BoundSql, DataFrame, Column, ColumnData, is_same_columndata_type};
 use tesseract_core::schema::{Cube, DimensionType};
 
 use crate::app::AppState;
+use crate::audit::AuditQuery;
 use crate::errors::ServerError;
 use crate::logic_layer::{LogicLayerConfig, CubeCache, Time};
 use super::super::util::{
-    boxed_error_string, boxed_error_http_response,
-    verify_authorization, format_to_content_type, generate_source_data,
-    validate_members
+    boxed_error_string,
+    verify_cube_authorization, verify_query_authorization, restrict_query, format_to_content_type, generate_source_data,
+    validate_members, audit_record, audit_denied,
 };
 use crate::handlers::logic_layer::{query_geoservice, GeoserviceQuery};
 
@@ -204,7 +205,9 @@ pub fn logic_layer_aggregation(
     info!("Format: {:?}", format);
 
     let query = req.query_string();
-    let schema = req.state().schema.read().unwrap();
+    // One clone per request; re-taking the read lock later can deadlock
+    // against a schema reload waiting on the write lock.
+    let schema = req.state().schema.read().unwrap().clone();
     let debug = req.state().debug;
 
     let logic_layer_config: Option<LogicLayerConfig> = match &req.state().logic_layer_config {
@@ -233,8 +236,8 @@ pub fn logic_layer_aggregation(
 
     let cube = ok_or_404!(schema.get_cube_by_name(&cube_name));
 
-    if let Err(err) = verify_authorization(&req, cube.min_auth_level) {
-        return boxed_error_http_response(err);
+    if let Err(err) = verify_cube_authorization(&req, cube) {
+        return audit_denied(&req, &cube_name, AuditQuery::default(), err);
     }
 
     let cache = req.state().cache.read().unwrap();
@@ -254,7 +257,18 @@ pub fn logic_layer_aggregation(
         agg_query.clone(), &cube, &cube_cache,
         &logic_layer_config, &req.state().env_vars.geoservice_url
     );
-    let (ts_queries, header_map) = ok_or_404!(ts_queries);
+    let (mut ts_queries, header_map) = ok_or_404!(ts_queries);
+
+    for ts_query in ts_queries.iter() {
+        if let Err(err) = verify_query_authorization(&req, cube, ts_query) {
+            return audit_denied(&req, &cube_name, AuditQuery::from_queries(&ts_queries), err);
+        }
+    }
+
+    // Row-level security: restrictions from the JWT can't be overridden by user cuts
+    if let Err(err) = ts_queries.iter_mut().try_for_each(|ts_query| restrict_query(&req, &cube, ts_query)) {
+        return audit_denied(&req, &cube_name, AuditQuery::from_queries(&ts_queries), err);
+    }
 
     if ts_queries.len() == 0 {
         return boxed_error_string("Unable to generate queries".to_string())
@@ -267,18 +281,16 @@ pub fn logic_layer_aggregation(
         HashMap::new()
     };
 
-    let mut sql_strings: Vec<String> = vec![];
+    let mut sql_queries: Vec<BoundSql> = vec![];
     let mut final_headers: Vec<String> = vec![];
 
     for ts_query in &ts_queries {
-        // SQL injection mitigation
+        // Cut members must exist, unless the level skips the members cache
         ok_or_404!(validate_members(&ts_query.cuts, &cube_cache));
 
         debug!("Tesseract query: {:?}", ts_query);
 
-        let query_ir_headers = req
-            .state()
-            .schema.read().unwrap()
+        let query_ir_headers = schema
             .sql_query(&cube_name, &ts_query, Some(&unique_header_map));
 
         let (query_ir, headers) = ok_or_404!(query_ir_headers);
@@ -287,9 +299,10 @@ pub fn logic_layer_aggregation(
 
         let sql = req.state()
             .backend
-            .generate_sql(query_ir);
+            .generate_bound_sql(query_ir);
 
-        debug!("SQL query: {}", sql);
+        debug!("SQL query: {}", sql.sql);
+        debug!("SQL params: {:?}", sql.params);
 
         // Substitute header names (only need to do this once)
         if final_headers.len() == 0 {
@@ -306,7 +319,7 @@ pub fn logic_layer_aggregation(
             }
         }
 
-        sql_strings.push(sql);
+        sql_queries.push(sql);
     }
 
     debug!("Headers: {:?}", final_headers);
@@ -314,16 +327,19 @@ pub fn logic_layer_aggregation(
     let exclude_map = agg_query.deserialize_exclude();
 
     // Joins all the futures for each TsQuery
-    let futs: JoinAll<Vec<Box<dyn Future<Item=DataFrame, Error=Error>>>> = join_all(sql_strings
+    let futs: JoinAll<Vec<Box<dyn Future<Item=DataFrame, Error=Error>>>> = join_all(sql_queries
             .iter()
             .map(|sql| {
                 req.state()
                     .backend
-                    .exec_sql(sql.clone())
+                    .exec_bound_sql(sql.clone())
             })
             .collect()
         );
 
+    let audit = audit_record(&req, &cube_name, AuditQuery::from_queries(&ts_queries));
+    let audit_err = audit.clone();
+
     // Process data received once all futures are resolved and return response
     futs
         .and_then(move |dfs| {
@@ -528,6 +544,7 @@ pub fn logic_layer_aggregation(
             }
 
             let final_df = DataFrame { columns: final_columns };
+            audit.add_rows(final_df.len());
 
             let content_type = format_to_content_type(&format);
 
@@ -537,10 +554,14 @@ pub fn logic_layer_aggregation(
                         .set(content_type)
                         .body(res))
                 },
-                Err(err) => Ok(HttpResponse::NotFound().json(err.to_string())),
+                Err(err) => {
+                    audit.set_status(404);
+                    Ok(HttpResponse::NotFound().json(err.to_string()))
+                },
             }
         })
         .map_err(move |e| {
+            audit_err.set_status(500);
             if debug {
                 ServerError::Db { cause: e.to_string() }.into()
             } else {
@@ -13,14 +13,16 @@ use serde_derive::{Serialize, Deserialize};
 use serde_qs as qs;
 
 use crate::app::AppState;
+use crate::audit::AuditQuery;
 use crate::logic_layer::{LogicLayerConfig};
 
 use tesseract_core::format::{format_records, FormatType};
 use tesseract_core::names::LevelName;
 
 use super::super::util::{
-    boxed_error_string, boxed_error_http_response,
-    verify_authorization, format_to_content_type
+    boxed_error_string,
+    verify_cube_authorization, verify_level_authorization, format_to_content_type,
+    get_allowed_members, filter_members, audit_record, audit_denied,
 };
 
 
@@ -75,8 +77,8 @@ pub fn get_members(
     // Get cube object to check for API key
     let cube_obj = ok_or_404!(schema.get_cube_by_name(&cube_name));
 
-    if let Err(err) = verify_authorization(&req, cube_obj.min_auth_level) {
-        return boxed_error_http_response(err);
+    if let Err(err) = verify_cube_authorization(&req, cube_obj) {
+        return audit_denied(&req, &cube_name, AuditQuery::default(), err);
     }
 
     if let Some(logic_layer_config) = &logic_layer_config {
@@ -145,9 +147,25 @@ pub fn get_members(
     debug!("{:?}", cube_name);
     debug!("{:?}", level_name);
 
+    // Row-level security: only list the members the user can see
+    let allowed_members = {
+        let cube = ok_or_404!(schema.get_cube_by_name(&cube_name));
+
+        if let Err(err) = verify_level_authorization(&req, cube, &level_name) {
+            return audit_denied(&req, &cube_name, AuditQuery::from_level(&level_name), err);
+        }
+
+        match get_allowed_members(&req, &cube, &level_name) {
+            Ok(allowed_members) => allowed_members,
+            Err(err) => return audit_denied(&req, &cube_name, AuditQuery::from_level(&level_name), err),
+        }
+    };
+
+    let identifier_style = req.state().backend.identifier_style();
+    let literal_style = req.state().backend.literal_style();
     let members_sql_and_headers = match members_query.locale {
-        Some(locale) => schema.members_locale_sql(&cube_name, &level_name, &locale),
-        None => schema.members_sql(&cube_name, &level_name)
+        Some(locale) => schema.members_locale_sql(&cube_name, &level_name, &locale, identifier_style, literal_style),
+        None => schema.members_sql(&cube_name, &level_name, identifier_style, literal_style)
     };
 
     let (members_sql, header) = match members_sql_and_headers {
@@ -164,16 +182,29 @@ pub fn get_members(
     debug!("{:?}", members_sql);
     debug!("{:?}", header);
 
+    let audit = audit_record(&req, &cube_name, AuditQuery::from_level(&level_name));
+    let audit_err = audit.clone();
+
     req.state()
         .backend
         .exec_sql(members_sql)
+        .map_err(move |err| {
+            audit_err.set_status(500);
+            err
+        })
         .from_err()
-        .and_then(move |df| {
+        .and_then(move |mut df| {
+            filter_members(&mut df, &allowed_members);
+            audit.add_rows(df.len());
+
             let content_type = format_to_content_type(&format);
 
             match format_records(&header, df, format, None, false) {
                 Ok(res) => Ok(HttpResponse::Ok().set(content_type).body(res)),
-                Err(err) => Ok(HttpResponse::NotFound().json(err.to_string())),
+                Err(err) => {
+                    audit.set_status(404);
+                    Ok(HttpResponse::NotFound().json(err.to_string()))
+                },
             }
         })
         .responder()
@@ -18,8 +18,12 @@ use tesseract_core::schema::metadata::{CubeMetadata, PropertyMetadata};
 use tesseract_core::DEFAULT_ALLOWED_ACCESS;
 
 use crate::app::AppState;
+use crate::audit::AuditQuery;
 use crate::logic_layer::LogicLayerConfig;
-use super::util::{boxed_error_http_response, verify_authorization, get_user_auth_level};
+use super::util::{
+    verify_authorization, verify_cube_authorization, verify_level_authorization, get_user_auth_level, get_api_key,
+    get_allowed_members, filter_members, audit_record, audit_denied,
+};
 
 
 pub fn metadata_handler(
@@ -27,12 +31,14 @@ pub fn metadata_handler(
     ) -> ActixResult<HttpResponse>
 {
     info!("Metadata for cube: {}", cube);
-    let cube = match req.state().schema.read().unwrap().cube_metadata(&cube){
+    let locale = req.query().get("locale").cloned();
+    let user_auth_level = get_user_auth_level(&req);
+    let cube = match req.state().schema.read().unwrap().cube_metadata(&cube, user_auth_level, locale.as_ref().map(|l| l.as_str())){
         Some(c) => c,
         None => return Ok(HttpResponse::NotFound().finish()),
     };
 
-    if let Err(err) = verify_authorization(&req, cube.min_auth_level) {
+    if let Err(err) = verify_authorization(&req, &cube.name, cube.min_auth_level, &format!("Cube \"{}\"", cube.name)) {
         return Ok(err);
     }
 
@@ -50,8 +56,17 @@ pub fn metadata_all_handler(
     ) -> ActixResult<HttpResponse>
 {
     info!("Metadata for all");
+    let api_key = match get_api_key(&req) {
+        Ok(api_key) => api_key,
+        Err(err) => return Ok(err),
+    };
     let user_auth_level = get_user_auth_level(&req);
-    let mut schema_details = req.state().schema.read().unwrap().metadata(user_auth_level);
+    let locale = req.query().get("locale").cloned();
+    let mut schema_details = req.state().schema.read().unwrap().metadata(user_auth_level, locale.as_ref().map(|l| l.as_str()));
+    // Filter out cubes that the API key can't access
+    if let Some(api_key) = api_key {
+        schema_details.cubes.retain(|cube| api_key.allows_cube(&cube.name));
+    }
     let ll_config = match &req.state().logic_layer_config {
         Some(llc) => llc.read().unwrap().clone(),
         None => {
@@ -160,8 +175,8 @@ pub fn do_members(
     let schema = &req.state().schema.read().unwrap().clone();
     let cube_obj = ok_or_404!(schema.get_cube_by_name(&cube));
 
-    if let Err(err) = verify_authorization(&req, cube_obj.min_auth_level) {
-        return boxed_error_http_response(err);
+    if let Err(err) = verify_cube_authorization(&req, cube_obj) {
+        return audit_denied(&req, &cube, AuditQuery::default(), err);
     }
 
     let format = ok_or_404!(format.parse::<FormatType>());
@@ -179,19 +194,44 @@ pub fn do_members(
 
     info!("Members for cube: {}, level: {}", cube, level);
 
-    let members_sql_and_headers = req.state().schema.read().unwrap()
-        .members_sql(&cube, &level);
+    if let Err(err) = verify_level_authorization(&req, cube_obj, &level) {
+        return audit_denied(&req, &cube, AuditQuery::from_level(&level), err);
+    }
+
+    // Row-level security: only list the members the user can see
+    let allowed_members = match get_allowed_members(&req, &cube_obj, &level) {
+        Ok(allowed_members) => allowed_members,
+        Err(err) => return audit_denied(&req, &cube, AuditQuery::from_level(&level), err),
+    };
+
+    let identifier_style = req.state().backend.identifier_style();
+    let literal_style = req.state().backend.literal_style();
+    let members_sql_and_headers = schema
+        .members_sql(&cube, &level, identifier_style, literal_style);
 
     let (members_sql, header) = ok_or_400!(members_sql_and_headers);
 
+    let audit = audit_record(&req, &cube, AuditQuery::from_level(&level));
+    let audit_err = audit.clone();
+
     req.state()
         .backend
         .exec_sql(members_sql)
+        .map_err(move |err| {
+            audit_err.set_status(500);
+            err
+        })
         .from_err()
-        .and_then(move |df| {
+        .and_then(move |mut df| {
+            filter_members(&mut df, &allowed_members);
+            audit.add_rows(df.len());
+
             match format_records(&header, df, format, None, false) {
                 Ok(res) => Ok(HttpResponse::Ok().body(res)),
-                Err(err) => Ok(HttpResponse::NotFound().json(err.to_string())),
+                Err(err) => {
+                    audit.set_status(404);
+                    Ok(HttpResponse::NotFound().json(err.to_string()))
+                },
             }
         })
         .responder()
@@ -0,0 +1,19 @@
+use actix_web::{
+    HttpRequest,
+    HttpResponse,
+    Result as ActixResult,
+};
+
+use crate::app::AppState;
+
+
+/// Returns server counters, in the Prometheus text format.
+pub fn metrics_handler(req: HttpRequest<AppState>) -> ActixResult<HttpResponse> {
+    let metrics = req.state().env_vars.rate_limiter.as_ref()
+        .map(|rate_limiter| rate_limiter.metrics())
+        .unwrap_or_default();
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics))
+}
@@ -1,13 +1,18 @@
 #[macro_use]
 mod util;
+mod admin;
 mod aggregate;
 mod aggregate_stream;
 mod diagnosis;
 mod flush;
 mod index;
 mod metadata;
+mod metrics;
 pub mod logic_layer;
 
+pub use self::admin::admin_schema_handler;
+pub use self::admin::admin_schema_put_handler;
+pub use self::admin::admin_schema_rollback_handler;
 pub use self::aggregate::aggregate_handler;
 pub use self::aggregate::aggregate_default_handler;
 pub use self::aggregate_stream::aggregate_handler as aggregate_stream_handler;
@@ -26,6 +31,7 @@ pub use self::metadata::members_handler;
 pub use self::metadata::members_default_handler;
 pub use self::metadata::metadata_handler;
 pub use self::metadata::metadata_all_handler;
+pub use self::metrics::metrics_handler;
 pub use self::logic_layer::logic_layer_relations_handler;
 pub use self::logic_layer::logic_layer_relations_default_handler;
 pub use self::logic_layer::logic_layer_relations_non_unique_levels_default_handler;
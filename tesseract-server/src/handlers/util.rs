@@ -1,23 +1,27 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use actix_web::{
     FutureResponse,
     HttpRequest,
     HttpResponse,
 };
 use futures::future::{self};
-use actix_web::http::header::ContentType;
+use actix_web::http::header::{self, ContentType};
 use mime;
 
 use tesseract_core::format::FormatType;
 use tesseract_core::schema::Cube;
 use tesseract_core::schema::metadata::SourceMetadata;
 
+use crate::api_keys::{ApiKey, ApiKeyError};
+use crate::audit::{AuditEntry, AuditLog, AuditQuery, AuditRecord};
 use crate::app::AppState;
 
-use failure::{bail, format_err, Error};
-use tesseract_core::names::Cut;
+use failure::{bail, Error};
+use tesseract_core::names::{Cut, LevelName};
+use tesseract_core::{DataFrame, Query as TsQuery, DEFAULT_ALLOWED_ACCESS};
 use crate::logic_layer::CubeCache;
-use crate::auth::{validate_web_token, extract_token, user_auth_level};
+use crate::auth::{validate_web_token, extract_token, extract_api_key, user_auth_level, user_restrictions, user_subject};
+use crate::restrictions::{self, Restriction};
 
 pub(crate) fn format_to_content_type(format_type: &FormatType) -> ContentType {
     match format_type {
@@ -67,21 +71,201 @@ pub fn generate_source_data(cube: &Cube) -> SourceMetadata {
 }
 
 pub fn get_user_auth_level(req: &HttpRequest<AppState>) -> Option<i32> {
-    let jwt_secret = &req.state().env_vars.jwt_secret;
+    if let Ok(Some(api_key)) = get_api_key(req) {
+        return Some(api_key.auth_level);
+    }
+
+    let env_vars = &req.state().env_vars;
     let user_token = extract_token(req);
-    user_auth_level(jwt_secret, &user_token)
+    match user_auth_level(&env_vars.jwt, &user_token) {
+        // API keys without JWT: requests without a key only get public access
+        None if env_vars.api_keys.is_some() => Some(DEFAULT_ALLOWED_ACCESS),
+        auth_level => auth_level,
+    }
+}
+
+/// Result of authenticating the request's API key, kept in the request
+/// extensions so that the key is only counted once against its quota.
+struct ApiKeyAuth(Result<ApiKey, ApiKeyError>);
+
+/// The request's API key, from the `x-tesseract-api-key` query param or
+/// header. None if API keys are not set on the server, or no key was sent.
+pub fn get_api_key(req: &HttpRequest<AppState>) -> Result<Option<ApiKey>, HttpResponse> {
+    let api_keys = match req.state().env_vars.api_keys {
+        Some(ref api_keys) => api_keys,
+        None => return Ok(None),
+    };
+
+    let raw_key = match extract_api_key(req) {
+        Some(raw_key) => raw_key,
+        None => return Ok(None),
+    };
+
+    let cached = req.extensions().get::<ApiKeyAuth>().map(|auth| auth.0.clone());
+    let auth = match cached {
+        Some(auth) => auth,
+        None => {
+            let auth = api_keys.authenticate(&raw_key).map(|api_key| api_key.clone());
+            req.extensions_mut().insert(ApiKeyAuth(auth.clone()));
+            auth
+        },
+    };
+
+    match auth {
+        Ok(api_key) => Ok(Some(api_key)),
+        Err(ApiKeyError::Invalid) => Err(HttpResponse::Unauthorized().json("Invalid API key")),
+        Err(ApiKeyError::Expired) => Err(HttpResponse::Unauthorized().json("API key has expired")),
+        Err(ApiKeyError::QuotaExceeded { retry_after }) => Err(
+            HttpResponse::TooManyRequests()
+                .header(header::RETRY_AFTER, retry_after.to_string())
+                .json("API key quota exceeded")
+        ),
+    }
 }
 
-pub fn verify_authorization(req: &HttpRequest<AppState>, min_auth_level: i32) -> Result<(), HttpResponse> {
-    let jwt_secret = &req.state().env_vars.jwt_secret;
+/// `element` names what is being accessed in the error, e.g. `Cube "sales"`.
+/// An API key is checked instead of the JWT when one is sent.
+pub fn verify_authorization(
+    req: &HttpRequest<AppState>,
+    cube_name: &str,
+    min_auth_level: i32,
+    element: &str,
+) -> Result<(), HttpResponse>
+{
+    if let Some(api_key) = get_api_key(req)? {
+        if !api_key.allows_cube(cube_name) {
+            return Err(HttpResponse::Unauthorized().json(format!("Cube \"{}\" is not allowed for this API key", cube_name)));
+        }
+        if api_key.auth_level < min_auth_level {
+            return Err(HttpResponse::Unauthorized().json(format!("{} is not public", element)));
+        }
+        return Ok(());
+    }
+
+    let env_vars = &req.state().env_vars;
     let user_token = extract_token(req);
-    if !validate_web_token(jwt_secret, &user_token, min_auth_level) {
-        return Err(HttpResponse::Unauthorized().json("This cube is not public".to_string()));
+    let authorized = if env_vars.jwt.is_none() && env_vars.api_keys.is_some() {
+        min_auth_level <= DEFAULT_ALLOWED_ACCESS
+    } else {
+        validate_web_token(&env_vars.jwt, &user_token, min_auth_level)
+    };
+
+    if !authorized {
+        return Err(HttpResponse::Unauthorized().json(format!("{} is not public", element)));
+    }
+
+    Ok(())
+}
+
+pub fn verify_cube_authorization(req: &HttpRequest<AppState>, cube: &Cube) -> Result<(), HttpResponse> {
+    verify_authorization(req, &cube.name, cube.min_auth_level, &format!("Cube \"{}\"", cube.name))
+}
+
+/// Checks the dimensions, hierarchies, levels and measures used by a query,
+/// after the cube itself has been checked.
+pub fn verify_query_authorization(req: &HttpRequest<AppState>, cube: &Cube, query: &TsQuery) -> Result<(), HttpResponse> {
+    for (element, min_auth_level) in cube.query_auth_levels(query) {
+        verify_authorization(req, &cube.name, min_auth_level, &element)?;
     }
 
     Ok(())
 }
 
+/// Checks the dimension, hierarchy and level of `level_name`, after the cube
+/// itself has been checked.
+pub fn verify_level_authorization(req: &HttpRequest<AppState>, cube: &Cube, level_name: &LevelName) -> Result<(), HttpResponse> {
+    for (element, min_auth_level) in cube.level_auth_levels(level_name) {
+        verify_authorization(req, &cube.name, min_auth_level, &element)?;
+    }
+
+    Ok(())
+}
+
+/// Starts an audit log entry for a data request, if the audit log is on for
+/// the cube. The entry is written once the record and its clones are dropped.
+pub fn audit_record(req: &HttpRequest<AppState>, cube_name: &str, query: AuditQuery) -> AuditRecord {
+    let env_vars = &req.state().env_vars;
+
+    let audit_log = match env_vars.audit_log {
+        Some(ref audit_log) if audit_log.audits_cube(cube_name) => audit_log,
+        _ => return AuditRecord::none(),
+    };
+
+    let mut entry = AuditEntry::new(req.path(), cube_name, query);
+    entry.user = user_subject(&env_vars.jwt, &extract_token(req));
+    entry.api_key = get_api_key(req).ok()
+        .and_then(|api_key| api_key)
+        .map(|api_key| api_key.name);
+
+    AuditLog::record(audit_log, entry)
+}
+
+/// Audits a data request which was denied by authorization or restrictions,
+/// and returns the error response.
+pub fn audit_denied(req: &HttpRequest<AppState>, cube_name: &str, query: AuditQuery, response: HttpResponse) -> FutureResponse<HttpResponse> {
+    audit_record(req, cube_name, query).set_status(response.status().as_u16());
+    boxed_error_http_response(response)
+}
+
+/// Restrictions (row-level security) from the user's JWT.
+pub fn get_user_restrictions(req: &HttpRequest<AppState>) -> Result<Vec<Restriction>, HttpResponse> {
+    let jwt = &req.state().env_vars.jwt;
+    let user_token = extract_token(req);
+
+    match user_restrictions(jwt, &user_token) {
+        Ok(Some(claim)) => restrictions::parse_restrictions(&claim)
+            .map_err(|err| HttpResponse::Forbidden().json(err.to_string())),
+        Ok(None) => Ok(vec![]),
+        Err(err) => Err(HttpResponse::Forbidden().json(err.to_string())),
+    }
+}
+
+/// Merges the user's restrictions into the cuts of a query. Must be done
+/// before the query's members are validated.
+pub fn restrict_query(req: &HttpRequest<AppState>, cube: &Cube, query: &mut TsQuery) -> Result<(), HttpResponse> {
+    let restrictions = get_user_restrictions(req)?;
+
+    let cuts = std::mem::replace(&mut query.cuts, vec![]);
+    query.cuts = restrictions::restrict_cuts(cube, cuts, &restrictions)
+        .map_err(|err| HttpResponse::Forbidden().json(err.to_string()))?;
+
+    Ok(())
+}
+
+/// Members of a level the user can see, or None if not restricted.
+pub fn get_allowed_members(
+    req: &HttpRequest<AppState>,
+    cube: &Cube,
+    level_name: &LevelName,
+) -> Result<Option<HashSet<String>>, HttpResponse>
+{
+    let restrictions = get_user_restrictions(req)?;
+
+    if restrictions.is_empty() {
+        return Ok(None);
+    }
+
+    let cache = req.state().cache.read().unwrap();
+    let cube_cache = cache.find_cube_info(&cube.name)
+        .ok_or_else(|| HttpResponse::NotFound().json(format!("Cube {} not found", cube.name)))?;
+
+    restrictions::allowed_members(cube, cube_cache, level_name, &restrictions)
+        .map_err(|err| HttpResponse::Forbidden().json(err.to_string()))
+}
+
+/// Removes the members (rows) the user can't see from a members dataframe,
+/// by their ID (the first column).
+pub fn filter_members(df: &mut DataFrame, allowed: &Option<HashSet<String>>) {
+    if let Some(allowed) = allowed {
+        let keep: Vec<bool> = match df.columns.get(0) {
+            Some(ids) => ids.stringify_column_data().iter().map(|id| allowed.contains(id)).collect(),
+            None => return,
+        };
+
+        df.retain_rows(&keep);
+    }
+}
+
 
 #[macro_export]
 macro_rules! ok_or_400 {
@@ -134,11 +318,16 @@ macro_rules! some_or_404 {
 }
 
 
+/// Checks that cut members exist. Levels with `skip_members_cache` are not
+/// checked; members are passed to the backend as bound parameters or escaped
+/// literals, so they can't inject sql.
 pub fn validate_members(cuts: &[Cut], cube_cache: &CubeCache) -> Result<(), Error> {
     for cut in cuts {
-        // get level cache
-        let member_cache = cube_cache.members_for_level(&cut.level_name)
-            .ok_or_else(|| format_err!("Level not found in cache"))?;
+        let member_cache = match cube_cache.members_for_level(&cut.level_name) {
+            Some(member_cache) => member_cache,
+            None if cube_cache.uncached_levels.contains(&cut.level_name) => continue,
+            None => bail!("Level {} is not in the members cache", cut.level_name),
+        };
         for member in &cut.members {
             if !member_cache.contains(member) {
                 bail!("Cut member not found");
@@ -146,4 +335,52 @@ pub fn validate_members(cuts: &[Cut], cube_cache: &CubeCache) -> Result<(), Erro
         }
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tesseract_core::names::Mask;
+    use crate::logic_layer::LevelCache;
+
+    #[test]
+    fn test_validate_members() {
+        let city = LevelName::new("Geography", "Geography", "City");
+        let street = LevelName::new("Geography", "Geography", "Street");
+
+        let mut level_caches = HashMap::new();
+        level_caches.insert(city.clone(), LevelCache {
+            unique_name: "City".to_owned(),
+            parent_map: None,
+            children_map: None,
+            neighbors_map: HashMap::new(),
+            members: vec!["10".to_owned(), "11".to_owned()].into_iter().collect(),
+        });
+        let mut cube_cache = CubeCache {
+            name: "sales".to_owned(),
+            year_level: None, year_values: None,
+            quarter_level: None, quarter_values: None,
+            month_level: None, month_values: None,
+            week_level: None, week_values: None,
+            day_level: None, day_values: None,
+            time_level: None, time_values: None,
+            level_map: HashMap::new(),
+            property_map: HashMap::new(),
+            level_caches,
+            dimension_caches: HashMap::new(),
+            uncached_levels: HashSet::new(),
+        };
+
+        let city_cut = Cut::new("Geography", "Geography", "City", vec!["10"], Mask::Include, false);
+        let bad_city_cut = Cut::new("Geography", "Geography", "City", vec!["12"], Mask::Include, false);
+        let street_cut = Cut::new("Geography", "Geography", "Street", vec!["100"], Mask::Include, false);
+
+        assert!(validate_members(&[city_cut.clone()], &cube_cache).is_ok());
+        assert!(validate_members(&[bad_city_cut], &cube_cache).is_err());
+
+        // a level which isn't cached must opt out of the cache to be cut
+        assert!(validate_members(&[city_cut.clone(), street_cut.clone()], &cube_cache).is_err());
+        cube_cache.uncached_levels.insert(street);
+        assert!(validate_members(&[city_cut, street_cut], &cube_cache).is_ok());
+    }
+}
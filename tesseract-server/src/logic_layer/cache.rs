@@ -8,6 +8,7 @@ use serde_derive::Deserialize;
 
 use tesseract_core::{Schema, Backend};
 use tesseract_core::names::{LevelName, Property};
+use tesseract_core::query_ir::quote_identifier;
 use tesseract_core::schema::{Level, Cube, InlineTable};
 
 use crate::logic_layer::{LogicLayerConfig};
@@ -161,6 +162,9 @@ pub struct CubeCache {
 
     // Maps a dimension name to a `DimensionCache` object
     pub dimension_caches: HashMap<String, DimensionCache>,
+
+    // Levels with `skip_members_cache`, which have no `LevelCache`
+    pub uncached_levels: HashSet<LevelName>,
 }
 
 
@@ -311,6 +315,7 @@ pub fn populate_cache(
 
         let mut level_caches: HashMap<LevelName, LevelCache> = HashMap::new();
         let mut dimension_caches: HashMap<String, DimensionCache> = HashMap::new();
+        let mut uncached_levels: HashSet<LevelName> = HashSet::new();
 
         for dimension in &cube.dimensions {
             let mut id_map: HashMap<String, Vec<LevelName>> = HashMap::new();
@@ -324,7 +329,7 @@ pub fn populate_cache(
                 for level in &hierarchy.levels {
                     if time_column_names.contains(&level.name) {
                         let val = get_distinct_values(
-                            &level.key_column, &table, backend.clone(), sys
+                            &level, &table, backend.clone(), sys
                         )?;
 
                         if level.name == "Year" {
@@ -356,7 +361,7 @@ pub fn populate_cache(
                                 for annotation in annotations {
                                     if annotation.name == "level" && time_column_names.contains(&annotation.text) {
                                         let val = get_distinct_values(
-                                            &level.key_column, &table, backend.clone(), sys
+                                            &level, &table, backend.clone(), sys
                                         )?;
 
                                         if annotation.text == "Year" {
@@ -394,7 +399,7 @@ pub fn populate_cache(
                         if !found_time {
                             // Want to get distinct time values from the fact table
                             let val = get_distinct_values(
-                                &level.key_column, &cube.table.name, backend.clone(), sys
+                                &level, &cube.table.name, backend.clone(), sys
                             )?;
 
                             time_level = Some(level.clone());
@@ -408,6 +413,11 @@ pub fn populate_cache(
                         level.name.clone()
                     );
 
+                    if level.skip_members_cache {
+                        uncached_levels.insert(level_name);
+                        continue;
+                    }
+
                     // Get unique name for this level
                     let unique_name = match get_unique_level_name(&cube, ll_config, &level_name)? {
                         Some(name) => name,
@@ -476,22 +486,28 @@ pub fn populate_cache(
 
                         // Get all IDs for this level
                         distinct_ids = get_distinct_values(
-                            &level.key_column, &table, backend.clone(), sys
+                            &level, &table, backend.clone(), sys
                         )?;
                     }
 
                     let neighbors_map = get_neighbors_map(&distinct_ids);
 
+                    // Calculated members are not in the database, but they're valid
+                    // members for cuts. They don't get neighbors.
+                    if let Some(ref calculated_members) = level.calculated_members {
+                        for cm in calculated_members {
+                            distinct_ids.push(cm.key.clone());
+                        }
+                    }
+
                     // Add each distinct ID to the id_map HashMap
-                    for distinct_id in distinct_ids {
+                    for distinct_id in distinct_ids.iter() {
                         id_map.entry(distinct_id.clone()).or_insert(vec![]);
-                        let map_entry = id_map.get_mut(&distinct_id).unwrap();
+                        let map_entry = id_map.get_mut(distinct_id).unwrap();
                         map_entry.push(level_name.clone());
                     }
 
-                    // neighbors are not optional, iterate over the keys of neighbors to get all
-                    // members.
-                    let members = neighbors_map.keys().cloned().collect();
+                    let members = distinct_ids.into_iter().collect();
 
                     level_caches.insert(
                         level_name,
@@ -530,6 +546,7 @@ pub fn populate_cache(
             property_map,
             level_caches,
             dimension_caches,
+            uncached_levels,
         })
     }
 
@@ -754,11 +771,14 @@ pub fn get_parent_data(
 ) -> Result<HashMap<String, String>, Error> {
     let mut parent_data: HashMap<String, String> = HashMap::new();
 
+    let style = backend.identifier_style();
     let future = backend
         .exec_sql(
             format!(
                 "select distinct {0}, {1} from {2} group by {0}, {1} order by {0}, {1}",
-                parent_level.key_column, current_level.key_column, table,
+                parent_level.key_column_sql(style),
+                current_level.key_column_sql(style),
+                quote_identifier(table, style),
             ).to_string()
         );
 
@@ -789,11 +809,14 @@ pub fn get_children_data(
 ) -> Result<HashMap<String, Vec<String>>, Error> {
     let mut children_data: HashMap<String, Vec<String>> = HashMap::new();
 
+    let style = backend.identifier_style();
     let future = backend
         .exec_sql(
             format!(
                 "select distinct {0}, {1} from {2} group by {0}, {1} order by {0}, {1}",
-                current_level.key_column, child_level.key_column, table,
+                current_level.key_column_sql(style),
+                child_level.key_column_sql(style),
+                quote_identifier(table, style),
             ).to_string()
         );
 
@@ -833,14 +856,18 @@ pub fn get_children_data(
 
 /// Queries the database to get all the distinct values for a given level.
 pub fn get_distinct_values(
-        column: &str,
+        level: &Level,
         table: &str,
         backend: Box<dyn Backend + Sync + Send>,
         sys: &mut SystemRunner
 ) -> Result<Vec<String>, Error> {
+    let style = backend.identifier_style();
     let future = backend
         .exec_sql(
-            format!("select distinct {} from {}", column, table).to_string()
+            format!("select distinct {} from {}",
+                level.key_column_sql(style),
+                quote_identifier(table, style),
+            ).to_string()
         );
 
     let mut df = match sys.block_on(future) {
@@ -1,5 +1,5 @@
 mod cache;
 mod config;
 
-pub use self::cache::{Cache, CubeCache, Time, TimePrecision, TimeValue, populate_cache};
+pub use self::cache::{Cache, CubeCache, LevelCache, Time, TimePrecision, TimeValue, populate_cache};
 pub use self::config::{LogicLayerConfig, read_config, read_config_str};
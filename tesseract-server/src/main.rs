@@ -19,25 +19,49 @@
 //! The database is able to be declared in the schema, each fact table and dim can be from
 //! different databases. Supported: clickhouse, postgres, mysql, sqlite.
 
+mod api_keys;
 mod app;
+mod audit;
+mod check;
+mod cors;
 mod db_config;
 mod errors;
 mod auth;
 pub mod handlers;
+mod jwt_keys;
 mod logic_layer;
+mod middleware;
+mod rate_limit;
+mod restrictions;
+mod scaffold;
 mod schema_config;
+mod schema_source;
+mod schema_vars;
+mod tenant;
+mod tls;
+mod watcher;
 
 use actix_web::server;
 use dotenv::dotenv;
-use failure::{Error, format_err};
+use failure::{Error, bail, format_err};
 use log::*;
 use std::env;
+use std::sync::Arc;
+use std::time::Duration;
 use structopt::StructOpt;
+use tesseract_core::DEFAULT_ALLOWED_ACCESS;
+use tesseract_core::schema::diff::SchemaDiff;
+use tesseract_core::schema::mondrian::MondrianImport;
 use url::Url;
 
-use std::sync::{Arc, RwLock};
-
-use crate::app::{EnvVars, SchemaSource, create_app};
+use crate::api_keys::ApiKeys;
+use crate::app::{EnvVars, SchemaSource};
+use crate::audit::AuditLog;
+use crate::auth::JwtConfig;
+use crate::cors::CorsConfig;
+use crate::rate_limit::RateLimiter;
+use crate::tenant::{Tenant, TenantConfig, TenantDefaults, TenantRegistry, TenantRoute};
+use crate::tls::TlsCerts;
 
 
 fn main() -> Result<(), Error> {
@@ -47,6 +71,61 @@ fn main() -> Result<(), Error> {
     dotenv().ok();
     let opt = Opt::from_args();
 
+    // Subcommands which don't need a database
+    if let Some(Command::ImportMondrian { ref path }) = opt.cmd {
+        let raw_schema = std::fs::read_to_string(path)
+            .map_err(|err| format_err!("Could not read Mondrian schema {}: {}", path, err))?;
+
+        let import = MondrianImport::from_xml(&raw_schema)?;
+
+        // warnings go to stderr, so that stdout can be redirected to a schema file
+        for warning in &import.warnings {
+            eprintln!("warning: {}", warning);
+        }
+        if let Err(err) = import.schema() {
+            eprintln!("warning: imported schema is not valid: {}", err);
+        }
+
+        println!("{}", import.to_json()?);
+
+        return Ok(());
+    }
+
+    if let Some(Command::CheckSchema { ref path, ref logic_layer_config }) = opt.cmd {
+        let logic_layer_config = logic_layer_config.clone()
+            .or_else(|| env::var("TESSERACT_LOGIC_LAYER_CONFIG_FILEPATH").ok());
+
+        let problems = check::check_schema(path, logic_layer_config.as_ref().map(|p| p.as_str()));
+
+        if problems.is_empty() {
+            println!("{}: ok", path);
+            return Ok(());
+        }
+
+        for problem in &problems {
+            println!("{}", problem);
+        }
+        println!("{} problem(s) found", problems.len());
+
+        std::process::exit(1);
+    }
+
+    if let Some(Command::DiffSchema { ref old, ref new }) = opt.cmd {
+        let old_schema = schema_config::read_schema(old)
+            .map_err(|err| format_err!("Could not read schema {}: {}", old, err))?;
+        let new_schema = schema_config::read_schema(new)
+            .map_err(|err| format_err!("Could not read schema {}: {}", new, err))?;
+
+        let diff = SchemaDiff::new(&old_schema, &new_schema);
+        print!("{}", diff);
+
+        if diff.has_breaking() {
+            std::process::exit(1);
+        }
+
+        return Ok(());
+    }
+
     // debug is boolean, but env var is Result.
     // cli opt overrides env var if env_var is false
     let env_var_debug = env::var("TESSERACT_DEBUG")
@@ -88,23 +167,89 @@ fn main() -> Result<(), Error> {
     // address
     let server_addr = opt.address.unwrap_or("127.0.0.1:7777".to_owned());
 
-    // JSONWebToken Secret
-    let jwt_secret = env::var("TESSERACT_JWT_SECRET").ok();
+    // HTTPS: certificate chain and private key
+    let tls_cert_path = env::var("TESSERACT_TLS_CERT_FILEPATH").ok().or(opt.tls_cert);
+    let tls_key_path = env::var("TESSERACT_TLS_KEY_FILEPATH").ok().or(opt.tls_key);
+    let tls = match (tls_cert_path, tls_key_path) {
+        (Some(cert_path), Some(key_path)) => Some(Arc::new(TlsCerts::load(&cert_path, &key_path)?)),
+        (None, None) => None,
+        _ => return Err(format_err!("TLS needs both a certificate and a private key")),
+    };
+
+    // Plain HTTP listener for health checks, when serving HTTPS
+    let health_addr = env::var("TESSERACT_HEALTH_ADDR").ok().or(opt.health_address);
+    if health_addr.is_some() && tls.is_none() {
+        return Err(format_err!("The health check listener is only used with TLS"));
+    }
+
+    // JSONWebToken: shared secret, public keys or JWKS
+    let jwt = JwtConfig::from_env()?;
+
+    // API keys, alongside JWTs
+    let api_keys = match env::var("TESSERACT_API_KEYS_FILEPATH") {
+        Ok(path) => Some(Arc::new(ApiKeys::read(&path)?)),
+        Err(_) => None,
+    };
+
+    // CORS, instead of adding the headers in a reverse proxy
+    let cors = CorsConfig::from_env()?;
+
+    // Audit log of data access
+    let audit_log = AuditLog::from_env()?.map(Arc::new);
+
+    // Rate limits per client
+    let rate_limiter = match env::var("TESSERACT_RATE_LIMITS_FILEPATH") {
+        Ok(path) => Some(Arc::new(RateLimiter::read(&path)?)),
+        Err(_) => None,
+    };
 
     // flush
     let flush_secret = env::var("TESSERACT_FLUSH_SECRET").ok();
 
+    // Admin api: JWT auth level allowed to manage the schema, besides the flush secret
+    let admin_auth_level = match env::var("TESSERACT_ADMIN_AUTH_LEVEL") {
+        Ok(level) => {
+            let level = level.parse::<i32>()
+                .map_err(|_| format_err!("TESSERACT_ADMIN_AUTH_LEVEL must be an integer, found: {}", level))?;
+            // anonymous requests get the default access level
+            if level <= DEFAULT_ALLOWED_ACCESS {
+                bail!("TESSERACT_ADMIN_AUTH_LEVEL must be greater than {}, found: {}", DEFAULT_ALLOWED_ACCESS, level);
+            }
+            Some(level)
+        },
+        Err(_) => None,
+    };
+
     // Database
-    let db_url_full = env::var("TESSERACT_DATABASE_URL")
-        .or(opt.database_url.ok_or(format_err!("")))
-        .map_err(|_| format_err!("database url not found; either TESSERACT_DATABASE_URL or cli option required"))?;
+    // Required, unless every tenant has its own database url
+    let db_url_full = env::var("TESSERACT_DATABASE_URL").ok()
+        .or(opt.database_url);
+
+    // Subcommands, which only need the database
+    if let Some(Command::Scaffold { table, foreign_keys, format }) = opt.cmd {
+        let db_url_full = db_url_full
+            .ok_or_else(|| format_err!("database url not found; either TESSERACT_DATABASE_URL or cli option required"))?;
+        let (db, _, _) = db_config::get_db(&db_url_full)?;
+
+        let foreign_keys = foreign_keys.iter()
+            .map(|fk| fk.parse())
+            .collect::<Result<Vec<scaffold::ForeignKey>, _>>()?;
+
+        let mut sys = actix::System::new("tesseract-scaffold");
+        let draft = scaffold::scaffold_cube(&scaffold::parse_table(&table), foreign_keys, &*db, &mut sys)?;
+
+        match format.as_str() {
+            "xml" => println!("{}", draft.to_xml()),
+            "json" => println!("{}", serde_json::to_string_pretty(&draft.to_json())?),
+            _ => return Err(format_err!("Scaffold format must be xml or json, found: {}", format)),
+        }
 
-    let (db, db_url, db_type) = db_config::get_db(&db_url_full)?;
-    let db_type_viz = db_type.clone();
+        return Ok(());
+    }
 
-    // Schema
-    let schema_path = env::var("TESSERACT_SCHEMA_FILEPATH")
-        .expect("TESSERACT_SCHEMA_FILEPATH not found");
+    // Schema validation against the database: "strict" refuses to start if
+    // tables or columns are missing, "warn" only logs them.
+    let schema_validation = env::var("TESSERACT_SCHEMA_VALIDATION").ok();
 
     // Geoservice
     let geoservice_url = match env::var("TESSERACT_GEOSERVICE_URL") {
@@ -117,80 +262,155 @@ fn main() -> Result<(), Error> {
         }
     };
 
-    // NOTE: Local schema is the only supported SchemaSource for now
-    let schema_source = SchemaSource::LocalSchema { filepath: schema_path.clone() };
+    // Polling for remote and database schemas, in seconds
+    let schema_poll_interval = match env::var("TESSERACT_SCHEMA_POLL_INTERVAL") {
+        Ok(interval) => {
+            let secs = interval.parse::<u64>()
+                .map_err(|_| format_err!("could not parse seconds from env_var TESSERACT_SCHEMA_POLL_INTERVAL"))?;
+            if secs == 0 {
+                bail!("TESSERACT_SCHEMA_POLL_INTERVAL must be at least 1 second");
+            }
+            Some(Duration::from_secs(secs))
+        },
+        Err(_) => None,
+    };
+
+    // Previous schema versions kept for rollback through the admin api
+    let schema_versions = match env::var("TESSERACT_SCHEMA_VERSIONS") {
+        Ok(versions) => {
+            versions.parse::<usize>()
+                .map_err(|_| format_err!("could not parse number from env_var TESSERACT_SCHEMA_VERSIONS"))?
+        },
+        Err(_) => tenant::DEFAULT_SCHEMA_VERSIONS,
+    };
+
+    // Reload local schema and logic layer config files on change
+    let watch_files = match env::var("TESSERACT_WATCH_SCHEMA") {
+        Ok(watch) => {
+            watch.parse::<bool>()
+                .map_err(|_| format_err!("could not parse bool from env_var TESSERACT_WATCH_SCHEMA"))?
+        },
+        Err(_) => false,
+    };
 
-    let mut schema = schema_config::read_schema(&schema_path)?;
-    schema.validate()?;
-    let mut has_unique_levels_properties = schema.has_unique_levels_properties();
-    let schema_arc = Arc::new(RwLock::new(schema.clone()));
-    let jwt_status = if jwt_secret.is_some() {
+    let jwt_status = if jwt.is_some() {
         "ON"
     } else {
         "OFF"
     };
-    // Env
-    let env_vars = EnvVars {
-        database_url: db_url.clone(),
-        geoservice_url,
-        schema_source,
-        jwt_secret,
-        flush_secret,
-    };
-
-    // Logic Layer Config
-    let logic_layer_config = match env::var("TESSERACT_LOGIC_LAYER_CONFIG_FILEPATH") {
-        Ok(config_path) => {
-            match logic_layer::read_config(&config_path) {
-                Ok(config_obj) => {
-                    has_unique_levels_properties = config_obj.has_unique_levels_properties(&schema)?;
-                    Some(config_obj)
-                },
-                Err(err) => return Err(err)
-            }
+
+    let api_keys_status = if api_keys.is_some() {
+        "ON"
+    } else {
+        "OFF"
+    };
+
+    let cors_status = if cors.is_some() {
+        "ON"
+    } else {
+        "OFF"
+    };
+
+    let rate_limit_status = if rate_limiter.is_some() {
+        "ON"
+    } else {
+        "OFF"
+    };
+
+    // Env, shared by all tenants. Database url and schema source are set per tenant.
+    let tenant_defaults = TenantDefaults {
+        database_url: db_url_full,
+        env_vars: EnvVars {
+            database_url: String::new(),
+            geoservice_url,
+            schema_source: SchemaSource::LocalSchema { filepath: String::new() },
+            jwt,
+            api_keys,
+            rate_limiter,
+            cors,
+            tls: tls.clone(),
+            audit_log,
+            flush_secret,
+            admin_auth_level,
+            schema_validation,
         },
-        Err(_) => None
+        schema_poll_interval,
+        watch_files,
+        schema_versions,
     };
 
     // Initialize actix system
     let mut sys = actix::System::new("tesseract");
 
-    // Populate internal cache
-    let cache = logic_layer::populate_cache(
-        schema.clone(), &logic_layer_config, db.clone(), &mut sys
-    ).map_err(|err| format_err!("Cache population error: {}", err))?;
+    // Tenants: several schemas from a tenants config, or a single schema
+    // (with the logic layer config from env vars) served at the root.
+    let tenants = match env::var("TESSERACT_TENANTS_FILEPATH") {
+        Ok(tenants_path) => {
+            let tenants_config = tenant::read_tenants_config(&tenants_path)?;
+            TenantRegistry::load(&tenants_config, &tenant_defaults, &mut sys)?
+        },
+        Err(_) => {
+            let tenant_config = TenantConfig {
+                name: "default".to_owned(),
+                schema_path: env::var("TESSERACT_SCHEMA_FILEPATH").ok(),
+                schema_url: env::var("TESSERACT_SCHEMA_URL").ok(),
+                schema_db_name: env::var("TESSERACT_SCHEMA_DB_NAME").ok(),
+                database_url: None,
+                logic_layer_config_path: env::var("TESSERACT_LOGIC_LAYER_CONFIG_FILEPATH").ok(),
+                host: None,
+            };
+
+            TenantRegistry::single(Tenant::load(&tenant_config, TenantRoute::Root, &tenant_defaults, &mut sys)?)
+        },
+    };
+
+    // Initialize Server
+    let server_tenants = tenants.clone();
 
-    let cache_arc = Arc::new(RwLock::new(cache));
+    let server = server::new(
+        move|| server_tenants.create_apps(debug, streaming_response)
+        );
 
-    // Create lock on logic layer config
-    let logic_layer_config = match logic_layer_config {
-        Some(ll_config) => Some(Arc::new(RwLock::new(ll_config))),
-        None => None
+    let server = match tls {
+        Some(ref certs) => {
+            tls::reload_on_hup(certs.clone())?;
+            server.bind_ssl(&server_addr, TlsCerts::acceptor(certs)?)
+        },
+        None => server.bind(&server_addr),
     };
 
-    // Initialize Server
-    server::new(
-        move|| create_app(
-                debug,
-                db.clone(),
-                db_type.clone(),
-                env_vars.clone(),
-                schema_arc.clone(),
-                cache_arc.clone(),
-                logic_layer_config.clone(),
-                streaming_response,
-                has_unique_levels_properties.clone(),
-            )
-        )
-        .bind(&server_addr)
+    server
         .expect(&format!("cannot bind to {}", server_addr))
         .start();
 
-    println!("Tesseract listening on: {}", server_addr);
-    println!("Tesseract database:     {}, {}", db_url, db_type_viz);
-    println!("Tesseract schema path:  {}", schema_path);
+    if let Some(ref health_addr) = health_addr {
+        server::new(app::create_health_app)
+            .bind(health_addr)
+            .expect(&format!("cannot bind to {}", health_addr))
+            .start();
+    }
+
+    println!("Tesseract listening on: {}{}", server_addr, if tls.is_some() { " (HTTPS)" } else { "" });
+    if let Some(ref health_addr) = health_addr {
+        println!("Tesseract health checks on: {}", health_addr);
+    }
+
+    for tenant in tenants.tenants() {
+        let route = match tenant.route {
+            TenantRoute::Root => "".to_owned(),
+            TenantRoute::Prefix(ref prefix) => format!(" (path prefix {})", prefix),
+            TenantRoute::Host(ref host) => format!(" (host {})", host),
+        };
+        println!("Tesseract schema {}{}:", tenant.name, route);
+        println!("  database:    {}, {}", tenant.env_vars.database_url, tenant.db_type);
+
+        println!("  schema:      {}", tenant.env_vars.schema_source);
+    }
 
     println!("Tesseract JWT token protection: {}", jwt_status);
+    println!("Tesseract API keys: {}", api_keys_status);
+    println!("Tesseract rate limiting: {}", rate_limit_status);
+    println!("Tesseract CORS: {}", cors_status);
 
     if debug {
         println!("Tesseract debug mode: ON");
@@ -223,4 +443,70 @@ struct Opt {
 
     #[structopt(long="streaming")]
     streaming_response: bool,
+
+    /// PEM file with the TLS certificate chain, to serve HTTPS
+    #[structopt(long="tls-cert")]
+    tls_cert: Option<String>,
+
+    /// PEM file with the TLS private key
+    #[structopt(long="tls-key")]
+    tls_key: Option<String>,
+
+    /// Address of a plain HTTP listener for health checks, when serving HTTPS
+    #[structopt(long="health-addr")]
+    health_address: Option<String>,
+
+    #[structopt(subcommand)]
+    cmd: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Prints a draft schema for a fact table, introspected from the database.
+    #[structopt(name="scaffold")]
+    Scaffold {
+        /// Fact table, optionally with its database schema (e.g. public.sales)
+        table: String,
+
+        /// Foreign key of the fact table, as column=table.primary_key. Needed
+        /// for databases without foreign key constraints (e.g. clickhouse).
+        #[structopt(long="foreign-key")]
+        foreign_keys: Vec<String>,
+
+        /// Output format, xml or json
+        #[structopt(long="format", default_value="xml")]
+        format: String,
+    },
+
+    /// Converts a Mondrian 3 XML schema into a tesseract JSON schema.
+    #[structopt(name="import-mondrian")]
+    ImportMondrian {
+        /// Path to the Mondrian schema
+        path: String,
+    },
+
+    /// Checks a schema, and optionally a logic layer config, without a
+    /// database. Exits with an error if there are problems.
+    #[structopt(name="check-schema")]
+    CheckSchema {
+        /// Path to the schema file or directory
+        path: String,
+
+        /// Path to the logic layer config. Defaults to
+        /// TESSERACT_LOGIC_LAYER_CONFIG_FILEPATH.
+        #[structopt(long="logic-layer-config")]
+        logic_layer_config: Option<String>,
+    },
+
+    /// Lists changes between two versions of a schema, breaking changes
+    /// (which break existing queries) first. Exits with an error if there
+    /// are breaking changes.
+    #[structopt(name="diff-schema")]
+    DiffSchema {
+        /// Path to the old schema file or directory
+        old: String,
+
+        /// Path to the new schema file or directory
+        new: String,
+    },
 }
@@ -0,0 +1,83 @@
+use actix_web::{HttpRequest, HttpResponse, Result as ActixResult};
+use actix_web::http::header;
+use actix_web::middleware::{Finished, Middleware, Started};
+use log::*;
+use std::sync::Arc;
+use tesseract_core::DEFAULT_ALLOWED_ACCESS;
+
+use crate::app::AppState;
+use crate::auth::{extract_api_key, extract_token, user_auth_level, user_subject};
+use crate::rate_limit::{Permit, RateLimited, RateLimiter};
+
+
+/// Rate limits each client, identified by API key, JWT `sub` or IP, in that
+/// order.
+pub struct RateLimit(pub Arc<RateLimiter>);
+
+impl Middleware<AppState> for RateLimit {
+    fn start(&self, req: &HttpRequest<AppState>) -> ActixResult<Started> {
+        let (client, auth_level) = identify_client(req, &self.0);
+
+        match RateLimiter::acquire(&self.0, &client, auth_level) {
+            Ok(Some(permit)) => {
+                req.extensions_mut().insert(permit);
+                Ok(Started::Done)
+            },
+            Ok(None) => Ok(Started::Done),
+            Err(limited) => {
+                info!("Rate limited {}: {:?}", client, limited);
+
+                let (retry_after, message) = match limited {
+                    RateLimited::TooManyRequests { retry_after } => (retry_after, "Too many requests"),
+                    RateLimited::TooManyInFlight => (1, "Too many requests in flight"),
+                };
+
+                Ok(Started::Response(
+                    HttpResponse::TooManyRequests()
+                        .header(header::RETRY_AFTER, retry_after.to_string())
+                        .json(message)
+                ))
+            },
+        }
+    }
+
+    fn finish(&self, req: &HttpRequest<AppState>, _resp: &HttpResponse) -> Finished {
+        // Releases the request's place in flight
+        req.extensions_mut().remove::<Permit>();
+        Finished::Done
+    }
+}
+
+/// Client id and auth level. Unknown API keys and invalid tokens fall back to
+/// the IP; they are refused by the handlers.
+fn identify_client(req: &HttpRequest<AppState>, limiter: &RateLimiter) -> (String, i32) {
+    let env_vars = &req.state().env_vars;
+
+    if let Some(ref api_keys) = env_vars.api_keys {
+        let api_key = extract_api_key(req)
+            .and_then(|raw_key| api_keys.find(&raw_key).cloned());
+
+        if let Some(api_key) = api_key {
+            return (format!("key:{}", api_key.name), api_key.auth_level);
+        }
+    }
+
+    let user_token = extract_token(req);
+    if let Some(sub) = user_subject(&env_vars.jwt, &user_token) {
+        let auth_level = user_auth_level(&env_vars.jwt, &user_token)
+            .unwrap_or(DEFAULT_ALLOWED_ACCESS);
+
+        return (format!("sub:{}", sub), auth_level);
+    }
+
+    let forwarded_for = req.headers().get_all("x-forwarded-for").iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let ip = req.peer_addr()
+        .map(|peer| limiter.client_ip(peer.ip(), &forwarded_for).to_string())
+        .unwrap_or_default();
+
+    (format!("ip:{}", ip), DEFAULT_ALLOWED_ACCESS)
+}
@@ -0,0 +1,321 @@
+//! Per-client rate limiting: requests per interval, and requests in flight.
+//!
+//! Clients are identified by API key, JWT `sub` or IP (see
+//! `middleware::RateLimit`), and get the limits of the highest auth level not
+//! above their own. Usage is kept in memory, per server, in fixed windows of
+//! `interval` seconds.
+//!
+//! The IP is the peer address of the connection, unless the peer is one of
+//! the `trusted_proxies`; then it's taken from `X-Forwarded-For`.
+
+use failure::{Error, format_err, bail};
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+
+/// Past this many clients, idle ones are forgotten once their window ends.
+const MAX_IDLE_CLIENTS: usize = 10_000;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RateLimitConfig {
+    pub limits: Vec<Limits>,
+    /// Proxies allowed to set the client IP in `X-Forwarded-For`
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Limits {
+    /// Lowest auth level these limits apply to
+    pub auth_level: i32,
+    /// Requests allowed per `interval`
+    pub requests: Option<u64>,
+    /// In seconds
+    pub interval: Option<u64>,
+    /// Requests being processed at the same time
+    pub max_in_flight: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RateLimited {
+    /// Seconds until the interval ends
+    TooManyRequests { retry_after: u64 },
+    TooManyInFlight,
+}
+
+#[derive(Debug, Default)]
+struct ClientUsage {
+    window: u64,
+    /// Unix time the window ends, when its requests are forgotten
+    window_end: u64,
+    requests: u64,
+    in_flight: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    requests: AtomicUsize,
+    limited_requests: AtomicUsize,
+    limited_in_flight: AtomicUsize,
+    in_flight: AtomicUsize,
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// Sorted by auth level, highest first
+    limits: Vec<Limits>,
+    trusted_proxies: Vec<IpAddr>,
+    clients: Mutex<HashMap<String, ClientUsage>>,
+    counters: Counters,
+}
+
+/// A request in flight, released when dropped.
+#[derive(Debug)]
+pub struct Permit {
+    limiter: Arc<RateLimiter>,
+    client: String,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.limiter.release(&self.client);
+    }
+}
+
+impl RateLimiter {
+    pub fn read(path: &str) -> Result<Self, Error> {
+        let json = fs::read_to_string(path)
+            .map_err(|err| format_err!("Could not read rate limits {}: {}", path, err))?;
+        let config: RateLimitConfig = serde_json::from_str(&json)
+            .map_err(|err| format_err!("Invalid rate limits {}: {}", path, err))?;
+
+        RateLimiter::new(config)
+    }
+
+    pub fn new(config: RateLimitConfig) -> Result<Self, Error> {
+        let mut limits = config.limits;
+
+        for limit in &limits {
+            if limits.iter().filter(|l| l.auth_level == limit.auth_level).count() > 1 {
+                bail!("Duplicate rate limits for auth level {}", limit.auth_level);
+            }
+            match (limit.requests, limit.interval) {
+                (Some(_), Some(interval)) if interval > 0 => (),
+                (None, None) => (),
+                _ => bail!("Rate limits for auth level {}: requests needs a positive interval", limit.auth_level),
+            }
+        }
+
+        limits.sort_by(|a, b| b.auth_level.cmp(&a.auth_level));
+
+        Ok(RateLimiter {
+            limits,
+            trusted_proxies: config.trusted_proxies,
+            clients: Mutex::new(HashMap::new()),
+            counters: Counters::default(),
+        })
+    }
+
+    /// Client IP of a connection from `peer`. Going back from the last
+    /// `X-Forwarded-For` hop, each hop is taken while the previous one was a
+    /// trusted proxy, so clients can't choose their IP by setting the header.
+    pub fn client_ip(&self, peer: IpAddr, forwarded_for: &str) -> IpAddr {
+        let mut ip = peer;
+
+        for hop in forwarded_for.rsplit(',') {
+            if !self.trusted_proxies.contains(&ip) {
+                break;
+            }
+            match hop.trim().parse::<IpAddr>() {
+                Ok(hop) => ip = hop,
+                Err(_) => break,
+            }
+        }
+
+        ip
+    }
+
+    fn limits_for(&self, auth_level: i32) -> Option<&Limits> {
+        self.limits.iter().find(|limits| limits.auth_level <= auth_level)
+    }
+
+    /// Counts a request from `client`, and holds a place in flight until the
+    /// permit is dropped. Clients below every configured auth level are not
+    /// limited.
+    pub fn acquire(limiter: &Arc<Self>, client: &str, auth_level: i32) -> Result<Option<Permit>, RateLimited> {
+        limiter.acquire_at(client, auth_level, unix_now())
+            .map(|acquired| {
+                if acquired {
+                    Some(Permit { limiter: limiter.clone(), client: client.to_owned() })
+                } else {
+                    None
+                }
+            })
+    }
+
+    fn acquire_at(&self, client: &str, auth_level: i32, now: u64) -> Result<bool, RateLimited> {
+        self.counters.requests.fetch_add(1, Ordering::Relaxed);
+
+        let limits = match self.limits_for(auth_level) {
+            Some(limits) => limits,
+            None => return Ok(false),
+        };
+
+        let mut clients = self.clients.lock().unwrap();
+
+        if clients.len() > MAX_IDLE_CLIENTS {
+            clients.retain(|_, usage| usage.in_flight > 0 || usage.window_end > now);
+        }
+
+        let usage = clients.entry(client.to_owned()).or_insert_with(ClientUsage::default);
+
+        if let Some(max_in_flight) = limits.max_in_flight {
+            if usage.in_flight >= max_in_flight {
+                self.counters.limited_in_flight.fetch_add(1, Ordering::Relaxed);
+                return Err(RateLimited::TooManyInFlight);
+            }
+        }
+
+        if let (Some(requests), Some(interval)) = (limits.requests, limits.interval) {
+            let window = now / interval;
+            if usage.window != window {
+                usage.window = window;
+                usage.window_end = (window + 1) * interval;
+                usage.requests = 0;
+            }
+
+            if usage.requests >= requests {
+                self.counters.limited_requests.fetch_add(1, Ordering::Relaxed);
+                return Err(RateLimited::TooManyRequests {
+                    retry_after: (window + 1) * interval - now,
+                });
+            }
+
+            usage.requests += 1;
+        }
+
+        usage.in_flight += 1;
+        self.counters.in_flight.fetch_add(1, Ordering::Relaxed);
+
+        Ok(true)
+    }
+
+    fn release(&self, client: &str) {
+        let mut clients = self.clients.lock().unwrap();
+
+        if let Some(usage) = clients.get_mut(client) {
+            usage.in_flight = usage.in_flight.saturating_sub(1);
+            self.counters.in_flight.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Counters, in the Prometheus text format.
+    pub fn metrics(&self) -> String {
+        let counters = &self.counters;
+        let mut out = String::new();
+
+        let _ = writeln!(out, "# TYPE tesseract_rate_limit_requests_total counter");
+        let _ = writeln!(out, "tesseract_rate_limit_requests_total {}", counters.requests.load(Ordering::Relaxed));
+        let _ = writeln!(out, "# TYPE tesseract_rate_limit_rejected_total counter");
+        let _ = writeln!(out, "tesseract_rate_limit_rejected_total{{reason=\"requests\"}} {}", counters.limited_requests.load(Ordering::Relaxed));
+        let _ = writeln!(out, "tesseract_rate_limit_rejected_total{{reason=\"in_flight\"}} {}", counters.limited_in_flight.load(Ordering::Relaxed));
+        let _ = writeln!(out, "# TYPE tesseract_requests_in_flight gauge");
+        let _ = writeln!(out, "tesseract_requests_in_flight {}", counters.in_flight.load(Ordering::Relaxed));
+
+        out
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rate_limits() {
+        let config: RateLimitConfig = serde_json::from_str(r#"{ "limits": [
+            { "auth_level": 0, "requests": 2, "interval": 60, "max_in_flight": 1 },
+            { "auth_level": 2, "max_in_flight": 2 }
+        ] }"#).unwrap();
+        let limiter = Arc::new(RateLimiter::new(config).unwrap());
+
+        // below every auth level: not limited
+        assert_eq!(limiter.acquire_at("anonymous", -1, 100), Ok(false));
+
+        // requests per interval
+        assert_eq!(limiter.acquire_at("ip:1.2.3.4", 0, 100), Ok(true));
+        limiter.release("ip:1.2.3.4");
+        assert_eq!(limiter.acquire_at("ip:1.2.3.4", 1, 110), Ok(true));
+        limiter.release("ip:1.2.3.4");
+        assert_eq!(limiter.acquire_at("ip:1.2.3.4", 0, 115), Err(RateLimited::TooManyRequests { retry_after: 5 }));
+        assert_eq!(limiter.acquire_at("ip:1.2.3.4", 0, 120), Ok(true));
+
+        // in flight, until the permit is dropped
+        assert_eq!(limiter.acquire_at("ip:1.2.3.4", 0, 121), Err(RateLimited::TooManyInFlight));
+        limiter.release("ip:1.2.3.4");
+
+        let first = RateLimiter::acquire(&limiter, "key:partner", 3).unwrap();
+        let second = RateLimiter::acquire(&limiter, "key:partner", 3).unwrap();
+        assert!(first.is_some() && second.is_some());
+        assert_eq!(RateLimiter::acquire(&limiter, "key:partner", 3).unwrap_err(), RateLimited::TooManyInFlight);
+        drop(first);
+        assert!(RateLimiter::acquire(&limiter, "key:partner", 3).is_ok());
+
+        let metrics = limiter.metrics();
+        assert!(metrics.contains("tesseract_rate_limit_rejected_total{reason=\"requests\"} 1\n"));
+        assert!(metrics.contains("tesseract_rate_limit_rejected_total{reason=\"in_flight\"} 2\n"));
+        assert!(metrics.contains("tesseract_requests_in_flight 1\n"));
+    }
+
+    #[test]
+    fn test_idle_clients() {
+        let config: RateLimitConfig = serde_json::from_str(r#"{ "limits": [
+            { "auth_level": 0, "requests": 1, "interval": 60 }
+        ] }"#).unwrap();
+        let limiter = RateLimiter::new(config).unwrap();
+
+        for i in 0..=MAX_IDLE_CLIENTS {
+            assert_eq!(limiter.acquire_at(&format!("ip:{}", i), 0, 100), Ok(true));
+            limiter.release(&format!("ip:{}", i));
+        }
+
+        // still limited while their window lasts, though past the idle limit
+        assert_eq!(limiter.acquire_at("ip:0", 0, 110), Err(RateLimited::TooManyRequests { retry_after: 10 }));
+        assert_eq!(limiter.clients.lock().unwrap().len(), MAX_IDLE_CLIENTS + 1);
+
+        // forgotten once it ends
+        assert_eq!(limiter.acquire_at("ip:new", 0, 125), Ok(true));
+        assert_eq!(limiter.clients.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let config: RateLimitConfig = serde_json::from_str(r#"{
+            "limits": [],
+            "trusted_proxies": ["10.0.0.2", "10.0.0.3"]
+        }"#).unwrap();
+        let limiter = RateLimiter::new(config).unwrap();
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+
+        // not through a trusted proxy: the header is ignored
+        assert_eq!(limiter.client_ip(ip("1.2.3.4"), "5.6.7.8"), ip("1.2.3.4"));
+
+        // the client can only prepend hops
+        assert_eq!(limiter.client_ip(ip("10.0.0.2"), "5.6.7.8, 1.2.3.4"), ip("1.2.3.4"));
+        assert_eq!(limiter.client_ip(ip("10.0.0.2"), "5.6.7.8, 1.2.3.4, 10.0.0.3"), ip("1.2.3.4"));
+        assert_eq!(limiter.client_ip(ip("10.0.0.2"), ""), ip("10.0.0.2"));
+        assert_eq!(limiter.client_ip(ip("10.0.0.2"), "unknown"), ip("10.0.0.2"));
+    }
+}
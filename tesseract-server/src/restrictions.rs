@@ -0,0 +1,363 @@
+//! Row-level security: restrictions from the JWT `restrictions` claim, which
+//! limit a user to some members of a level (e.g. their own region).
+//!
+//! Restrictions apply to every cube which has the restricted level. They are
+//! merged into the cuts of each query, intersected with the user's own cuts
+//! on the same level, so they can't be overridden. `/members` of the
+//! restricted level, and of other levels in its hierarchy, only lists the
+//! members the user can see, and `/relations` leaves the others out.
+
+use failure::{Error, format_err, bail};
+use std::collections::{HashMap, HashSet};
+
+use tesseract_core::names::{Cut, LevelName, Mask};
+use tesseract_core::schema::{Cube, Hierarchy};
+
+use crate::logic_layer::CubeCache;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Restriction {
+    pub level_name: LevelName,
+    pub members: Vec<String>,
+}
+
+/// Parses the `restrictions` claim. Level names are full level names, e.g.
+/// `Geography.Geography.Region` or `[Geography].[Region]`.
+pub fn parse_restrictions(claim: &HashMap<String, Vec<String>>) -> Result<Vec<Restriction>, Error> {
+    let mut restrictions = claim.iter()
+        .map(|(level_name, members)| {
+            let level_name = level_name.parse()
+                .map_err(|_| format_err!("Invalid level name in restrictions: {}", level_name))?;
+
+            Ok(Restriction { level_name, members: members.clone() })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    // claims are a map, so order them for stable queries
+    restrictions.sort_by(|a, b| a.level_name.to_string().cmp(&b.level_name.to_string()));
+
+    Ok(restrictions)
+}
+
+/// Merges restrictions into the cuts of a query on `cube`. User cuts on a
+/// restricted level are replaced by a single cut with the members they
+/// select out of the allowed ones. Errors if none of the allowed members are
+/// selected, or a user cut on a restricted level isn't exact.
+pub fn restrict_cuts(cube: &Cube, cuts: Vec<Cut>, restrictions: &[Restriction]) -> Result<Vec<Cut>, Error> {
+    let mut cuts = cuts;
+
+    for restriction in restrictions {
+        if cube.get_level(&restriction.level_name).is_none() {
+            continue;
+        }
+
+        let mut allowed = restriction.members.clone();
+
+        let (level_cuts, other_cuts): (Vec<Cut>, Vec<Cut>) = cuts.into_iter()
+            .partition(|cut| cut.level_name == restriction.level_name);
+
+        for cut in level_cuts {
+            if cut.for_match {
+                bail!("Cuts on restricted level {} must list members", restriction.level_name);
+            }
+
+            match cut.mask {
+                Mask::Include => allowed.retain(|member| cut.members.contains(member)),
+                Mask::Exclude => allowed.retain(|member| !cut.members.contains(member)),
+            }
+        }
+
+        if allowed.is_empty() {
+            bail!("No access to the requested members of {}", restriction.level_name);
+        }
+
+        cuts = other_cuts;
+        cuts.push(Cut {
+            level_name: restriction.level_name.clone(),
+            members: allowed,
+            mask: Mask::Include,
+            for_match: false,
+        });
+    }
+
+    Ok(cuts)
+}
+
+/// Members of `level_name` the user can see, or None if the level isn't
+/// restricted. Levels above or below a restricted level in its hierarchy are
+/// limited to the parents or children of the allowed members; levels of
+/// other hierarchies in the same dimension can't be listed.
+pub fn allowed_members(
+    cube: &Cube,
+    cube_cache: &CubeCache,
+    level_name: &LevelName,
+    restrictions: &[Restriction],
+) -> Result<Option<HashSet<String>>, Error>
+{
+    let mut allowed: Option<HashSet<String>> = None;
+
+    for restriction in restrictions {
+        let restricted = &restriction.level_name;
+
+        if restricted.dimension != level_name.dimension || cube.get_level(restricted).is_none() {
+            continue;
+        }
+
+        if restricted.hierarchy != level_name.hierarchy {
+            bail!("Members of dimension {} are restricted to hierarchy {}", restricted.dimension, restricted.hierarchy);
+        }
+
+        let hierarchy = find_hierarchy(cube, level_name)
+            .ok_or_else(|| format_err!("Hierarchy not found for {}", level_name))?;
+        let level_idx = level_index(hierarchy, &level_name.level)?;
+        let restricted_idx = level_index(hierarchy, &restricted.level)?;
+
+        let restricted_members: HashSet<&String> = restriction.members.iter().collect();
+
+        let members: HashSet<String> = if level_idx == restricted_idx {
+            restriction.members.iter().cloned().collect()
+        } else if level_idx > restricted_idx {
+            // children: members whose ancestor at the restricted level is allowed
+            let level_cache = cube_cache.level_caches.get(level_name)
+                .ok_or_else(|| format_err!("Level cache not found for {}", level_name))?;
+
+            let mut members = HashSet::new();
+            for member in &level_cache.members {
+                let ancestor = ancestor(cube_cache, hierarchy, level_name, member, level_idx, restricted_idx)?;
+                if ancestor.map(|a| restricted_members.contains(&a)).unwrap_or(false) {
+                    members.insert(member.clone());
+                }
+            }
+            members
+        } else {
+            // parents: ancestors of the allowed members
+            let mut members = HashSet::new();
+            for member in &restriction.members {
+                if let Some(ancestor) = ancestor(cube_cache, hierarchy, restricted, member, restricted_idx, level_idx)? {
+                    members.insert(ancestor);
+                }
+            }
+            members
+        };
+
+        allowed = Some(match allowed {
+            Some(allowed) => allowed.intersection(&members).cloned().collect(),
+            None => members,
+        });
+    }
+
+    Ok(allowed)
+}
+
+/// Checks members of any level against the restrictions, keeping the allowed
+/// members of each level it has seen. Members of levels whose allowed members
+/// can't be found are refused.
+pub struct MemberFilter<'a> {
+    cube: &'a Cube,
+    cube_cache: &'a CubeCache,
+    restrictions: &'a [Restriction],
+    allowed: HashMap<LevelName, Option<HashSet<String>>>,
+}
+
+impl<'a> MemberFilter<'a> {
+    pub fn new(cube: &'a Cube, cube_cache: &'a CubeCache, restrictions: &'a [Restriction]) -> Self {
+        MemberFilter { cube, cube_cache, restrictions, allowed: HashMap::new() }
+    }
+
+    pub fn allows(&mut self, level_name: &LevelName, member: &str) -> bool {
+        if self.restrictions.is_empty() {
+            return true;
+        }
+
+        let (cube, cube_cache, restrictions) = (self.cube, self.cube_cache, self.restrictions);
+
+        let allowed = self.allowed.entry(level_name.clone())
+            .or_insert_with(|| {
+                allowed_members(cube, cube_cache, level_name, restrictions)
+                    .unwrap_or_else(|_| Some(HashSet::new()))
+            });
+
+        match allowed {
+            Some(allowed) => allowed.contains(member),
+            None => true,
+        }
+    }
+}
+
+fn find_hierarchy<'a>(cube: &'a Cube, level_name: &LevelName) -> Option<&'a Hierarchy> {
+    cube.dimensions.iter()
+        .find(|dimension| dimension.name == level_name.dimension)
+        .and_then(|dimension| {
+            dimension.hierarchies.iter().find(|hierarchy| hierarchy.name == level_name.hierarchy)
+        })
+}
+
+fn level_index(hierarchy: &Hierarchy, level: &str) -> Result<usize, Error> {
+    hierarchy.levels.iter()
+        .position(|l| l.name == level)
+        .ok_or_else(|| format_err!("Level {} not found in hierarchy {}", level, hierarchy.name))
+}
+
+/// Walks up the parent maps from `member` of `level_name` (at `from_idx`) to
+/// the level at `to_idx`. None if a parent is missing from the cache.
+fn ancestor(
+    cube_cache: &CubeCache,
+    hierarchy: &Hierarchy,
+    level_name: &LevelName,
+    member: &str,
+    from_idx: usize,
+    to_idx: usize,
+) -> Result<Option<String>, Error>
+{
+    let mut current = member.to_owned();
+
+    for idx in (to_idx + 1..=from_idx).rev() {
+        let current_level = LevelName::new(
+            level_name.dimension.clone(),
+            level_name.hierarchy.clone(),
+            hierarchy.levels[idx].name.clone(),
+        );
+
+        let parent_map = cube_cache.level_caches.get(&current_level)
+            .and_then(|level_cache| level_cache.parent_map.as_ref())
+            .ok_or_else(|| format_err!("Parents not found in cache for {}", current_level))?;
+
+        current = match parent_map.get(&current) {
+            Some(parent) => parent.clone(),
+            None => return Ok(None),
+        };
+    }
+
+    Ok(Some(current))
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tesseract_core::Schema;
+    use crate::logic_layer::LevelCache;
+
+    fn cube() -> Cube {
+        let schema = Schema::from_xml(r##"
+            <Schema name="test">
+                <Cube name="sales">
+                    <Table name="sales" />
+                    <Dimension name="Geography" foreign_key="city_id">
+                        <Hierarchy name="Geography">
+                            <Table name="dim_geo" />
+                            <Level name="Region" key_column="region_id" />
+                            <Level name="City" key_column="city_id" />
+                        </Hierarchy>
+                    </Dimension>
+                    <Measure name="Quantity" column="quantity" aggregator="sum" />
+                </Cube>
+            </Schema>
+        "##).unwrap();
+
+        schema.cubes[0].clone()
+    }
+
+    fn restrictions() -> Vec<Restriction> {
+        let mut claim = HashMap::new();
+        claim.insert("Geography.Region".to_owned(), vec!["1".to_owned(), "2".to_owned()]);
+        // not in the cube, ignored
+        claim.insert("Company.Company".to_owned(), vec!["acme".to_owned()]);
+
+        parse_restrictions(&claim).unwrap()
+    }
+
+    #[test]
+    fn test_restrict_cuts() {
+        let cube = cube();
+        let region = LevelName::new("Geography", "Geography", "Region");
+
+        // no user cut: only allowed members
+        let cuts = restrict_cuts(&cube, vec![], &restrictions()).unwrap();
+        assert_eq!(cuts, vec![Cut::new("Geography", "Geography", "Region", vec!["1", "2"], Mask::Include, false)]);
+
+        // user cuts can only narrow the allowed members
+        let user_cuts = vec!["Geography.Region.2,3".parse().unwrap()];
+        let cuts = restrict_cuts(&cube, user_cuts, &restrictions()).unwrap();
+        assert_eq!(cuts[0].members, vec!["2".to_owned()]);
+
+        let user_cuts = vec!["~Geography.Region.1".parse().unwrap()];
+        let cuts = restrict_cuts(&cube, user_cuts, &restrictions()).unwrap();
+        assert_eq!(cuts[0].members, vec!["2".to_owned()]);
+
+        let user_cuts = vec![Cut::new("Geography", "Geography", "Region", vec!["3"], Mask::Include, false)];
+        assert!(restrict_cuts(&cube, user_cuts, &restrictions()).is_err());
+
+        // cuts on other levels are kept
+        let user_cuts = vec![Cut::new("Geography", "Geography", "City", vec!["10"], Mask::Include, false)];
+        let cuts = restrict_cuts(&cube, user_cuts, &restrictions()).unwrap();
+        assert_eq!(cuts.len(), 2);
+        assert_eq!(cuts[1].level_name, region);
+    }
+
+    #[test]
+    fn test_allowed_members() {
+        let cube = cube();
+        let region = LevelName::new("Geography", "Geography", "Region");
+        let city = LevelName::new("Geography", "Geography", "City");
+
+        let mut parent_map = HashMap::new();
+        parent_map.insert("10".to_owned(), "1".to_owned());
+        parent_map.insert("20".to_owned(), "2".to_owned());
+        parent_map.insert("30".to_owned(), "3".to_owned());
+
+        let mut level_caches = HashMap::new();
+        level_caches.insert(city.clone(), LevelCache {
+            unique_name: "City".to_owned(),
+            parent_map: Some(parent_map),
+            children_map: None,
+            neighbors_map: HashMap::new(),
+            members: vec!["10", "20", "30"].into_iter().map(|m| m.to_owned()).collect(),
+        });
+
+        let cube_cache = CubeCache {
+            name: "sales".to_owned(),
+            year_level: None, year_values: None,
+            quarter_level: None, quarter_values: None,
+            month_level: None, month_values: None,
+            week_level: None, week_values: None,
+            day_level: None, day_values: None,
+            time_level: None, time_values: None,
+            level_map: HashMap::new(),
+            property_map: HashMap::new(),
+            level_caches,
+            dimension_caches: HashMap::new(),
+            uncached_levels: HashSet::new(),
+        };
+
+        let allowed = |level_name| {
+            let mut members: Vec<_> = allowed_members(&cube, &cube_cache, level_name, &restrictions())
+                .unwrap().unwrap()
+                .into_iter().collect();
+            members.sort();
+            members
+        };
+
+        assert_eq!(allowed(&region), vec!["1", "2"]);
+        assert_eq!(allowed(&city), vec!["10", "20"]);
+
+        let other_dim = LevelName::new("Time", "Time", "Year");
+        assert_eq!(allowed_members(&cube, &cube_cache, &other_dim, &restrictions()).unwrap(), None);
+
+        let restrictions = restrictions();
+        let mut filter = MemberFilter::new(&cube, &cube_cache, &restrictions);
+        assert!(filter.allows(&region, "2"));
+        assert!(!filter.allows(&region, "3"));
+        assert!(filter.allows(&city, "10"));
+        assert!(!filter.allows(&city, "30"));
+        assert!(filter.allows(&other_dim, "2020"));
+
+        // levels of other hierarchies are refused, not unrestricted
+        let other_hierarchy = LevelName::new("Geography", "Postal", "Zip");
+        assert!(!filter.allows(&other_hierarchy, "10001"));
+
+        let mut unrestricted = MemberFilter::new(&cube, &cube_cache, &[]);
+        assert!(unrestricted.allows(&region, "3"));
+    }
+}
@@ -0,0 +1,723 @@
+//! Scaffolds a draft schema for a fact table, by introspecting the database
+//! catalog through the `Backend`.
+//!
+//! - Foreign keys of the fact table become dimensions on the referenced
+//!   tables. Databases without foreign key constraints (e.g. clickhouse)
+//!   can be given foreign keys explicitly. Dimensions are named after their
+//!   table, or after their foreign key when several reference the same
+//!   table.
+//! - Date columns of the fact table become time dimensions (`date_column`).
+//! - Other id and text columns of the fact table become dimensions on the
+//!   fact table.
+//! - Other numeric columns of the fact table become measures, with sum
+//!   aggregators.
+//!
+//! In dimension tables, the primary key is the lowest level, other `_id`
+//! columns are its parent levels, `<level>_name` (or `name`) columns are the
+//! levels' name columns, and the rest of the columns are properties.
+//!
+//! The draft is only a starting point, and is meant to be edited by hand.
+
+use actix::SystemRunner;
+use failure::{Error, format_err, bail};
+use serde_json::{json, Value};
+
+use tesseract_core::{Backend, Table};
+use tesseract_core::query_ir::MemberType;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CatalogColumn {
+    pub name: String,
+    pub col_type: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForeignKey {
+    pub column: String,
+    pub table: Table,
+    pub primary_key: String,
+}
+
+impl std::str::FromStr for ForeignKey {
+    type Err = Error;
+
+    /// Parses `column=table.primary_key`, where table can include a
+    /// database schema (`column=schema.table.primary_key`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut column_reference = s.splitn(2, '=');
+        let column = column_reference.next();
+        let reference = column_reference.next();
+
+        let (column, reference) = match (column, reference) {
+            (Some(c), Some(r)) if !c.is_empty() => (c, r),
+            _ => bail!("Foreign key must be column=table.primary_key, found: {}", s),
+        };
+
+        let mut table_key = reference.rsplitn(2, '.');
+        let primary_key = table_key.next();
+        let table = table_key.next();
+
+        match (table, primary_key) {
+            (Some(table), Some(primary_key)) if !table.is_empty() && !primary_key.is_empty() => {
+                Ok(ForeignKey {
+                    column: column.to_owned(),
+                    table: parse_table(table),
+                    primary_key: primary_key.to_owned(),
+                })
+            },
+            _ => bail!("Foreign key must be column=table.primary_key, found: {}", s),
+        }
+    }
+}
+
+/// Parses `table` or `schema.table`.
+pub fn parse_table(s: &str) -> Table {
+    let mut schema_table = s.rsplitn(2, '.');
+    let name = schema_table.next().unwrap_or("").to_owned();
+    let schema = schema_table.next().map(|s| s.to_owned());
+
+    Table { name, schema, primary_key: None }
+}
+
+/// Introspects the fact table and the tables it references, and returns a
+/// draft of a schema with one cube.
+///
+/// Foreign keys passed in are added to the ones found in the catalog.
+pub fn scaffold_cube(
+    fact_table: &Table,
+    foreign_keys: Vec<ForeignKey>,
+    backend: &dyn Backend,
+    sys: &mut SystemRunner,
+) -> Result<CubeDraft, Error>
+{
+    let fact_columns = table_columns(fact_table, backend, sys)?;
+
+    if fact_columns.is_empty() {
+        bail!("Table {} not found", fact_table.full_name());
+    }
+
+    let mut foreign_keys = foreign_keys;
+
+    if let Some(foreign_keys_sql) = backend.foreign_keys_sql(fact_table) {
+        let df = sys.block_on(backend.exec_sql(foreign_keys_sql))
+            .map_err(|err| format_err!("Error reading foreign keys of table {}: {}", fact_table.full_name(), err))?;
+
+        if df.columns.len() >= 4 {
+            let columns = df.columns[0].stringify_column_data();
+            let schemas = df.columns[1].stringify_column_data();
+            let tables = df.columns[2].stringify_column_data();
+            let primary_keys = df.columns[3].stringify_column_data();
+
+            for i in 0..columns.len() {
+                // explicit foreign keys take precedence
+                if foreign_keys.iter().any(|fk| fk.column == columns[i]) {
+                    continue;
+                }
+
+                // tables in the default schema are left unqualified
+                let schema = if fact_table.schema.as_ref() == Some(&schemas[i]) || fact_table.schema.is_none() {
+                    fact_table.schema.clone()
+                } else {
+                    Some(schemas[i].clone())
+                };
+
+                foreign_keys.push(ForeignKey {
+                    column: columns[i].clone(),
+                    table: Table { name: tables[i].clone(), schema, primary_key: None },
+                    primary_key: primary_keys[i].clone(),
+                });
+            }
+        }
+    }
+
+    let mut dim_tables = vec![];
+
+    for foreign_key in foreign_keys {
+        let dim_columns = table_columns(&foreign_key.table, backend, sys)?;
+
+        if dim_columns.is_empty() {
+            bail!("Table {} not found", foreign_key.table.full_name());
+        }
+
+        dim_tables.push((foreign_key, dim_columns));
+    }
+
+    Ok(CubeDraft::new(fact_table, &fact_columns, &dim_tables))
+}
+
+fn table_columns(
+    table: &Table,
+    backend: &dyn Backend,
+    sys: &mut SystemRunner,
+) -> Result<Vec<CatalogColumn>, Error>
+{
+    let columns_sql = backend.table_columns_sql(table)
+        .ok_or_else(|| format_err!("Scaffolding is not supported by this backend"))?;
+
+    let df = sys.block_on(backend.exec_sql(columns_sql))
+        .map_err(|err| format_err!("Error reading columns of table {}: {}", table.full_name(), err))?;
+
+    if df.columns.len() < 2 {
+        return Ok(vec![]);
+    }
+
+    let names = df.columns[0].stringify_column_data();
+    let col_types = df.columns[1].stringify_column_data();
+
+    Ok(names.into_iter()
+        .zip(col_types.into_iter())
+        .map(|(name, col_type)| CatalogColumn { name, col_type })
+        .collect())
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CubeDraft {
+    pub name: String,
+    pub table: Table,
+    pub dimensions: Vec<DimensionDraft>,
+    pub measures: Vec<MeasureDraft>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DimensionDraft {
+    pub name: String,
+    pub foreign_key: String,
+    /// `None` for dimensions on the fact table
+    pub table: Option<Table>,
+    pub primary_key: Option<String>,
+    pub levels: Vec<LevelDraft>,
+    /// Set for time dimensions generated from a date column
+    pub date_column: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelDraft {
+    pub name: String,
+    pub key_column: String,
+    pub name_column: Option<String>,
+    pub key_type: MemberType,
+    pub properties: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeasureDraft {
+    pub name: String,
+    pub column: String,
+}
+
+impl CubeDraft {
+    pub fn new(
+        fact_table: &Table,
+        fact_columns: &[CatalogColumn],
+        dim_tables: &[(ForeignKey, Vec<CatalogColumn>)],
+    ) -> Self
+    {
+        let mut dimensions = vec![];
+        let mut measures = vec![];
+
+        for column in fact_columns {
+            if let Some((foreign_key, dim_columns)) = dim_tables.iter().find(|(fk, _)| fk.column == column.name) {
+                dimensions.push(DimensionDraft::from_dim_table(foreign_key, dim_columns));
+            } else if is_date_type(&column.col_type) {
+                dimensions.push(DimensionDraft {
+                    name: caption(&column.name),
+                    foreign_key: column.name.clone(),
+                    table: None,
+                    primary_key: None,
+                    levels: vec![],
+                    date_column: Some(column.name.clone()),
+                });
+            } else if is_id_column(&column.name) || !is_numeric_type(&column.col_type) {
+                let level = LevelDraft {
+                    name: level_caption(&column.name),
+                    key_column: column.name.clone(),
+                    name_column: None,
+                    key_type: member_type(&column.col_type),
+                    properties: vec![],
+                };
+
+                dimensions.push(DimensionDraft {
+                    name: level.name.clone(),
+                    foreign_key: column.name.clone(),
+                    table: None,
+                    primary_key: None,
+                    levels: vec![level],
+                    date_column: None,
+                });
+            } else {
+                measures.push(MeasureDraft {
+                    name: caption(&column.name),
+                    column: column.name.clone(),
+                });
+            }
+        }
+
+        unique_dimension_names(&mut dimensions);
+
+        CubeDraft {
+            name: fact_table.name.clone(),
+            table: fact_table.clone(),
+            dimensions,
+            measures,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let dimensions: Vec<_> = self.dimensions.iter()
+            .map(|dim| {
+                if let Some(ref date_column) = dim.date_column {
+                    return json!({
+                        "name": dim.name,
+                        "date_column": date_column,
+                    });
+                }
+
+                let levels: Vec<_> = dim.levels.iter()
+                    .map(|level| {
+                        let mut level_json = json!({
+                            "name": level.name,
+                            "key_column": level.key_column,
+                            "key_type": member_type_str(&level.key_type),
+                        });
+
+                        if let Some(ref name_column) = level.name_column {
+                            level_json["name_column"] = json!(name_column);
+                        }
+                        if !level.properties.is_empty() {
+                            level_json["properties"] = level.properties.iter()
+                                .map(|p| json!({ "name": caption(p), "column": p }))
+                                .collect();
+                        }
+
+                        level_json
+                    })
+                    .collect();
+
+                let mut hierarchy_json = json!({
+                    "name": dim.name,
+                    "levels": levels,
+                });
+
+                if let Some(ref table) = dim.table {
+                    hierarchy_json["table"] = table_json(table);
+                }
+                if let Some(ref primary_key) = dim.primary_key {
+                    hierarchy_json["primary_key"] = json!(primary_key);
+                }
+
+                json!({
+                    "name": dim.name,
+                    "foreign_key": dim.foreign_key,
+                    "hierarchies": [hierarchy_json],
+                })
+            })
+            .collect();
+
+        let measures: Vec<_> = self.measures.iter()
+            .map(|mea| json!({
+                "name": mea.name,
+                "column": mea.column,
+                "aggregator": "sum",
+            }))
+            .collect();
+
+        json!({
+            "name": self.name,
+            "cubes": [{
+                "name": self.name,
+                "table": table_json(&self.table),
+                "dimensions": dimensions,
+                "measures": measures,
+            }],
+        })
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut xml = format!("<Schema name=\"{}\">\n", escape_xml(&self.name));
+        xml.push_str(&format!("    <Cube name=\"{}\">\n", escape_xml(&self.name)));
+        xml.push_str(&format!("        {}\n", table_xml(&self.table)));
+
+        for dim in &self.dimensions {
+            if let Some(ref date_column) = dim.date_column {
+                xml.push_str(&format!("        <Dimension name=\"{}\" date_column=\"{}\" />\n",
+                    escape_xml(&dim.name),
+                    escape_xml(date_column),
+                ));
+                continue;
+            }
+
+            xml.push_str(&format!("        <Dimension name=\"{}\" foreign_key=\"{}\">\n",
+                escape_xml(&dim.name),
+                escape_xml(&dim.foreign_key),
+            ));
+
+            match dim.primary_key {
+                Some(ref primary_key) => xml.push_str(&format!("            <Hierarchy name=\"{}\" primary_key=\"{}\">\n",
+                    escape_xml(&dim.name),
+                    escape_xml(primary_key),
+                )),
+                None => xml.push_str(&format!("            <Hierarchy name=\"{}\">\n", escape_xml(&dim.name))),
+            }
+
+            if let Some(ref table) = dim.table {
+                xml.push_str(&format!("                {}\n", table_xml(table)));
+            }
+
+            for level in &dim.levels {
+                let name_column = level.name_column.as_ref()
+                    .map(|c| format!(" name_column=\"{}\"", escape_xml(c)))
+                    .unwrap_or_else(|| "".to_owned());
+
+                let level_attrs = format!("name=\"{}\" key_column=\"{}\"{} key_type=\"{}\"",
+                    escape_xml(&level.name),
+                    escape_xml(&level.key_column),
+                    name_column,
+                    member_type_str(&level.key_type),
+                );
+
+                if level.properties.is_empty() {
+                    xml.push_str(&format!("                <Level {} />\n", level_attrs));
+                } else {
+                    xml.push_str(&format!("                <Level {}>\n", level_attrs));
+                    for property in &level.properties {
+                        xml.push_str(&format!("                    <Property name=\"{}\" column=\"{}\" />\n",
+                            escape_xml(&caption(property)),
+                            escape_xml(property),
+                        ));
+                    }
+                    xml.push_str("                </Level>\n");
+                }
+            }
+
+            xml.push_str("            </Hierarchy>\n");
+            xml.push_str("        </Dimension>\n");
+        }
+
+        for mea in &self.measures {
+            xml.push_str(&format!("        <Measure name=\"{}\" column=\"{}\" aggregator=\"sum\" />\n",
+                escape_xml(&mea.name),
+                escape_xml(&mea.column),
+            ));
+        }
+
+        xml.push_str("    </Cube>\n");
+        xml.push_str("</Schema>\n");
+        xml
+    }
+}
+
+impl DimensionDraft {
+    fn from_dim_table(foreign_key: &ForeignKey, dim_columns: &[CatalogColumn]) -> Self {
+        let column_names: Vec<_> = dim_columns.iter().map(|c| c.name.as_str()).collect();
+        let mut used_columns = vec![foreign_key.primary_key.as_str()];
+
+        // parent levels from other id columns, in column order
+        let mut levels: Vec<_> = dim_columns.iter()
+            .filter(|c| c.name != foreign_key.primary_key && is_id_column(&c.name))
+            .map(|c| {
+                used_columns.push(&c.name);
+                LevelDraft {
+                    name: level_caption(&c.name),
+                    key_column: c.name.clone(),
+                    name_column: None,
+                    key_type: member_type(&c.col_type),
+                    properties: vec![],
+                }
+            })
+            .collect();
+
+        let pk_type = dim_columns.iter()
+            .find(|c| c.name == foreign_key.primary_key)
+            .map(|c| member_type(&c.col_type))
+            .unwrap_or(MemberType::NonText);
+
+        let dim_name = caption(dim_table_name(&foreign_key.table.name));
+
+        levels.push(LevelDraft {
+            name: if id_prefix(&foreign_key.primary_key).is_some() {
+                level_caption(&foreign_key.primary_key)
+            } else {
+                dim_name.clone()
+            },
+            key_column: foreign_key.primary_key.clone(),
+            name_column: None,
+            key_type: pk_type,
+            properties: vec![],
+        });
+
+        // name columns: <level>_name, or name for the lowest level
+        let last_idx = levels.len() - 1;
+        for (idx, level) in levels.iter_mut().enumerate() {
+            let prefix = id_prefix(&level.key_column);
+            let name_column = prefix.map(|p| format!("{}_name", p))
+                .filter(|c| column_names.contains(&c.as_str()))
+                .or_else(|| {
+                    if idx == last_idx && column_names.contains(&"name") {
+                        Some("name".to_owned())
+                    } else {
+                        None
+                    }
+                });
+
+            level.name_column = name_column;
+        }
+
+        for level in &levels {
+            if let Some(ref name_column) = level.name_column {
+                used_columns.push(name_column);
+            }
+        }
+
+        let properties: Vec<_> = column_names.iter()
+            .filter(|c| !used_columns.contains(c))
+            .map(|c| c.to_string())
+            .collect();
+
+        levels[last_idx].properties = properties;
+
+        DimensionDraft {
+            name: dim_name,
+            foreign_key: foreign_key.column.clone(),
+            table: Some(foreign_key.table.clone()),
+            primary_key: Some(foreign_key.primary_key.clone()),
+            levels,
+            date_column: None,
+        }
+    }
+}
+
+/// Several foreign keys to the same table (e.g. `origin_city_id` and
+/// `destination_city_id`) give dimensions with the same name, so those are
+/// named after their foreign key instead (`Origin City`, `Destination City`).
+/// Names which still repeat get a number.
+fn unique_dimension_names(dimensions: &mut [DimensionDraft]) {
+    let names: Vec<_> = dimensions.iter().map(|d| d.name.clone()).collect();
+
+    for dim in dimensions.iter_mut() {
+        if names.iter().filter(|name| **name == dim.name).count() > 1 {
+            dim.name = level_caption(&dim.foreign_key);
+        }
+    }
+
+    let mut seen: Vec<String> = vec![];
+    for dim in dimensions.iter_mut() {
+        let mut name = dim.name.clone();
+        let mut n = 2;
+        while seen.contains(&name) {
+            name = format!("{} {}", dim.name, n);
+            n += 1;
+        }
+        dim.name = name.clone();
+        seen.push(name);
+    }
+}
+
+/// Strips wrappers like `Nullable(...)` and `LowCardinality(...)`.
+fn base_type(col_type: &str) -> String {
+    let mut col_type = col_type.trim().to_lowercase();
+
+    for wrapper in &["nullable(", "lowcardinality("] {
+        while col_type.starts_with(wrapper) && col_type.ends_with(')') {
+            col_type = col_type[wrapper.len()..col_type.len() - 1].to_owned();
+        }
+    }
+
+    col_type
+}
+
+fn is_numeric_type(col_type: &str) -> bool {
+    let col_type = base_type(col_type);
+
+    ["int", "uint", "float", "decimal", "numeric", "double", "real", "bigint", "smallint", "tinyint", "mediumint", "serial", "bigserial"]
+        .iter()
+        .any(|t| col_type.starts_with(t))
+}
+
+fn is_date_type(col_type: &str) -> bool {
+    let col_type = base_type(col_type);
+
+    col_type.starts_with("date") || col_type.starts_with("timestamp")
+}
+
+fn member_type(col_type: &str) -> MemberType {
+    if is_numeric_type(col_type) {
+        MemberType::NonText
+    } else {
+        MemberType::Text
+    }
+}
+
+fn member_type_str(member_type: &MemberType) -> &'static str {
+    match member_type {
+        MemberType::Text => "text",
+        MemberType::NonText => "nontext",
+    }
+}
+
+fn is_id_column(column: &str) -> bool {
+    column == "id" || column.ends_with("_id")
+}
+
+/// `country_id` -> `country`
+fn id_prefix(column: &str) -> Option<&str> {
+    if column.ends_with("_id") {
+        Some(&column[..column.len() - 3])
+    } else {
+        None
+    }
+}
+
+/// `dim_geo` -> `geo`
+fn dim_table_name(table: &str) -> &str {
+    if table.starts_with("dim_") {
+        &table[4..]
+    } else {
+        table
+    }
+}
+
+/// `total_sales` -> `Total Sales`
+fn caption(column: &str) -> String {
+    column.split('_')
+        .filter(|w| !w.is_empty())
+        .map(|w| {
+            let mut chars = w.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// `country_id` -> `Country`
+fn level_caption(column: &str) -> String {
+    caption(id_prefix(column).unwrap_or(column))
+}
+
+fn table_json(table: &Table) -> Value {
+    match table.schema {
+        Some(ref schema) => json!({ "name": table.name, "schema": schema }),
+        None => json!({ "name": table.name }),
+    }
+}
+
+fn table_xml(table: &Table) -> String {
+    match table.schema {
+        Some(ref schema) => format!("<Table name=\"{}\" schema=\"{}\" />", escape_xml(&table.name), escape_xml(schema)),
+        None => format!("<Table name=\"{}\" />", escape_xml(&table.name)),
+    }
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tesseract_core::Schema;
+
+    fn columns(cols: &[(&str, &str)]) -> Vec<CatalogColumn> {
+        cols.iter()
+            .map(|(name, col_type)| CatalogColumn { name: name.to_string(), col_type: col_type.to_string() })
+            .collect()
+    }
+
+    fn draft() -> CubeDraft {
+        let fact_table = parse_table("sales");
+        let fact_columns = columns(&[
+            ("geo_id", "Int32"),
+            ("product_code", "LowCardinality(String)"),
+            ("sale_date", "Date"),
+            ("quantity", "UInt32"),
+            ("total_price", "Nullable(Float64)"),
+        ]);
+        let dim_tables = vec![
+            (
+                "geo_id=dim_geo.id".parse().unwrap(),
+                columns(&[
+                    ("id", "Int32"),
+                    ("name", "String"),
+                    ("continent_id", "String"),
+                    ("continent_name", "String"),
+                    ("population", "UInt64"),
+                ]),
+            ),
+        ];
+
+        CubeDraft::new(&fact_table, &fact_columns, &dim_tables)
+    }
+
+    #[test]
+    fn test_draft() {
+        let draft = draft();
+
+        let dim_names: Vec<_> = draft.dimensions.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(dim_names, vec!["Geo", "Product Code", "Sale Date"]);
+
+        let geo = &draft.dimensions[0];
+        assert_eq!(geo.levels.len(), 2);
+        assert_eq!(geo.levels[0].name, "Continent");
+        assert_eq!(geo.levels[0].name_column, Some("continent_name".to_owned()));
+        assert_eq!(geo.levels[0].key_type, MemberType::Text);
+        assert_eq!(geo.levels[1].name, "Geo");
+        assert_eq!(geo.levels[1].name_column, Some("name".to_owned()));
+        assert_eq!(geo.levels[1].properties, vec!["population".to_owned()]);
+
+        let measure_names: Vec<_> = draft.measures.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(measure_names, vec!["Quantity", "Total Price"]);
+    }
+
+    #[test]
+    fn test_draft_unique_dimension_names() {
+        let fact_table = parse_table("flights");
+        let fact_columns = columns(&[
+            ("origin_city_id", "Int32"),
+            ("destination_city_id", "Int32"),
+            ("passengers", "UInt32"),
+        ]);
+        let city_columns = columns(&[("id", "Int32"), ("name", "String")]);
+        let dim_tables = vec![
+            ("origin_city_id=dim_city.id".parse().unwrap(), city_columns.clone()),
+            ("destination_city_id=dim_city.id".parse().unwrap(), city_columns),
+        ];
+
+        let draft = CubeDraft::new(&fact_table, &fact_columns, &dim_tables);
+
+        let dim_names: Vec<_> = draft.dimensions.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(dim_names, vec!["Origin City", "Destination City"]);
+        assert!(Schema::from_xml(&draft.to_xml()).unwrap().validate().is_ok());
+    }
+
+    #[test]
+    fn test_draft_schema() {
+        let draft = draft();
+
+        let mut schema = Schema::from_xml(&draft.to_xml()).unwrap();
+        schema.validate().unwrap();
+
+        let mut json_schema = Schema::from_json(&draft.to_json().to_string()).unwrap();
+        json_schema.validate().unwrap();
+
+        assert_eq!(schema, json_schema);
+    }
+
+    #[test]
+    fn test_parse_foreign_key() {
+        let fk: ForeignKey = "geo_id=public.dim_geo.id".parse().unwrap();
+        assert_eq!(fk.column, "geo_id");
+        assert_eq!(fk.table.full_name(), "public.dim_geo");
+        assert_eq!(fk.primary_key, "id");
+
+        assert!("geo_id".parse::<ForeignKey>().is_err());
+        assert!("geo_id=dim_geo".parse::<ForeignKey>().is_err());
+    }
+}
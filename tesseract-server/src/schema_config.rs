@@ -1,20 +1,71 @@
-use failure::{Error, format_err};
+use actix::SystemRunner;
+use failure::{Error, format_err, bail};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
-use tesseract_core::Schema;
+use tesseract_core::{Backend, Schema};
+use tesseract_core::schema::SchemaConfigJson;
+
+use crate::schema_vars;
 
 
 /// Reads a schema from an XML or JSON file and converts it into a `tesseract_core::Schema` object.
+///
+/// The schema can be split across multiple files, either by:
+/// - passing a directory, in which case all XML and JSON files in it are merged (in
+///   alphabetical order; the schema name comes from the first file), or
+/// - using include directives in the root file (`<Include path="..." />` in XML,
+///   `"includes": [{ "path": "..." }]` in JSON). Paths are relative to the including file,
+///   and included files can include other files.
+///
+/// Files are merged before being converted into a `Schema`, so shared dimensions can be
+/// defined once and used by cubes in other files.
+///
+/// Table names, columns and annotations can use environment variables, as `${VAR}` or
+/// `${VAR:-default}` (see `schema_vars`).
 pub fn read_schema(schema_path: &str) -> Result<Schema, Error> {
-    let schema_str = std::fs::read_to_string(&schema_path)
-        .map_err(|_| format_err!("Schema file not found at {}", schema_path))?;
+    read_schema_files(schema_path).map(|(schema, _)| schema)
+}
+
+/// Same as `read_schema`, but also returns the files the schema was read
+/// from, in the order they were merged.
+pub fn read_schema_files(schema_path: &str) -> Result<(Schema, Vec<PathBuf>), Error> {
+    let path = Path::new(schema_path);
+
+    let mut merger = SchemaConfigMerger::new();
+
+    if path.is_dir() {
+        let mut file_paths = vec![];
+
+        let entries = std::fs::read_dir(path)
+            .map_err(|err| format_err!("Could not read schema directory {}: {}", schema_path, err))?;
 
-    let schema = if schema_path.ends_with("xml") {
-        Schema::from_xml(&schema_str)?
-    } else if schema_path.ends_with("json") {
-        Schema::from_json(&schema_str)?
+        for entry in entries {
+            let file_path = entry?.path();
+            let is_schema_file = file_path.extension()
+                .map(|ext| ext == "xml" || ext == "json")
+                .unwrap_or(false);
+
+            if file_path.is_file() && is_schema_file {
+                file_paths.push(file_path);
+            }
+        }
+
+        if file_paths.is_empty() {
+            bail!("No schema files found in directory {}", schema_path);
+        }
+
+        file_paths.sort();
+
+        for file_path in file_paths {
+            merger.add_file(&file_path)?;
+        }
     } else {
-        return Err(format_err!("Schema format not supported"))
-    };
+        merger.add_file(path)?;
+    }
+
+    let (schema_config, file_paths) = merger.finish()?;
+    let schema: Schema = schema_config.into();
 
     // TODO Should this check be done in core?
     for cube in &schema.cubes {
@@ -30,5 +81,258 @@ pub fn read_schema(schema_path: &str) -> Result<Schema, Error> {
         }
     }
 
-    Ok(schema)
+    Ok((schema, file_paths))
+}
+
+/// Checks the tables and columns of a schema against the database catalog.
+///
+/// Returns an error listing all tables and columns which were not found.
+pub fn validate_schema_catalog(
+    schema: &Schema,
+    backend: &dyn Backend,
+    sys: &mut SystemRunner,
+) -> Result<(), Error>
+{
+    let mut catalog = HashMap::new();
+
+    for table in schema.catalog_tables() {
+        let columns_sql = backend.table_columns_sql(&table)
+            .ok_or_else(|| format_err!("Schema validation against the database is not supported by this backend"))?;
+
+        let df = sys.block_on(backend.exec_sql(columns_sql))
+            .map_err(|err| format_err!("Error reading columns of table {}: {}", table.full_name(), err))?;
+
+        let columns = df.columns.get(0)
+            .map(|col| col.stringify_column_data())
+            .unwrap_or_else(|| vec![]);
+
+        // a table with no columns in the catalog doesn't exist
+        if !columns.is_empty() {
+            catalog.insert(table.full_name(), columns);
+        }
+    }
+
+    schema.validate_catalog(&catalog)
+}
+
+/// Reads a single schema file, without resolving includes.
+fn read_schema_config_file(file_path: &Path) -> Result<SchemaConfigJson, Error> {
+    let schema_str = std::fs::read_to_string(file_path)
+        .map_err(|_| format_err!("Schema file not found at {}", file_path.display()))?;
+
+    let extension = file_path.extension().and_then(|ext| ext.to_str());
+
+    // Environment variables are resolved before parsing
+    let lookup = |name: &str| std::env::var(name).ok();
+
+    let schema_config = match extension {
+        Some("xml") => schema_vars::resolve_xml(&schema_str, lookup)
+            .and_then(|schema_str| SchemaConfigJson::from_xml(&schema_str)),
+        Some("json") => schema_vars::resolve_json(&schema_str, lookup)
+            .and_then(|schema_str| SchemaConfigJson::from_json(&schema_str)),
+        _ => return Err(format_err!("Schema format not supported: {}", file_path.display())),
+    };
+
+    schema_config.map_err(|err| format_err!("Error reading schema file {}: {}", file_path.display(), err))
+}
+
+/// Merges schema files, keeping track of which file defined each cube and
+/// shared dimension, so that collisions can point at the files involved.
+struct SchemaConfigMerger {
+    schema_config: Option<SchemaConfigJson>,
+    cube_files: HashMap<String, PathBuf>,
+    shared_dimension_files: HashMap<String, PathBuf>,
+    visited_files: HashSet<PathBuf>,
+    include_stack: Vec<PathBuf>,
+    file_paths: Vec<PathBuf>,
+}
+
+impl SchemaConfigMerger {
+    fn new() -> Self {
+        SchemaConfigMerger {
+            schema_config: None,
+            cube_files: HashMap::new(),
+            shared_dimension_files: HashMap::new(),
+            visited_files: HashSet::new(),
+            include_stack: vec![],
+            file_paths: vec![],
+        }
+    }
+
+    /// Adds a file and, recursively, the files it includes. A file included
+    /// from several files is only merged once; a file including itself
+    /// (directly or through other files) is an error.
+    fn add_file(&mut self, file_path: &Path) -> Result<(), Error> {
+        let canonical_path = file_path.canonicalize()
+            .map_err(|_| format_err!("Schema file not found at {}", file_path.display()))?;
+
+        if self.include_stack.contains(&canonical_path) {
+            let cycle = self.include_stack.iter()
+                .skip_while(|path| **path != canonical_path)
+                .chain(Some(&canonical_path))
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>();
+            bail!("Schema file {} includes itself: {}", file_path.display(), cycle.join(" -> "));
+        }
+
+        if !self.visited_files.insert(canonical_path.clone()) {
+            return Ok(());
+        }
+
+        let mut schema_config = read_schema_config_file(file_path)?;
+        self.file_paths.push(file_path.to_owned());
+        let includes = schema_config.includes.take().unwrap_or_else(|| vec![]);
+
+        for cube in &schema_config.cubes {
+            if let Some(other_path) = self.cube_files.get(&cube.name) {
+                bail!("Cube \"{}\" in {} is already defined in {}",
+                    cube.name,
+                    file_path.display(),
+                    other_path.display(),
+                );
+            }
+            self.cube_files.insert(cube.name.clone(), file_path.to_owned());
+        }
+
+        for shared_dim in schema_config.shared_dimensions.iter().flat_map(|dims| dims.iter()) {
+            if let Some(other_path) = self.shared_dimension_files.get(&shared_dim.name) {
+                bail!("Shared dimension \"{}\" in {} is already defined in {}",
+                    shared_dim.name,
+                    file_path.display(),
+                    other_path.display(),
+                );
+            }
+            self.shared_dimension_files.insert(shared_dim.name.clone(), file_path.to_owned());
+        }
+
+        match self.schema_config {
+            Some(ref mut root) => root.merge(schema_config),
+            None => self.schema_config = Some(schema_config),
+        }
+
+        let base_dir = file_path.parent().unwrap_or_else(|| Path::new(""));
+
+        self.include_stack.push(canonical_path);
+        for include in includes {
+            self.add_file(&base_dir.join(&include.path))?;
+        }
+        self.include_stack.pop();
+
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(SchemaConfigJson, Vec<PathBuf>), Error> {
+        let schema_config = self.schema_config
+            .ok_or_else(|| format_err!("No schema files found"))?;
+
+        Ok((schema_config, self.file_paths))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    fn write_files(dir_name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(dir_name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        for (name, contents) in files {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+
+        dir
+    }
+
+    const SHARED_DIMS: &str = r##"
+        <Schema name="shared">
+            <SharedDimension name="Geo">
+                <Hierarchy name="Geo">
+                    <Table name="dim_geo" />
+                    <Level name="Country" key_column="country_id" />
+                </Hierarchy>
+            </SharedDimension>
+        </Schema>
+    "##;
+
+    const SALES_CUBE: &str = r##"
+        <Schema name="sales">
+            <Cube name="sales">
+                <Table name="sales" />
+                <DimensionUsage source="Geo" foreign_key="country_id" />
+                <Measure name="Quantity" column="quantity" aggregator="sum" />
+            </Cube>
+        </Schema>
+    "##;
+
+    #[test]
+    fn test_read_schema_includes() {
+        let root = r##"
+            <Schema name="root">
+                <Include path="shared.xml" />
+                <Include path="cubes/sales.xml" />
+            </Schema>
+        "##;
+        let dir = write_files("tesseract_schema_includes", &[("root.xml", root), ("shared.xml", SHARED_DIMS)]);
+        fs::create_dir_all(dir.join("cubes")).unwrap();
+        fs::write(dir.join("cubes/sales.xml"), SALES_CUBE).unwrap();
+
+        let schema = read_schema(dir.join("root.xml").to_str().unwrap()).unwrap();
+        assert_eq!(schema.name, "root");
+        assert_eq!(schema.cubes.len(), 1);
+        assert_eq!(schema.cubes[0].dimensions[0].name, "Geo");
+    }
+
+    #[test]
+    fn test_read_schema_diamond_includes() {
+        // both cube files include the shared dimensions, which are merged once
+        let root = r##"
+            <Schema name="root">
+                <Include path="sales.xml" />
+                <Include path="returns.xml" />
+            </Schema>
+        "##;
+        let include_shared = |cube: &str| SALES_CUBE
+            .replace(r#"<Schema name="sales">"#, r#"<Schema name="sales"><Include path="shared.xml" />"#)
+            .replace(r#"<Cube name="sales">"#, &format!(r#"<Cube name="{}">"#, cube));
+        let sales = include_shared("sales");
+        let returns = include_shared("returns");
+        let dir = write_files("tesseract_schema_diamond", &[
+            ("root.xml", root),
+            ("shared.xml", SHARED_DIMS),
+            ("sales.xml", &sales),
+            ("returns.xml", &returns),
+        ]);
+
+        let (schema, file_paths) = read_schema_files(dir.join("root.xml").to_str().unwrap()).unwrap();
+        assert_eq!(schema.cubes.len(), 2);
+        assert_eq!(file_paths.len(), 4);
+    }
+
+    #[test]
+    fn test_read_schema_include_cycle() {
+        let a = r##"<Schema name="a"><Include path="b.xml" /></Schema>"##;
+        let b = r##"<Schema name="b"><Include path="a.xml" /></Schema>"##;
+        let dir = write_files("tesseract_schema_cycle", &[("a.xml", a), ("b.xml", b)]);
+
+        let err = read_schema(dir.join("a.xml").to_str().unwrap()).unwrap_err().to_string();
+        assert!(err.contains("includes itself"));
+        assert!(err.contains("a.xml -> "));
+    }
+
+    #[test]
+    fn test_read_schema_dir_collision() {
+        let dir = write_files("tesseract_schema_dir", &[
+            ("a_shared.xml", SHARED_DIMS),
+            ("b_sales.xml", SALES_CUBE),
+            ("c_sales.xml", SALES_CUBE),
+        ]);
+
+        let err = read_schema(dir.to_str().unwrap()).unwrap_err().to_string();
+        assert!(err.contains("c_sales.xml"));
+        assert!(err.contains("b_sales.xml"));
+    }
 }
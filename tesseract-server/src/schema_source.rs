@@ -0,0 +1,230 @@
+//! Reading schemas from their source: a local file or directory, an HTTP
+//! endpoint, or a table in the database.
+//!
+//! Schemas are fetched as futures, so that they can be reloaded from a
+//! handler (`/flush`) as well as at startup and by polling.
+
+use actix::SystemRunner;
+use actix_web::{client, HttpMessage};
+use failure::{Error, format_err, bail};
+use futures::future::{self, Future};
+use log::*;
+use std::str;
+use std::thread;
+use std::time::Duration;
+
+use tesseract_core::{Backend, Schema};
+use tesseract_core::query_ir::{quote_identifier, text_literal};
+
+use crate::app::SchemaSource;
+use crate::schema_config;
+use crate::tenant::{self, Tenant};
+
+
+/// Largest schema accepted from an HTTP endpoint
+const MAX_REMOTE_SCHEMA_SIZE: usize = 16 * 1024 * 1024;
+
+/// Table for schemas stored in the database, with `name` and `schema` columns.
+const SCHEMAS_TABLE: &str = "tesseract_schemas";
+
+/// Fetches a schema from its source, validates it and resolves date levels
+/// for the backend. The schema is only returned if it's valid, so it can be
+/// swapped in directly.
+pub fn fetch_schema(
+    source: &SchemaSource,
+    backend: Box<dyn Backend + Send + Sync>,
+) -> Box<dyn Future<Item=Schema, Error=Error>>
+{
+    let schema_fut: Box<dyn Future<Item=Schema, Error=Error>> = match source {
+        SchemaSource::LocalSchema { filepath } => {
+            Box::new(future::result(schema_config::read_schema(filepath)))
+        },
+        SchemaSource::RemoteSchema { endpoint } => {
+            Box::new(fetch_remote_schema(endpoint))
+        },
+        SchemaSource::DatabaseSchema { name } => {
+            Box::new(fetch_database_schema(name, &*backend))
+        },
+    };
+
+    Box::new(schema_fut.and_then(move |mut schema| {
+        schema.validate()?;
+        schema.resolve_date_levels(&*backend);
+        Ok(schema)
+    }))
+}
+
+fn fetch_remote_schema(endpoint: &str) -> impl Future<Item=Schema, Error=Error> {
+    let endpoint = endpoint.to_owned();
+
+    future::result(client::get(&endpoint).finish())
+        .map_err(|err| format_err!("{}", err))
+        .and_then(|req| req.send().map_err(|err| format_err!("{}", err)))
+        .and_then(move |res| {
+            if !res.status().is_success() {
+                bail!("Schema endpoint {} returned {}", endpoint, res.status());
+            }
+
+            let is_json = res.content_type().contains("json");
+            Ok((res, is_json))
+        })
+        .and_then(|(res, is_json)| {
+            res.body()
+                .limit(MAX_REMOTE_SCHEMA_SIZE)
+                .map_err(|err| format_err!("{}", err))
+                .and_then(move |body| {
+                    let raw_schema = str::from_utf8(&body)?;
+
+                    if is_json {
+                        Schema::from_json(raw_schema)
+                    } else {
+                        parse_schema(raw_schema)
+                    }
+                })
+        })
+        .map_err(|err| format_err!("Error fetching remote schema: {}", err))
+}
+
+fn fetch_database_schema(name: &str, backend: &dyn Backend) -> impl Future<Item=Schema, Error=Error> {
+    let sql = format!("select {} from {} where name = {}",
+        quote_identifier("schema", backend.identifier_style()),
+        SCHEMAS_TABLE,
+        text_literal(name, backend.literal_style()),
+    );
+    let name = name.to_owned();
+
+    backend.exec_sql(sql)
+        .and_then(move |df| {
+            let raw_schema = df.columns.get(0)
+                .and_then(|col| col.stringify_column_data().into_iter().next())
+                .ok_or_else(|| format_err!("Schema {} not found in table {}", name, SCHEMAS_TABLE))?;
+
+            parse_schema(&raw_schema)
+        })
+        .map_err(|err| format_err!("Error fetching schema from database: {}", err))
+}
+
+/// Parses a schema which may be XML or JSON.
+pub fn parse_schema(raw_schema: &str) -> Result<Schema, Error> {
+    if raw_schema.trim_start().starts_with('<') {
+        Schema::from_xml(raw_schema)
+    } else {
+        Schema::from_json(raw_schema)
+    }
+}
+
+/// Polls a remote or database schema source in a background thread, and
+/// reloads the tenant when the schema has changed since the last poll (so
+/// schemas activated through the admin api are kept until the source
+/// changes). Invalid schemas are logged, and the current schema is kept.
+pub fn poll_schema(tenant: Tenant, current_schema: Schema, interval: Duration) {
+    thread::spawn(move || {
+        let mut sys: SystemRunner = actix::System::new("tesseract-schema-poll");
+
+        let mut last_schema = current_schema;
+
+        loop {
+            thread::sleep(interval);
+
+            match sys.block_on(fetch_schema(&tenant.env_vars.schema_source, tenant.backend.clone())) {
+                Ok(ref new_schema) if *new_schema == last_schema => (),
+                Ok(new_schema) => {
+                    match reload_polled_schema(&tenant, new_schema.clone(), &mut sys) {
+                        Ok(()) => {
+                            info!("Schema for {} changed, reloaded", tenant.name);
+                            last_schema = new_schema;
+                        },
+                        Err(err) => error!("Schema reload for {} failed, keeping current schema: {}", tenant.name, err),
+                    }
+                },
+                Err(err) => error!("Schema reload for {} failed, keeping current schema: {}", tenant.name, err),
+            }
+        }
+    });
+}
+
+fn reload_polled_schema(tenant: &Tenant, schema: Schema, sys: &mut SystemRunner) -> Result<(), Error> {
+    let logic_layer_config = tenant.logic_layer_config.as_ref()
+        .map(|config| config.read().unwrap().clone());
+
+    let cache = tenant::prepare_reload(
+        &schema,
+        &logic_layer_config,
+        tenant.backend.clone(),
+        &tenant.has_unique_levels_properties,
+        &tenant.env_vars.schema_validation,
+        sys,
+    )?;
+
+    tenant::activate(
+        &tenant.schema_versions,
+        &tenant.schema,
+        &tenant.cache,
+        None,
+        &tenant.env_vars.schema_source,
+        tenant::Reload { source: None, schema, cache, logic_layer_config: None },
+    );
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    const SCHEMA: &str = r##"
+        <Schema name="remote">
+            <Cube name="sales">
+                <Table name="sales" />
+                <Dimension name="Geo" foreign_key="geo_id">
+                    <Hierarchy name="Geo">
+                        <Level name="Country" key_column="geo_id" />
+                    </Hierarchy>
+                </Dimension>
+                <Measure name="Quantity" column="quantity" aggregator="sum" />
+            </Cube>
+        </Schema>
+    "##;
+
+    /// Serves one response, and returns the endpoint
+    fn serve_once(status: &'static str, body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}/schema.xml", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf);
+
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: application/xml\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status, body.len(), body,
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+
+        endpoint
+    }
+
+    #[test]
+    fn test_fetch_remote_schema() {
+        let mut sys = actix::System::new("test");
+
+        let endpoint = serve_once("200 OK", SCHEMA);
+        let schema = sys.block_on(fetch_remote_schema(&endpoint)).unwrap();
+        assert_eq!(schema.name, "remote");
+        assert_eq!(schema.cubes[0].name, "sales");
+
+        let endpoint = serve_once("404 Not Found", "");
+        assert!(sys.block_on(fetch_remote_schema(&endpoint)).is_err());
+    }
+
+    #[test]
+    fn test_parse_schema() {
+        assert_eq!(parse_schema(SCHEMA).unwrap().name, "remote");
+        assert!(parse_schema(r#"{"name": "json", "cubes": []}"#).is_ok());
+    }
+}
@@ -0,0 +1,316 @@
+//! Environment variables in schema files, as `${VAR}` or `${VAR:-default}`.
+//!
+//! Variables are resolved in the text of each schema file before it's
+//! parsed, but only in values which never reach the metadata api: table
+//! names, schemas and primary keys, columns and foreign keys. The one
+//! exception is annotations, which are served as written, so shouldn't hold
+//! secrets. A variable anywhere else is an error, as is an unset variable
+//! without a default. Resolved values are escaped for the file format, so
+//! they can't change the structure of the schema.
+
+use failure::{Error, format_err, bail};
+use serde_json::Value;
+
+/// Attributes (XML) or keys (JSON) which can use variables in any element.
+const COLUMN_ATTRIBUTES: &[&str] = &[
+    "column",
+    "key_column",
+    "name_column",
+    "foreign_key",
+    "primary_key",
+];
+
+/// Attributes (XML) or keys (JSON) of tables which can use variables.
+const TABLE_ATTRIBUTES: &[&str] = &["name", "schema", "primary_key"];
+
+/// Resolves variables in an XML schema.
+pub fn resolve_xml<L>(xml: &str, lookup: L) -> Result<String, Error>
+    where L: Fn(&str) -> Option<String>
+{
+    if !xml.contains("${") {
+        return Ok(xml.to_owned());
+    }
+
+    let mut out = String::with_capacity(xml.len());
+    let mut rest = xml;
+    // element whose text is being read, to allow variables in annotations
+    let mut text_element: Option<&str> = None;
+
+    while !rest.is_empty() {
+        // Text, up to the next tag
+        let text_end = rest.find('<').unwrap_or_else(|| rest.len());
+        let text = &rest[..text_end];
+
+        if text.contains("${") {
+            match text_element {
+                Some("Annotation") => out.push_str(&resolve_value(text, &lookup, escape_xml)?),
+                _ => bail!("Schema variables are not allowed in the text of {}", text_element.unwrap_or("the schema")),
+            }
+        } else {
+            out.push_str(text);
+        }
+        rest = &rest[text_end..];
+
+        if rest.is_empty() {
+            break;
+        }
+
+        // Comments, CDATA, declarations and closing tags are copied as they are
+        let skip_to = if rest.starts_with("<!--") {
+            Some("-->")
+        } else if rest.starts_with("<![CDATA[") {
+            Some("]]>")
+        } else if rest.starts_with("<?") {
+            Some("?>")
+        } else if rest.starts_with("</") || rest.starts_with("<!") {
+            Some(">")
+        } else {
+            None
+        };
+
+        if let Some(end) = skip_to {
+            let end_idx = rest.find(end)
+                .map(|idx| idx + end.len())
+                .ok_or_else(|| format_err!("Unterminated tag in schema"))?;
+            out.push_str(&rest[..end_idx]);
+            rest = &rest[end_idx..];
+            text_element = None;
+            continue;
+        }
+
+        // Start tag: copy the name, then resolve attribute values
+        let name_end = rest[1..].find(|c: char| c.is_whitespace() || c == '/' || c == '>')
+            .map(|idx| idx + 1)
+            .ok_or_else(|| format_err!("Unterminated tag in schema"))?;
+        let tag = &rest[1..name_end];
+        out.push_str(&rest[..name_end]);
+        rest = &rest[name_end..];
+
+        loop {
+            let trimmed = rest.trim_start();
+            out.push_str(&rest[..rest.len() - trimmed.len()]);
+            rest = trimmed;
+
+            if rest.starts_with("/>") {
+                out.push_str("/>");
+                rest = &rest[2..];
+                text_element = None;
+                break;
+            }
+            if rest.starts_with('>') {
+                out.push('>');
+                rest = &rest[1..];
+                text_element = Some(tag);
+                break;
+            }
+
+            // attribute="value"
+            let eq_idx = rest.find('=')
+                .ok_or_else(|| format_err!("Invalid attribute in <{}>", tag))?;
+            let attribute = rest[..eq_idx].trim();
+            out.push_str(&rest[..=eq_idx]);
+            rest = &rest[eq_idx + 1..];
+
+            let trimmed = rest.trim_start();
+            out.push_str(&rest[..rest.len() - trimmed.len()]);
+            rest = trimmed;
+
+            let quote = rest.chars().next()
+                .filter(|c| *c == '"' || *c == '\'')
+                .ok_or_else(|| format_err!("Unquoted attribute {} in <{}>", attribute, tag))?;
+            let value_end = rest[1..].find(quote)
+                .map(|idx| idx + 1)
+                .ok_or_else(|| format_err!("Unterminated attribute {} in <{}>", attribute, tag))?;
+            let value = &rest[1..value_end];
+
+            out.push(quote);
+            if value.contains("${") {
+                if !allows_variables(tag == "Table", attribute) {
+                    bail!("Schema variables are not allowed in attribute {} of <{}>", attribute, tag);
+                }
+                out.push_str(&resolve_value(value, &lookup, escape_xml)?);
+            } else {
+                out.push_str(value);
+            }
+            out.push(quote);
+            rest = &rest[value_end + 1..];
+        }
+    }
+
+    Ok(out)
+}
+
+/// Resolves variables in a JSON schema.
+pub fn resolve_json<L>(json: &str, lookup: L) -> Result<String, Error>
+    where L: Fn(&str) -> Option<String>
+{
+    if !json.contains("${") {
+        return Ok(json.to_owned());
+    }
+
+    let mut value: Value = serde_json::from_str(json)?;
+    resolve_json_value(&mut value, None, None, &lookup)?;
+
+    Ok(value.to_string())
+}
+
+/// `parent` is the key of the object or array containing `value`, and `key`
+/// the key of `value` in its object.
+fn resolve_json_value<L>(value: &mut Value, parent: Option<&str>, key: Option<&str>, lookup: &L) -> Result<(), Error>
+    where L: Fn(&str) -> Option<String>
+{
+    match value {
+        Value::String(s) => {
+            if s.contains("${") {
+                let key = key.unwrap_or("");
+                let allowed = allows_variables(parent == Some("table"), key)
+                    || (parent == Some("annotations") && key == "text");
+
+                if !allowed {
+                    bail!("Schema variables are not allowed in {}", key);
+                }
+                *s = resolve_value(s, lookup, |v| v.to_owned())?;
+            }
+        },
+        Value::Array(values) => {
+            // objects in an array have the key of the array as parent
+            for value in values.iter_mut() {
+                resolve_json_value(value, key, key, lookup)?;
+            }
+        },
+        Value::Object(map) => {
+            for (child_key, child) in map.iter_mut() {
+                resolve_json_value(child, key, Some(child_key), lookup)?;
+            }
+        },
+        _ => (),
+    }
+
+    Ok(())
+}
+
+fn allows_variables(is_table: bool, attribute: &str) -> bool {
+    COLUMN_ATTRIBUTES.contains(&attribute)
+        || (is_table && TABLE_ATTRIBUTES.contains(&attribute))
+}
+
+/// Replaces `${VAR}` and `${VAR:-default}` in a value. Only the resolved
+/// values are escaped; the rest of the value is already in the file format.
+fn resolve_value<L, E>(value: &str, lookup: &L, escape: E) -> Result<String, Error>
+    where L: Fn(&str) -> Option<String>,
+          E: Fn(&str) -> String,
+{
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+
+        let end = rest[start..].find('}')
+            .map(|idx| start + idx)
+            .ok_or_else(|| format_err!("Unterminated schema variable in {}", value))?;
+        let expr = &rest[start + 2..end];
+
+        let (name, default) = match expr.find(":-") {
+            Some(idx) => (&expr[..idx], Some(&expr[idx + 2..])),
+            None => (expr, None),
+        };
+
+        let is_valid_name = !name.is_empty()
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !is_valid_name {
+            bail!("Invalid schema variable name: {}", name);
+        }
+
+        // errors name the variable, but never include its value
+        let resolved = lookup(name)
+            .or_else(|| default.map(|d| d.to_owned()))
+            .ok_or_else(|| format_err!("Schema variable {} is not set, and has no default", name))?;
+
+        out.push_str(&escape(&resolved));
+        rest = &rest[end + 1..];
+    }
+
+    out.push_str(rest);
+
+    Ok(out)
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "DB" => Some("staging".to_owned()),
+            "PREFIX" => Some("stg_".to_owned()),
+            "QUOTED" => Some("a\"<b>".to_owned()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_resolve_xml() {
+        let xml = r##"<?xml version="1.0"?>
+            <!-- ${NOT_RESOLVED} in comments -->
+            <Schema name="trade">
+                <Cube name="sales">
+                    <Table name="${PREFIX}sales" schema="${DB}" />
+                    <Dimension name="Geo" foreign_key="${GEO_KEY:-geo_id}">
+                        <Hierarchy name="Geo">
+                            <Level name="Country" key_column="country_id" />
+                        </Hierarchy>
+                    </Dimension>
+                    <Measure name="Quantity" column="quantity" aggregator="sum" />
+                    <Annotation name="source">${QUOTED}</Annotation>
+                </Cube>
+            </Schema>
+        "##;
+
+        let resolved = resolve_xml(xml, lookup).unwrap();
+        assert!(resolved.contains(r#"<Table name="stg_sales" schema="staging" />"#));
+        assert!(resolved.contains(r#"foreign_key="geo_id""#));
+        assert!(resolved.contains("<Annotation name=\"source\">a&quot;&lt;b&gt;</Annotation>"));
+        assert!(resolved.contains("${NOT_RESOLVED}"));
+
+        let err = resolve_xml(r#"<Table name="${MISSING}" />"#, lookup).unwrap_err();
+        assert!(err.to_string().contains("MISSING"));
+
+        // not a table, column or annotation
+        assert!(resolve_xml(r#"<Cube name="${DB}"></Cube>"#, lookup).is_err());
+    }
+
+    #[test]
+    fn test_resolve_json() {
+        let json = r#"{
+            "name": "trade",
+            "cubes": [{
+                "name": "sales",
+                "table": { "name": "${PREFIX}sales", "schema": "${DB}" },
+                "measures": [{ "name": "Quantity", "column": "${COLUMN:-quantity}", "aggregator": "sum" }],
+                "annotations": [{ "name": "source", "text": "${QUOTED}" }]
+            }]
+        }"#;
+
+        let resolved: Value = serde_json::from_str(&resolve_json(json, lookup).unwrap()).unwrap();
+        let cube = &resolved["cubes"][0];
+        assert_eq!(cube["table"]["name"], "stg_sales");
+        assert_eq!(cube["table"]["schema"], "staging");
+        assert_eq!(cube["measures"][0]["column"], "quantity");
+        assert_eq!(cube["annotations"][0]["text"], "a\"<b>");
+
+        assert!(resolve_json(r#"{ "name": "${DB}" }"#, lookup).is_err());
+        assert!(resolve_json(r#"{ "table": { "name": "${MISSING}" } }"#, lookup).is_err());
+    }
+}
@@ -0,0 +1,649 @@
+//! Serving several schemas (tenants) from one server.
+//!
+//! Each tenant has its own schema, database, logic layer config and cache,
+//! and is served by its own actix-web `App` (so handlers are unchanged, and
+//! only see the `AppState` of their tenant). Tenants are routed by Host
+//! header if they have a `host`, otherwise by a path prefix with their name
+//! (e.g. `/trade/cubes/...`). `/flush` only reloads the tenant it's called on.
+//!
+//! Without a tenants config, the single schema from the env vars is served
+//! at the root, as before.
+
+use actix::SystemRunner;
+use actix_web::App;
+use failure::{Error, format_err, bail};
+use log::*;
+use serde_derive::Deserialize;
+use std::collections::HashSet;
+use futures::future::Future;
+use futures::sync::oneshot;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use tesseract_core::{Backend, Schema, CubeHasUniqueLevelsAndProperties};
+
+use crate::app::{AppState, EnvVars, SchemaSource, create_app};
+use crate::db_config::{self, Database};
+use crate::logic_layer::{self, Cache, LogicLayerConfig};
+use crate::schema_config;
+use crate::schema_source;
+use crate::watcher;
+
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TenantsConfig {
+    pub tenants: Vec<TenantConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TenantConfig {
+    pub name: String,
+    /// Schema source: exactly one of a local path, an HTTP endpoint, or a
+    /// name in the database's `tesseract_schemas` table
+    pub schema_path: Option<String>,
+    pub schema_url: Option<String>,
+    pub schema_db_name: Option<String>,
+    /// Defaults to the server's database url
+    pub database_url: Option<String>,
+    pub logic_layer_config_path: Option<String>,
+    /// Routes by Host header instead of path prefix
+    pub host: Option<String>,
+}
+
+impl TenantConfig {
+    pub fn schema_source(&self) -> Result<SchemaSource, Error> {
+        match (&self.schema_path, &self.schema_url, &self.schema_db_name) {
+            (Some(filepath), None, None) => Ok(SchemaSource::LocalSchema { filepath: filepath.clone() }),
+            (None, Some(endpoint), None) => Ok(SchemaSource::RemoteSchema { endpoint: endpoint.clone() }),
+            (None, None, Some(name)) => Ok(SchemaSource::DatabaseSchema { name: name.clone() }),
+            _ => bail!("Exactly one of schema path, url or database name required for {}", self.name),
+        }
+    }
+}
+
+/// How requests are routed to a tenant.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TenantRoute {
+    /// Single schema, served at the root
+    Root,
+    Prefix(String),
+    Host(String),
+}
+
+/// Whether the Host of a request is a tenant's host. Hosts are case
+/// insensitive, and the port of the request is ignored unless the tenant's
+/// host has one.
+pub fn host_matches(tenant_host: &str, request_host: &str) -> bool {
+    let request_host = if strip_port(tenant_host) == tenant_host {
+        strip_port(request_host)
+    } else {
+        request_host
+    };
+
+    request_host.eq_ignore_ascii_case(tenant_host)
+}
+
+fn strip_port(host: &str) -> &str {
+    match host.rfind(':') {
+        // IPv6 addresses are in brackets, e.g. `[::1]:8080`
+        Some(idx) if !host.ends_with(']') => {
+            let port = &host[idx + 1..];
+            if !port.is_empty() && port.chars().all(|c| c.is_ascii_digit()) {
+                &host[..idx]
+            } else {
+                host
+            }
+        },
+        _ => host,
+    }
+}
+
+/// Settings shared by all tenants.
+#[derive(Debug, Clone)]
+pub struct TenantDefaults {
+    pub database_url: Option<String>,
+    pub env_vars: EnvVars,
+    /// Polling interval for remote and database schemas
+    pub schema_poll_interval: Option<Duration>,
+    /// Reload local schemas and logic layer configs when their files change
+    pub watch_files: bool,
+    /// Number of previous schema versions kept for rollback
+    pub schema_versions: usize,
+}
+
+/// A loaded tenant: the state of its `App`, and its route.
+#[derive(Clone)]
+pub struct Tenant {
+    pub name: String,
+    pub route: TenantRoute,
+    pub backend: Box<dyn Backend + Sync + Send>,
+    pub db_type: Database,
+    pub env_vars: EnvVars,
+    pub schema: Arc<RwLock<Schema>>,
+    pub cache: Arc<RwLock<Cache>>,
+    pub logic_layer_config: Option<Arc<RwLock<LogicLayerConfig>>>,
+    pub has_unique_levels_properties: CubeHasUniqueLevelsAndProperties,
+    pub schema_versions: Arc<Mutex<SchemaVersions>>,
+}
+
+impl Tenant {
+    /// Reads and validates the schema and logic layer config, and populates
+    /// the cache.
+    pub fn load(
+        config: &TenantConfig,
+        route: TenantRoute,
+        defaults: &TenantDefaults,
+        sys: &mut SystemRunner,
+    ) -> Result<Self, Error>
+    {
+        let db_url_full = config.database_url.as_ref()
+            .or(defaults.database_url.as_ref())
+            .ok_or_else(|| format_err!("database url not found for {}; either TESSERACT_DATABASE_URL, cli option or tenant database_url required", config.name))?;
+
+        let (backend, db_url, db_type) = db_config::get_db(db_url_full)?;
+
+        let schema_source = config.schema_source()?;
+        let schema = sys.block_on(schema_source::fetch_schema(&schema_source, backend.clone()))?;
+        let mut has_unique_levels_properties = schema.has_unique_levels_properties();
+
+        let logic_layer_config = match config.logic_layer_config_path {
+            Some(ref config_path) => {
+                let config_obj = logic_layer::read_config(config_path)?;
+                has_unique_levels_properties = config_obj.has_unique_levels_properties(&schema)?;
+                Some(config_obj)
+            },
+            None => None,
+        };
+
+        // Check schema against the database
+        validate_catalog(&schema, &*backend, &defaults.env_vars.schema_validation, sys)
+            .map_err(|err| format_err!("{}: {}", config.name, err))?;
+
+        // Populate internal cache
+        let cache = logic_layer::populate_cache(
+            schema.clone(), &logic_layer_config, backend.clone(), sys
+        ).map_err(|err| format_err!("Cache population error for {}: {}", config.name, err))?;
+
+        let polled_schema = schema.clone();
+        let schema = Arc::new(RwLock::new(schema));
+
+        let env_vars = EnvVars {
+            database_url: db_url,
+            schema_source,
+            ..defaults.env_vars.clone()
+        };
+
+        let tenant = Tenant {
+            name: config.name.clone(),
+            route,
+            backend,
+            db_type,
+            env_vars,
+            schema,
+            cache: Arc::new(RwLock::new(cache)),
+            logic_layer_config: logic_layer_config.map(|c| Arc::new(RwLock::new(c))),
+            has_unique_levels_properties,
+            schema_versions: Arc::new(Mutex::new(SchemaVersions::new(defaults.schema_versions))),
+        };
+
+        if defaults.watch_files {
+            watcher::watch_tenant(tenant.clone(), config.logic_layer_config_path.clone(), watcher::WATCH_INTERVAL);
+        }
+
+        // Local schemas are reloaded with /flush, or by the file watcher
+        if let Some(interval) = defaults.schema_poll_interval {
+            match tenant.env_vars.schema_source {
+                SchemaSource::LocalSchema { .. } => (),
+                _ => schema_source::poll_schema(tenant.clone(), polled_schema, interval),
+            }
+        }
+
+        Ok(tenant)
+    }
+
+    pub fn app_state(&self, debug: bool) -> AppState {
+        AppState {
+            debug,
+            backend: self.backend.clone(),
+            db_type: self.db_type.clone(),
+            env_vars: self.env_vars.clone(),
+            schema: self.schema.clone(),
+            cache: self.cache.clone(),
+            logic_layer_config: self.logic_layer_config.clone(),
+            has_unique_levels_properties: self.has_unique_levels_properties.clone(),
+            schema_versions: self.schema_versions.clone(),
+        }
+    }
+}
+
+/// Checks the schema against the database, as set by
+/// TESSERACT_SCHEMA_VALIDATION: "strict" returns an error, "warn" only logs.
+pub fn validate_catalog(
+    schema: &Schema,
+    backend: &dyn Backend,
+    schema_validation: &Option<String>,
+    sys: &mut SystemRunner,
+) -> Result<(), Error>
+{
+    match schema_validation.as_ref().map(|s| s.as_str()) {
+        Some("strict") => {
+            schema_config::validate_schema_catalog(schema, backend, sys)?;
+        },
+        Some("warn") => {
+            if let Err(err) = schema_config::validate_schema_catalog(schema, backend, sys) {
+                warn!("{}", err);
+            }
+        },
+        Some(other) => {
+            bail!("TESSERACT_SCHEMA_VALIDATION must be strict or warn, found: {}", other);
+        },
+        None => (),
+    }
+
+    Ok(())
+}
+
+/// Checks a new (validated) schema for a running tenant against the
+/// database, and builds its cache, so that both can be swapped in.
+pub fn prepare_reload(
+    schema: &Schema,
+    logic_layer_config: &Option<LogicLayerConfig>,
+    backend: Box<dyn Backend + Sync + Send>,
+    current_unique: &CubeHasUniqueLevelsAndProperties,
+    schema_validation: &Option<String>,
+    sys: &mut SystemRunner,
+) -> Result<Cache, Error>
+{
+    // Routes for the logic layer are set when the server starts
+    let has_unique_levels_properties = match logic_layer_config {
+        Some(ref config) => config.has_unique_levels_properties(schema)?,
+        None => schema.has_unique_levels_properties(),
+    };
+
+    match (current_unique, &has_unique_levels_properties) {
+        (CubeHasUniqueLevelsAndProperties::True, CubeHasUniqueLevelsAndProperties::False { cube, name }) => {
+            warn!("Level or property name {} in cube {} is no longer unique; restart the server to disable the logic layer", name, cube);
+        },
+        (CubeHasUniqueLevelsAndProperties::False { .. }, CubeHasUniqueLevelsAndProperties::True) => {
+            warn!("Level and property names are now unique; restart the server to enable the logic layer");
+        },
+        _ => (),
+    }
+
+    validate_catalog(schema, &*backend, schema_validation, sys)?;
+
+    logic_layer::populate_cache(
+        schema.clone(), logic_layer_config, backend, sys
+    ).map_err(|err| format_err!("Cache population error: {}", err))
+}
+
+/// A schema read again, with its cache, ready to be swapped in by `activate`.
+pub struct Reload {
+    /// Source of a schema which doesn't come from the configured source (e.g.
+    /// an upload), see `SchemaVersions::active_source`
+    pub source: Option<String>,
+    pub schema: Schema,
+    pub cache: Cache,
+    /// Only set if the config was read again
+    pub logic_layer_config: Option<LogicLayerConfig>,
+}
+
+/// Swaps in a reloaded schema, its cache and (if it was read again) its logic
+/// layer config together, so requests never see a schema with the cache or
+/// config of another version. The active schema is kept as the previous
+/// version, for rollback.
+///
+/// Locks are taken in the same order everywhere: versions, schema, cache,
+/// then config. Returns the hash of the new schema and the number of previous
+/// versions.
+pub fn activate(
+    versions: &Mutex<SchemaVersions>,
+    schema: &RwLock<Schema>,
+    cache: &RwLock<Cache>,
+    logic_layer_config: Option<&RwLock<LogicLayerConfig>>,
+    configured_source: &SchemaSource,
+    reload: Reload,
+) -> (String, usize)
+{
+    let hash = schema_hash(&reload.schema);
+
+    let mut versions = versions.lock().unwrap();
+    let mut schema_w = schema.write().unwrap();
+    let mut cache_w = cache.write().unwrap();
+    let mut config_w = logic_layer_config.map(|c| c.write().unwrap());
+
+    let current_hash = schema_hash(&schema_w);
+    let current_source = versions.active_source(&current_hash, configured_source);
+    let current_config = config_w.as_ref().map(|config_w| (**config_w).clone());
+
+    versions.push_previous(SchemaVersion {
+        source: current_source,
+        hash: current_hash,
+        schema: mem::replace(&mut *schema_w, reload.schema),
+        logic_layer_config: current_config,
+    });
+    versions.activated = reload.source.map(|source| (source, hash.clone()));
+
+    *cache_w = reload.cache;
+    if let (Some(config_w), Some(config)) = (config_w.as_mut(), reload.logic_layer_config) {
+        **config_w = config;
+    }
+
+    (hash, versions.previous.len())
+}
+
+/// Swaps in the most recent previous version, with a cache rebuilt for it
+/// (see `prepare_reload`), and its logic layer config. The active schema
+/// isn't kept.
+///
+/// Takes the locks in the same order as `activate`. Errors if `hash` is no
+/// longer the most recent previous version, e.g. if another schema was
+/// activated while the cache was rebuilt.
+pub fn restore(
+    versions: &Mutex<SchemaVersions>,
+    schema: &RwLock<Schema>,
+    cache: &RwLock<Cache>,
+    logic_layer_config: Option<&RwLock<LogicLayerConfig>>,
+    hash: &str,
+    restored_cache: Cache,
+) -> Result<usize, Error>
+{
+    let mut versions = versions.lock().unwrap();
+
+    match versions.previous.last() {
+        Some(previous) if previous.hash == hash => (),
+        _ => bail!("Schema versions changed during rollback, try again"),
+    }
+    let previous = versions.previous.pop().expect("checked above");
+
+    let mut schema_w = schema.write().unwrap();
+    let mut cache_w = cache.write().unwrap();
+    let mut config_w = logic_layer_config.map(|c| c.write().unwrap());
+
+    *schema_w = previous.schema;
+    *cache_w = restored_cache;
+    if let (Some(config_w), Some(config)) = (config_w.as_mut(), previous.logic_layer_config) {
+        **config_w = config;
+    }
+    versions.activated = Some((previous.source, previous.hash));
+
+    Ok(versions.previous.len())
+}
+
+/// Runs `f` in a new thread with its own actix system, for work which
+/// blocks on futures (like populating the cache) and so can't run inside
+/// the server's system.
+pub fn run_in_system<T, F>(name: &'static str, f: F) -> impl Future<Item=T, Error=Error>
+    where T: Send + 'static,
+          F: FnOnce(&mut SystemRunner) -> Result<T, Error> + Send + 'static,
+{
+    let (tx, rx) = oneshot::channel();
+
+    thread::spawn(move || {
+        let mut sys = actix::System::new(name);
+        // the receiver may be gone if the request was dropped
+        let _ = tx.send(f(&mut sys));
+    });
+
+    rx.map_err(|_| format_err!("Background task was canceled"))
+        .and_then(|res| res)
+}
+
+/// Default number of previous schema versions kept for rollback
+pub const DEFAULT_SCHEMA_VERSIONS: usize = 2;
+
+/// A schema version, with its logic layer config, which can be restored by
+/// rollback. Caches are large, so aren't kept; rollback rebuilds it.
+#[derive(Debug, Clone)]
+pub struct SchemaVersion {
+    pub source: String,
+    pub hash: String,
+    pub schema: Schema,
+    /// Config active with this schema, if the tenant has one
+    pub logic_layer_config: Option<LogicLayerConfig>,
+}
+
+/// Versions of a tenant's schema activated through the admin api.
+#[derive(Debug, Clone)]
+pub struct SchemaVersions {
+    /// Source and hash of the last schema activated through the admin api.
+    /// If the active schema has another hash, it was reloaded from the
+    /// configured source since.
+    pub activated: Option<(String, String)>,
+    /// Previous versions, most recent last
+    pub previous: Vec<SchemaVersion>,
+    /// Maximum number of previous versions kept
+    pub max_previous: usize,
+}
+
+impl SchemaVersions {
+    pub fn new(max_previous: usize) -> Self {
+        SchemaVersions {
+            activated: None,
+            previous: vec![],
+            max_previous,
+        }
+    }
+
+    /// Source of the active schema
+    pub fn active_source(&self, active_hash: &str, configured_source: &SchemaSource) -> String {
+        match self.activated {
+            Some((ref source, ref hash)) if hash == active_hash => source.clone(),
+            _ => configured_source.to_string(),
+        }
+    }
+
+    pub fn push_previous(&mut self, version: SchemaVersion) {
+        self.previous.push(version);
+
+        if self.previous.len() > self.max_previous {
+            let excess = self.previous.len() - self.max_previous;
+            self.previous.drain(..excess);
+        }
+    }
+}
+
+/// Version hash of a schema, from its serialized form
+pub fn schema_hash(schema: &Schema) -> String {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(schema)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+
+    format!("{:016x}", hasher.finish())
+}
+
+/// All tenants served by the server.
+#[derive(Clone)]
+pub struct TenantRegistry {
+    tenants: Vec<Tenant>,
+}
+
+impl TenantRegistry {
+    /// Loads the tenants of a tenants config.
+    pub fn load(
+        tenants_config: &TenantsConfig,
+        defaults: &TenantDefaults,
+        sys: &mut SystemRunner,
+    ) -> Result<Self, Error>
+    {
+        let mut tenants = vec![];
+
+        for config in &tenants_config.tenants {
+            let route = match config.host {
+                Some(ref host) => TenantRoute::Host(host.clone()),
+                None => TenantRoute::Prefix(format!("/{}", config.name)),
+            };
+
+            let tenant = Tenant::load(config, route, defaults, sys)
+                .map_err(|err| format_err!("Error loading tenant {}: {}", config.name, err))?;
+
+            tenants.push(tenant);
+        }
+
+        Ok(TenantRegistry { tenants })
+    }
+
+    /// Single schema served at the root.
+    pub fn single(tenant: Tenant) -> Self {
+        TenantRegistry {
+            tenants: vec![tenant],
+        }
+    }
+
+    pub fn tenants(&self) -> &[Tenant] {
+        &self.tenants
+    }
+
+    /// One app per tenant. actix-web tries apps in order, so apps routed by
+    /// host come before apps routed by prefix, and the root app is last.
+    pub fn create_apps(&self, debug: bool, streaming_response: bool) -> Vec<App<AppState>> {
+        let mut tenants: Vec<_> = self.tenants.iter().collect();
+
+        tenants.sort_by_key(|t| match t.route {
+            TenantRoute::Host(_) => 0,
+            TenantRoute::Prefix(_) => 1,
+            TenantRoute::Root => 2,
+        });
+
+        tenants.into_iter()
+            .map(|t| create_app(t.app_state(debug), &t.route, streaming_response))
+            .collect()
+    }
+}
+
+/// Reads a tenants config JSON file.
+pub fn read_tenants_config(config_path: &str) -> Result<TenantsConfig, Error> {
+    let config_str = std::fs::read_to_string(config_path)
+        .map_err(|_| format_err!("Tenants config file not found at {}", config_path))?;
+
+    read_tenants_config_str(&config_str)
+}
+
+pub fn read_tenants_config_str(config_str: &str) -> Result<TenantsConfig, Error> {
+    let config: TenantsConfig = serde_json::from_str(config_str)
+        .map_err(|err| format_err!("Unable to read tenants config: {}", err))?;
+
+    if config.tenants.is_empty() {
+        bail!("Tenants config must have at least one tenant");
+    }
+
+    let mut names = HashSet::new();
+    let mut hosts = HashSet::new();
+
+    for tenant in &config.tenants {
+        // names are used as path prefixes
+        let valid_name = !tenant.name.is_empty() && tenant.name.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if !valid_name {
+            bail!("Tenant name must be non-empty, and only contain letters, numbers, - and _: {:?}", tenant.name);
+        }
+        tenant.schema_source()?;
+
+        if !names.insert(&tenant.name) {
+            bail!("Tenant names must be unique, found repeated name: {}", tenant.name);
+        }
+        if let Some(ref host) = tenant.host {
+            if !hosts.insert(host) {
+                bail!("Tenant hosts must be unique, found repeated host: {}", host);
+            }
+        }
+    }
+
+    Ok(config)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_host_matches() {
+        assert!(host_matches("example.com", "example.com"));
+        assert!(host_matches("example.com", "example.com:8443"));
+        assert!(host_matches("example.com", "Example.COM"));
+        assert!(!host_matches("example.com", "api.example.com"));
+        assert!(!host_matches("example.com", "example.com.evil.org"));
+
+        // a port in the tenant host has to match
+        assert!(host_matches("example.com:8443", "example.com:8443"));
+        assert!(!host_matches("example.com:8443", "example.com:8080"));
+        assert!(!host_matches("example.com:8443", "example.com"));
+
+        assert!(host_matches("[::1]", "[::1]:8080"));
+        assert!(host_matches("[::1]", "[::1]"));
+    }
+
+    #[test]
+    fn test_read_tenants_config() {
+        let config = read_tenants_config_str(r#"{
+            "tenants": [
+                { "name": "trade", "schema_path": "trade.xml" },
+                { "name": "health", "schema_url": "https://example.com/health.xml", "host": "health.example.com", "database_url": "postgres://localhost/health" }
+            ]
+        }"#).unwrap();
+        assert_eq!(config.tenants.len(), 2);
+        assert_eq!(config.tenants[1].host, Some("health.example.com".to_owned()));
+        assert!(config.tenants[0].schema_source().is_ok());
+        assert!(config.tenants[1].schema_source().is_ok());
+
+        let repeated = read_tenants_config_str(r#"{
+            "tenants": [
+                { "name": "trade", "schema_path": "a.xml" },
+                { "name": "trade", "schema_path": "b.xml" }
+            ]
+        }"#);
+        assert!(repeated.is_err());
+
+        let invalid_name = read_tenants_config_str(r#"{
+            "tenants": [{ "name": "trade/2019", "schema_path": "a.xml" }]
+        }"#);
+        assert!(invalid_name.is_err());
+    }
+
+    #[test]
+    fn test_schema_versions() {
+        let schema = Schema::from_json(r#"{"name": "a", "cubes": []}"#).unwrap();
+        let other = Schema::from_json(r#"{"name": "b", "cubes": []}"#).unwrap();
+        let hash = schema_hash(&schema);
+        assert_eq!(hash, schema_hash(&schema.clone()));
+        assert_ne!(hash, schema_hash(&other));
+
+        let configured = SchemaSource::LocalSchema { filepath: "schema.xml".to_owned() };
+        let mut versions = SchemaVersions::new(2);
+        assert_eq!(versions.active_source(&hash, &configured), "schema.xml");
+
+        versions.activated = Some(("upload".to_owned(), hash.clone()));
+        assert_eq!(versions.active_source(&hash, &configured), "upload");
+        // reloaded from the configured source since
+        assert_eq!(versions.active_source(&schema_hash(&other), &configured), "schema.xml");
+
+        // only the most recent versions are kept
+        for (i, s) in [&schema, &other, &schema].iter().enumerate() {
+            versions.push_previous(SchemaVersion {
+                source: i.to_string(),
+                hash: schema_hash(s),
+                schema: (*s).clone(),
+                logic_layer_config: None,
+            });
+        }
+        let sources: Vec<_> = versions.previous.iter().map(|v| v.source.as_str()).collect();
+        assert_eq!(sources, vec!["1", "2"]);
+
+        // keeping none disables rollback
+        let mut versions = SchemaVersions::new(0);
+        versions.push_previous(SchemaVersion {
+            source: "upload".to_owned(),
+            hash,
+            schema,
+            logic_layer_config: None,
+        });
+        assert!(versions.previous.is_empty());
+    }
+}
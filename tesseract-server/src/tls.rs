@@ -0,0 +1,129 @@
+//! HTTPS serving with openssl.
+//!
+//! The certificate chain and private key are read from PEM files, and can be
+//! read again (e.g. after a renewal) without restarting the server, on
+//! `/flush` or on SIGHUP. If the new files are invalid, the current
+//! certificate keeps being served, and the error is logged.
+
+use failure::{Error, format_err};
+use log::*;
+use openssl::pkey::{PKey, Private};
+use openssl::ssl::{SniError, SslAcceptor, SslAcceptorBuilder, SslContext, SslMethod};
+use openssl::x509::X509;
+use std::fmt;
+use std::fs;
+use std::sync::{Arc, RwLock};
+
+
+/// Certificate and key served to all clients, which can be reloaded.
+pub struct TlsCerts {
+    cert_path: String,
+    key_path: String,
+    context: RwLock<SslContext>,
+}
+
+impl fmt::Debug for TlsCerts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TlsCerts")
+            .field("cert_path", &self.cert_path)
+            .field("key_path", &self.key_path)
+            .finish()
+    }
+}
+
+impl TlsCerts {
+    pub fn load(cert_path: &str, key_path: &str) -> Result<Self, Error> {
+        Ok(TlsCerts {
+            cert_path: cert_path.to_owned(),
+            key_path: key_path.to_owned(),
+            context: RwLock::new(read_context(cert_path, key_path)?.build().into_context()),
+        })
+    }
+
+    /// Reads the certificate and key files again. The current ones are kept
+    /// if the files are invalid.
+    pub fn reload(&self) -> Result<(), Error> {
+        let context = read_context(&self.cert_path, &self.key_path)?.build().into_context();
+        *self.context.write().unwrap() = context;
+
+        Ok(())
+    }
+
+    /// Acceptor for `bind_ssl`, which always uses the latest certificate:
+    /// the context is swapped in at the start of each handshake.
+    pub fn acceptor(certs: &Arc<Self>) -> Result<SslAcceptorBuilder, Error> {
+        let mut builder = read_context(&certs.cert_path, &certs.key_path)?;
+
+        let certs = certs.clone();
+        builder.set_servername_callback(move |ssl, _alert| {
+            ssl.set_ssl_context(&certs.context.read().unwrap())
+                .map_err(|_| SniError::ALERT_FATAL)
+        });
+
+        Ok(builder)
+    }
+}
+
+fn read_context(cert_path: &str, key_path: &str) -> Result<SslAcceptorBuilder, Error> {
+    let mut certs = read_certs(cert_path)?.into_iter();
+    let key = read_private_key(key_path)?;
+
+    let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())?;
+
+    let leaf = certs.next().expect("read_certs checks for certificates");
+    builder.set_certificate(&leaf)?;
+    for cert in certs {
+        builder.add_extra_chain_cert(cert)?;
+    }
+
+    builder.set_private_key(&key)?;
+    builder.check_private_key()
+        .map_err(|_| format_err!("TLS private key {} doesn't match certificate {}", key_path, cert_path))?;
+
+    Ok(builder)
+}
+
+fn read_certs(path: &str) -> Result<Vec<X509>, Error> {
+    let pem = fs::read(path)
+        .map_err(|err| format_err!("Could not read TLS certificate {}: {}", path, err))?;
+
+    let certs = X509::stack_from_pem(&pem)
+        .map_err(|_| format_err!("Invalid TLS certificate {}", path))?;
+
+    if certs.is_empty() {
+        return Err(format_err!("No certificates found in {}", path));
+    }
+
+    Ok(certs)
+}
+
+/// Reads a PKCS#8 (`PRIVATE KEY`) or PKCS#1 (`RSA PRIVATE KEY`) key.
+fn read_private_key(path: &str) -> Result<PKey<Private>, Error> {
+    let pem = fs::read(path)
+        .map_err(|err| format_err!("Could not read TLS private key {}: {}", path, err))?;
+
+    PKey::private_key_from_pem(&pem)
+        .map_err(|_| format_err!("No private key found in {}", path))
+}
+
+/// Reloads the certificate and key on SIGHUP, in a background thread.
+#[cfg(unix)]
+pub fn reload_on_hup(certs: Arc<TlsCerts>) -> Result<(), Error> {
+    let signals = signal_hook::iterator::Signals::new(&[signal_hook::SIGHUP])?;
+
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            match certs.reload() {
+                Ok(()) => info!("Reloaded TLS certificate"),
+                Err(err) => error!("TLS certificate reload failed, keeping current certificate: {}", err),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn reload_on_hup(_certs: Arc<TlsCerts>) -> Result<(), Error> {
+    Ok(())
+}
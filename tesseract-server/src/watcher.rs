@@ -0,0 +1,179 @@
+//! Reloads a local schema and logic layer config when their files change.
+//!
+//! Files are watched by checking their modification times at an interval,
+//! which works the same on all platforms and for files on mounted volumes.
+//! On change, the schema and config are read and validated (also against the
+//! database, if TESSERACT_SCHEMA_VALIDATION is set), and the cache is
+//! rebuilt, before schema, config and cache are swapped in together. If any
+//! step fails, the last good version keeps being served.
+
+use actix::SystemRunner;
+use failure::Error;
+use log::*;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::app::SchemaSource;
+use crate::logic_layer::{self, LogicLayerConfig};
+use crate::schema_config;
+use crate::tenant::{self, Tenant};
+
+
+/// How often file modification times are checked
+pub const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Watches the schema files (all files of a directory, and included files)
+/// and the logic layer config of a tenant with a local schema, in a
+/// background thread.
+pub fn watch_tenant(tenant: Tenant, logic_layer_config_path: Option<String>, interval: Duration) {
+    let schema_path = match tenant.env_vars.schema_source {
+        SchemaSource::LocalSchema { ref filepath } => filepath.clone(),
+        _ => return,
+    };
+
+    thread::spawn(move || {
+        let mut sys = actix::System::new("tesseract-watcher");
+
+        let mut files = FileTimes::new(watched_paths(&schema_path, &logic_layer_config_path));
+
+        loop {
+            thread::sleep(interval);
+
+            if !files.changed() {
+                continue;
+            }
+
+            info!("Schema files for {} changed, reloading", tenant.name);
+
+            match reload(&tenant, &schema_path, &logic_layer_config_path, &mut sys) {
+                Ok(()) => info!("Reloaded schema for {}", tenant.name),
+                Err(err) => error!("Reload for {} failed, keeping last good schema: {}", tenant.name, err),
+            }
+
+            // files may have been added to or removed from the schema
+            files = FileTimes::new(watched_paths(&schema_path, &logic_layer_config_path));
+        }
+    });
+}
+
+/// Schema files, the schema directory (which changes when files are added
+/// or removed) and the logic layer config.
+fn watched_paths(schema_path: &str, logic_layer_config_path: &Option<String>) -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::from(schema_path)];
+
+    // if the schema can't be read, at least the root is watched
+    if let Ok((_, file_paths)) = schema_config::read_schema_files(schema_path) {
+        paths.extend(file_paths);
+    }
+
+    paths.extend(logic_layer_config_path.iter().map(PathBuf::from));
+
+    paths
+}
+
+fn reload(
+    tenant: &Tenant,
+    schema_path: &str,
+    logic_layer_config_path: &Option<String>,
+    sys: &mut SystemRunner,
+) -> Result<(), Error>
+{
+    let mut schema = schema_config::read_schema(schema_path)?;
+    schema.validate()?;
+    schema.resolve_date_levels(&*tenant.backend);
+
+    let logic_layer_config: Option<LogicLayerConfig> = match logic_layer_config_path {
+        Some(config_path) => Some(logic_layer::read_config(config_path)?),
+        None => None,
+    };
+
+    let cache = tenant::prepare_reload(
+        &schema,
+        &logic_layer_config,
+        tenant.backend.clone(),
+        &tenant.has_unique_levels_properties,
+        &tenant.env_vars.schema_validation,
+        sys,
+    )?;
+
+    tenant::activate(
+        &tenant.schema_versions,
+        &tenant.schema,
+        &tenant.cache,
+        tenant.logic_layer_config.as_ref().map(|c| &**c),
+        &tenant.env_vars.schema_source,
+        tenant::Reload { source: None, schema, cache, logic_layer_config },
+    );
+
+    Ok(())
+}
+
+/// Modification times of a set of files.
+struct FileTimes {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl FileTimes {
+    fn new(paths: Vec<PathBuf>) -> Self {
+        let files = paths.into_iter()
+            .map(|path| {
+                let modified = modified(&path);
+                (path, modified)
+            })
+            .collect();
+
+        FileTimes { files }
+    }
+
+    /// Whether any file was modified, created or removed since the last check.
+    fn changed(&mut self) -> bool {
+        let mut changed = false;
+
+        for (path, last_modified) in self.files.iter_mut() {
+            let modified = modified(path);
+
+            if modified != *last_modified {
+                *last_modified = modified;
+                changed = true;
+            }
+        }
+
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_file_times() {
+        let dir = std::env::temp_dir().join("tesseract_watcher");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let schema_path = dir.join("schema.xml");
+        fs::write(&schema_path, "<Schema name=\"a\"></Schema>").unwrap();
+
+        let mut files = FileTimes::new(vec![schema_path.clone()]);
+        assert!(!files.changed());
+
+        // make sure the modification time is different
+        thread::sleep(Duration::from_millis(1100));
+        fs::write(&schema_path, "<Schema name=\"b\"></Schema>").unwrap();
+        assert!(files.changed());
+        assert!(!files.changed());
+
+        fs::remove_file(&schema_path).unwrap();
+        assert!(files.changed());
+    }
+}